@@ -1,18 +1,34 @@
-mod geodata;
+pub mod analytics;
+pub mod calendar;
+pub mod delays;
+pub mod detour;
+pub mod geodata;
+pub mod graph;
 pub mod gtfs;
+pub mod hazard;
+pub mod isochrone;
+pub mod matrix;
+pub mod network_stats;
+pub mod realtime;
+pub mod routing;
+pub mod scenario;
+pub mod stats;
+pub mod summary;
+pub mod transfers;
+pub mod validation;
 pub mod visualize;
 use std::collections::HashMap;
 
 use geo_types::{geometry, Coord};
 use geodata::{GeoShapeLine, GeoShapePoint, StopsJson};
 use geojson::ser;
+use serde::Serialize;
 
 pub fn load_gtfs_file(gtfs_path: std::path::PathBuf) -> gtfs::GtfsFile {
-    let gtfs_file = match gtfs::GtfsFile::new(&gtfs_path) {
+    match gtfs::GtfsFile::new(&gtfs_path) {
         Ok(gtfs_file) => gtfs_file,
         Err(error) => panic!("{}", error),
-    };
-    gtfs_file
+    }
 }
 
 pub fn file_list(gtfs_file: &mut gtfs::GtfsFile) {
@@ -58,6 +74,34 @@ pub fn fare_attributes_out(gtfs_file: &mut gtfs::GtfsFile) {
     }
 }
 
+// CSV and JSON output of derived tables
+
+pub fn to_csv_string<T: Serialize>(records: &[T]) -> String {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    for record in records {
+        writer
+            .serialize(record)
+            .expect("Unable to serialize record");
+    }
+    let bytes = writer.into_inner().expect("Unable to flush csv writer");
+    String::from_utf8(bytes).expect("csv output is not valid utf-8")
+}
+
+pub fn to_json_string<T: Serialize + ?Sized>(records: &T) -> String {
+    serde_json::to_string_pretty(records).expect("Unable to serialize json")
+}
+
+// Rounded to a number of decimal places for output
+pub fn round(value: f64, decimals: i32) -> f64 {
+    let factor = 10f64.powi(decimals);
+    (value * factor).round() / factor
+}
+
+// Features with a geometry field as a GeoJSON feature collection
+pub fn to_geojson_string<T: Serialize>(features: &[T]) -> Result<String, String> {
+    ser::to_feature_collection_string(features).map_err(|error| error.to_string())
+}
+
 // JSON output to file
 
 pub fn simple_stops_json(gtfs_file: &mut gtfs::GtfsFile) -> String {
@@ -107,10 +151,10 @@ pub fn simple_shapes_json(gtfs_file: &mut gtfs::GtfsFile) -> String {
 
     let geojson = ser::to_feature_collection_string(&shape_vec);
     match geojson {
-        Ok(output) => return output,
+        Ok(output) => output,
         Err(error) => {
             println!("{:?}", error);
-            return String::new();
+            String::new()
         }
     }
 }
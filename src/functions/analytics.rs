@@ -0,0 +1,326 @@
+use std::collections::{BTreeMap, HashMap};
+#[cfg(test)]
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::objects::pattern::{self, StopPattern};
+use crate::objects::trip::{self, TripInstance};
+
+use super::gtfs::{self, Direction, Route, Stop, Time};
+use super::round;
+
+// Time bands used to count trips, as [start, end) hours of the service day
+const TIME_BANDS: [(u64, u64); 6] = [(0, 6), (6, 9), (9, 15), (15, 19), (19, 24), (24, 48)];
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum HeadwayFormat {
+    Csv,
+    Json,
+    // Stop statistics only, as points
+    GeoJson,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsLevel {
+    Stop,
+    Route,
+    Pattern,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct FrequencyStats {
+    pub level: StatsLevel,
+    pub id: String,
+    pub name: Option<String>,
+    pub departures: usize,
+    pub first_departure: Option<String>,
+    pub last_departure: Option<String>,
+    pub span_hours: f64,
+    pub departures_per_hour: Option<f64>,
+    pub max_departures_per_hour: usize,
+    pub mean_headway_min: Option<f64>,
+    pub median_headway_min: Option<f64>,
+    pub max_headway_min: Option<f64>,
+    pub trips_early: usize,
+    pub trips_am_peak: usize,
+    pub trips_midday: usize,
+    pub trips_pm_peak: usize,
+    pub trips_evening: usize,
+    pub trips_night: usize,
+    // Departures for every hour of the service day, separated by semicolons
+    pub hourly_departures: String,
+}
+
+#[derive(Serialize)]
+struct StopFrequencyJson<'a> {
+    #[serde(serialize_with = "geojson::ser::serialize_geometry")]
+    geometry: geo_types::Point,
+    #[serde(flatten)]
+    stats: &'a FrequencyStats,
+}
+
+pub fn read_headway_stats(gtfs_file: &mut gtfs::GtfsFile, date: NaiveDate) -> Vec<FrequencyStats> {
+    let instances = trip::read_trip_instances(gtfs_file, date);
+    let patterns = pattern::extract_patterns(gtfs_file);
+    let routes: Vec<Route> = gtfs_file.read_vec();
+    let stops: Vec<Stop> = gtfs_file.read_vec();
+
+    headway_stats(&instances, &patterns, &routes, &stops)
+}
+
+pub fn headway_stats(
+    instances: &[TripInstance],
+    patterns: &[StopPattern],
+    routes: &[Route],
+    stops: &[Stop],
+) -> Vec<FrequencyStats> {
+    let mut stop_departures: BTreeMap<&str, Vec<u64>> = BTreeMap::new();
+    // Route departures are kept apart by direction, so headways are measured along one direction
+    let mut route_departures: BTreeMap<&str, BTreeMap<Option<u8>, Vec<u64>>> = BTreeMap::new();
+    let mut pattern_departures: BTreeMap<&str, Vec<u64>> = BTreeMap::new();
+
+    let trip_patterns: HashMap<&str, &str> = patterns
+        .iter()
+        .flat_map(|pattern| {
+            pattern
+                .trip_ids
+                .iter()
+                .map(|trip_id| (trip_id.as_str(), pattern.pattern_id.as_str()))
+        })
+        .collect();
+
+    for instance in instances {
+        // The last stop of a trip is an arrival only
+        let departing = &instance.stops[..instance.stops.len() - 1];
+        for stop in departing {
+            stop_departures
+                .entry(stop.stop_id.as_str())
+                .or_default()
+                .push(stop.departure);
+        }
+
+        let direction = instance
+            .trip
+            .direction_id
+            .map(|direction: Direction| direction as u8);
+        route_departures
+            .entry(instance.trip.route_id.as_str())
+            .or_default()
+            .entry(direction)
+            .or_default()
+            .push(instance.start_time());
+
+        if let Some(pattern_id) = trip_patterns.get(instance.trip.trip_id.as_str()) {
+            pattern_departures
+                .entry(pattern_id)
+                .or_default()
+                .push(instance.start_time());
+        }
+    }
+
+    let stop_names: HashMap<&str, Option<String>> = stops
+        .iter()
+        .map(|stop| (stop.stop_id.as_str(), stop.stop_name.clone()))
+        .collect();
+    let route_names: HashMap<&str, String> = routes
+        .iter()
+        .map(|route| {
+            (
+                route.route_id.as_str(),
+                pattern::route_name(route).to_owned(),
+            )
+        })
+        .collect();
+    let pattern_labels: HashMap<&str, String> = patterns
+        .iter()
+        .map(|pattern| (pattern.pattern_id.as_str(), pattern.label.to_owned()))
+        .collect();
+
+    let mut stats: Vec<FrequencyStats> = Vec::new();
+    for (stop_id, departures) in stop_departures {
+        let name = stop_names.get(stop_id).cloned().flatten();
+        stats.push(frequency_stats(
+            StatsLevel::Stop,
+            stop_id,
+            name,
+            vec![departures],
+        ));
+    }
+    for (route_id, directions) in route_departures {
+        let name = route_names.get(route_id).cloned();
+        stats.push(frequency_stats(
+            StatsLevel::Route,
+            route_id,
+            name,
+            directions.into_values().collect(),
+        ));
+    }
+    for (pattern_id, departures) in pattern_departures {
+        let name = pattern_labels.get(pattern_id).cloned();
+        stats.push(frequency_stats(
+            StatsLevel::Pattern,
+            pattern_id,
+            name,
+            vec![departures],
+        ));
+    }
+
+    stats
+}
+
+// Statistics over one or more series of departures. Headways are measured within each series.
+fn frequency_stats(
+    level: StatsLevel,
+    id: &str,
+    name: Option<String>,
+    mut series: Vec<Vec<u64>>,
+) -> FrequencyStats {
+    let mut headways: Vec<u64> = Vec::new();
+    for departures in series.iter_mut() {
+        departures.sort();
+        for pair in departures.windows(2) {
+            headways.push(pair[1] - pair[0]);
+        }
+    }
+    headways.sort();
+
+    let mut all_departures: Vec<u64> = series.into_iter().flatten().collect();
+    all_departures.sort();
+
+    let first = all_departures.first().copied();
+    let last = all_departures.last().copied();
+    let span_seconds = match (first, last) {
+        (Some(first), Some(last)) => last - first,
+        _ => 0,
+    };
+
+    let hours = last.map_or(0, |last| last / 3600 + 1) as usize;
+    let mut hourly: Vec<usize> = vec![0; hours];
+    for departure in &all_departures {
+        hourly[(departure / 3600) as usize] += 1;
+    }
+
+    let mut bands = [0; TIME_BANDS.len()];
+    for departure in &all_departures {
+        let hour = departure / 3600;
+        if let Some(band) = TIME_BANDS
+            .iter()
+            .position(|(start, end)| *start <= hour && hour < *end)
+        {
+            bands[band] += 1;
+        }
+    }
+
+    let mean_headway = if headways.is_empty() {
+        None
+    } else {
+        Some(headways.iter().sum::<u64>() as f64 / headways.len() as f64 / 60.0)
+    };
+
+    FrequencyStats {
+        level,
+        id: id.to_owned(),
+        name,
+        departures: all_departures.len(),
+        first_departure: first.map(|first| Time::from_seconds(first).to_string()),
+        last_departure: last.map(|last| Time::from_seconds(last).to_string()),
+        span_hours: round(span_seconds as f64 / 3600.0, 2),
+        departures_per_hour: match span_seconds {
+            0 => None,
+            _ => Some(round(
+                all_departures.len() as f64 / (span_seconds as f64 / 3600.0),
+                2,
+            )),
+        },
+        max_departures_per_hour: hourly.iter().copied().max().unwrap_or(0),
+        mean_headway_min: mean_headway.map(|mean| round(mean, 2)),
+        median_headway_min: median(&headways).map(|median| round(median / 60.0, 2)),
+        max_headway_min: headways.last().map(|max| round(*max as f64 / 60.0, 2)),
+        trips_early: bands[0],
+        trips_am_peak: bands[1],
+        trips_midday: bands[2],
+        trips_pm_peak: bands[3],
+        trips_evening: bands[4],
+        trips_night: bands[5],
+        hourly_departures: hourly
+            .iter()
+            .map(|count| count.to_string())
+            .collect::<Vec<String>>()
+            .join(";"),
+    }
+}
+
+pub fn median(sorted: &[u64]) -> Option<f64> {
+    match sorted.len() {
+        0 => None,
+        len if len % 2 == 1 => Some(sorted[len / 2] as f64),
+        len => Some((sorted[len / 2 - 1] + sorted[len / 2]) as f64 / 2.0),
+    }
+}
+
+// Stop level statistics as GeoJSON points, to map the frequent network
+pub fn stop_stats_geojson(stats: &[FrequencyStats], stops: &[Stop]) -> Result<String, String> {
+    let coordinates: HashMap<&str, geo_types::Point> = stops
+        .iter()
+        .filter_map(|stop| {
+            let point = geo_types::Point::new(stop.stop_lon?, stop.stop_lat?);
+            Some((stop.stop_id.as_str(), point))
+        })
+        .collect();
+
+    let features: Vec<StopFrequencyJson> = stats
+        .iter()
+        .filter(|stats| stats.level == StatsLevel::Stop)
+        .filter_map(|stats| {
+            Some(StopFrequencyJson {
+                geometry: *coordinates.get(stats.id.as_str())?,
+                stats,
+            })
+        })
+        .collect();
+
+    super::to_geojson_string(&features)
+}
+
+#[test]
+fn test_frequency_stats() {
+    let departures = vec![
+        vec![6 * 3600, 6 * 3600 + 600, 6 * 3600 + 1800],
+        vec![7 * 3600],
+    ];
+    let stats = frequency_stats(StatsLevel::Route, "R", None, departures);
+
+    assert_eq!(stats.departures, 4);
+    assert_eq!(stats.first_departure.as_deref(), Some("06:00:00"));
+    assert_eq!(stats.last_departure.as_deref(), Some("07:00:00"));
+    assert_eq!(stats.mean_headway_min, Some(15.0));
+    assert_eq!(stats.median_headway_min, Some(15.0));
+    assert_eq!(stats.max_headway_min, Some(20.0));
+    assert_eq!(stats.max_departures_per_hour, 3);
+    assert_eq!(stats.trips_am_peak, 4);
+    assert_eq!(stats.hourly_departures, "0;0;0;0;0;0;3;1");
+}
+
+#[test]
+fn test_headway_stats_geojson() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let date = NaiveDate::from_ymd_opt(2007, 6, 2).unwrap();
+    let stats = read_headway_stats(&mut gtfs_file, date);
+
+    let shuttle = stats
+        .iter()
+        .find(|stats| stats.level == StatsLevel::Route && stats.id == "STBA")
+        .unwrap();
+    assert_eq!(shuttle.departures, 32);
+    assert_eq!(shuttle.median_headway_min, Some(30.0));
+
+    let stops: Vec<Stop> = gtfs_file.read_vec();
+    let geojson = stop_stats_geojson(&stats, &stops).unwrap();
+    assert!(geojson.contains("\"id\":\"STAGECOACH\""));
+    assert!(geojson.contains("\"median_headway_min\""));
+}
@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+#[cfg(test)]
+use std::path::PathBuf;
+
+use chrono::{Datelike, NaiveDate, Weekday};
+
+use super::gtfs::{self, Calendar, CalendarDate, CalendarException};
+
+// Service ids running on a date, after applying calendar_dates exceptions
+pub fn active_services(
+    calendars: &[Calendar],
+    calendar_dates: &[CalendarDate],
+    date: NaiveDate,
+) -> HashSet<String> {
+    let mut services: HashSet<String> = HashSet::new();
+    for calendar in calendars {
+        if calendar.start_date <= date
+            && date <= calendar.end_date
+            && runs_on(calendar, date.weekday())
+        {
+            services.insert(calendar.service_id.to_owned());
+        }
+    }
+
+    for calendar_date in calendar_dates {
+        if calendar_date.date != date {
+            continue;
+        }
+        match calendar_date.exception_type {
+            CalendarException::Added => {
+                services.insert(calendar_date.service_id.to_owned());
+            }
+            CalendarException::Removed => {
+                services.remove(&calendar_date.service_id);
+            }
+        }
+    }
+
+    services
+}
+
+pub fn read_active_services(gtfs_file: &mut gtfs::GtfsFile, date: NaiveDate) -> HashSet<String> {
+    let calendars: Vec<Calendar> = gtfs_file.read_optional_vec();
+    let calendar_dates: Vec<CalendarDate> = gtfs_file.read_optional_vec();
+    active_services(&calendars, &calendar_dates, date)
+}
+
+// First and last date covered by calendar.txt and calendar_dates.txt
+pub fn service_date_range(
+    calendars: &[Calendar],
+    calendar_dates: &[CalendarDate],
+) -> Option<(NaiveDate, NaiveDate)> {
+    let starts = calendars.iter().map(|calendar| calendar.start_date).chain(
+        calendar_dates
+            .iter()
+            .map(|calendar_date| calendar_date.date),
+    );
+    let ends = calendars.iter().map(|calendar| calendar.end_date).chain(
+        calendar_dates
+            .iter()
+            .map(|calendar_date| calendar_date.date),
+    );
+
+    Some((starts.min()?, ends.max()?))
+}
+
+pub fn runs_on(calendar: &Calendar, weekday: Weekday) -> bool {
+    match weekday {
+        Weekday::Mon => calendar.monday,
+        Weekday::Tue => calendar.tuesday,
+        Weekday::Wed => calendar.wednesday,
+        Weekday::Thu => calendar.thursday,
+        Weekday::Fri => calendar.friday,
+        Weekday::Sat => calendar.saturday,
+        Weekday::Sun => calendar.sunday,
+    }
+}
+
+#[test]
+fn test_active_services() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+
+    let saturday = NaiveDate::from_ymd_opt(2007, 6, 2).unwrap();
+    let mut services: Vec<String> = read_active_services(&mut gtfs_file, saturday)
+        .into_iter()
+        .collect();
+    services.sort();
+    assert_eq!(services, vec!["FULLW", "WE"]);
+
+    // FULLW is removed on this monday through calendar_dates.txt
+    let removed = NaiveDate::from_ymd_opt(2007, 6, 4).unwrap();
+    assert!(read_active_services(&mut gtfs_file, removed).is_empty());
+}
@@ -0,0 +1,350 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+#[cfg(test)]
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::objects::feed::Feed;
+use crate::objects::pattern;
+use crate::objects::trip::TripInstance;
+
+use super::gtfs::{self, Time};
+use super::realtime::{proto, realized};
+use super::routing::nearest_rank;
+
+// One observed call of a trip, with times like in stop_times.txt. The call is given by its
+// stop_sequence, or its stop_id where the sequence is unknown. Frequency based trips also need the
+// start_time of the instance.
+#[derive(Debug, Deserialize)]
+pub struct Observation {
+    pub trip_id: String,
+    #[serde(with = "gtfs::date")]
+    pub service_date: NaiveDate,
+    #[serde(default)]
+    pub start_time: Option<Time>,
+    #[serde(default)]
+    pub stop_id: Option<String>,
+    #[serde(default)]
+    pub stop_sequence: Option<u64>,
+    #[serde(default)]
+    pub observed_arrival: Option<Time>,
+    #[serde(default)]
+    pub observed_departure: Option<Time>,
+}
+
+pub fn read_observations(path: &Path) -> Result<Vec<Observation>, String> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+    parse_observations(&contents)
+}
+
+fn parse_observations(contents: &str) -> Result<Vec<Observation>, String> {
+    let mut reader = csv::Reader::from_reader(contents.as_bytes());
+    let mut observations: Vec<Observation> = Vec::new();
+    for (row, record) in reader.deserialize().enumerate() {
+        observations.push(record.map_err(|error| format!("Row {}: {}", row + 1, error))?);
+    }
+    Ok(observations)
+}
+
+// A call of an observed trip instance with its delays in seconds, either observed or carried on
+// from the last observed departure before it
+#[derive(Debug, Serialize)]
+pub struct CallDelay {
+    pub trip_id: String,
+    pub service_date: String,
+    pub start_time: Time,
+    pub route_id: String,
+    pub stop_sequence: u64,
+    pub stop_id: String,
+    pub scheduled_arrival: Time,
+    pub scheduled_departure: Time,
+    pub arrival_delay: Option<i64>,
+    pub departure_delay: Option<i64>,
+    pub observed: bool,
+}
+
+pub struct DelayData {
+    pub calls: Vec<CallDelay>,
+    pub trips: usize,
+    // Observations of trips, dates or calls missing from the feed
+    pub unmatched: usize,
+}
+
+// Joins the observations to the scheduled calls of their trip instances. A call observed only on
+// arrival departs at that time at the earliest. Delays carry on to later calls as for realtime
+// updates, so calls before the first observation have no delay.
+pub fn join_observations(feed: &Feed, observations: &[Observation]) -> DelayData {
+    let mut groups: BTreeMap<(&str, NaiveDate, Option<u64>), Vec<&Observation>> = BTreeMap::new();
+    for observation in observations {
+        let start_time = observation.start_time.as_ref().map(Time::seconds);
+        groups
+            .entry((
+                observation.trip_id.as_str(),
+                observation.service_date,
+                start_time,
+            ))
+            .or_default()
+            .push(observation);
+    }
+
+    let event = |time: Option<i64>| {
+        time.map(|time| proto::StopTimeEvent {
+            time: Some(time),
+            ..Default::default()
+        })
+    };
+    let mut instances: HashMap<NaiveDate, Vec<TripInstance>> = HashMap::new();
+    let mut data = DelayData {
+        calls: Vec::new(),
+        trips: 0,
+        unmatched: 0,
+    };
+    for ((trip_id, date, start_time), observations) in groups {
+        let instances = instances
+            .entry(date)
+            .or_insert_with(|| feed.trip_instances(&feed.active_services(date)));
+        let mut candidates = instances
+            .iter()
+            .filter(|instance| instance.trip.trip_id == trip_id);
+        let instance = match start_time {
+            Some(start_time) => candidates.find(|instance| instance.start_time() == start_time),
+            None => candidates.next().filter(|_| candidates.next().is_none()),
+        };
+        let Some(instance) = instance else {
+            data.unmatched += observations.len();
+            continue;
+        };
+
+        // Observations as updates of their calls, with times since the start of the service day
+        let mut observed: Vec<bool> = vec![false; instance.stops.len()];
+        let mut updates: Vec<proto::StopTimeUpdate> = Vec::new();
+        for observation in observations {
+            let call = match (observation.stop_sequence, &observation.stop_id) {
+                (Some(sequence), _) => instance
+                    .stops
+                    .iter()
+                    .position(|stop| stop.stop_sequence == sequence),
+                (None, Some(stop_id)) => instance
+                    .stops
+                    .iter()
+                    .position(|stop| &stop.stop_id == stop_id),
+                (None, None) => None,
+            };
+            let Some(call) = call else {
+                data.unmatched += 1;
+                continue;
+            };
+            let stop = &instance.stops[call];
+            let arrival = observation
+                .observed_arrival
+                .as_ref()
+                .map(|time| time.seconds() as i64);
+            let departure = observation
+                .observed_departure
+                .as_ref()
+                .map(|time| time.seconds() as i64)
+                .or(arrival.map(|arrival| arrival.max(stop.departure as i64)));
+            observed[call] = true;
+            updates.push(proto::StopTimeUpdate {
+                stop_sequence: Some(stop.stop_sequence as u32),
+                arrival: event(arrival),
+                departure: event(departure),
+                ..Default::default()
+            });
+        }
+
+        data.trips += 1;
+        let mut delays: Vec<(Option<i64>, Option<i64>)> = vec![(None, None); instance.stops.len()];
+        for (call, prediction) in realized::predict_calls(instance, &updates, 0) {
+            let stop = &instance.stops[call];
+            delays[call] = (
+                Some(prediction.arrival - stop.arrival as i64),
+                Some(prediction.departure - stop.departure as i64),
+            );
+        }
+        for ((stop, (arrival_delay, departure_delay)), observed) in
+            instance.stops.iter().zip(delays).zip(observed)
+        {
+            data.calls.push(CallDelay {
+                trip_id: trip_id.to_owned(),
+                service_date: date.format("%Y%m%d").to_string(),
+                start_time: Time::from_seconds(instance.start_time()),
+                route_id: instance.trip.route_id.to_owned(),
+                stop_sequence: stop.stop_sequence,
+                stop_id: stop.stop_id.to_owned(),
+                scheduled_arrival: Time::from_seconds(stop.arrival),
+                scheduled_departure: Time::from_seconds(stop.departure),
+                arrival_delay,
+                departure_delay,
+                observed,
+            });
+        }
+    }
+    data
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum DelaysFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Grouping {
+    Stop,
+    Route,
+}
+
+// Delays counting as on time, in seconds either side of the schedule
+#[derive(Debug, Clone, Copy)]
+pub struct OnTimeWindow {
+    pub early_s: i64,
+    pub late_s: i64,
+}
+
+impl Default for OnTimeWindow {
+    fn default() -> OnTimeWindow {
+        OnTimeWindow {
+            early_s: 60,
+            late_s: 300,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Punctuality {
+    pub id: String,
+    pub name: Option<String>,
+    // Calls with a delay, and how many of them were observed
+    pub calls: usize,
+    pub observed: usize,
+    pub on_time_pct: f64,
+    pub early_pct: f64,
+    pub late_pct: f64,
+    pub mean_delay_s: f64,
+    pub p50_delay_s: i64,
+    pub p90_delay_s: i64,
+    pub p95_delay_s: i64,
+}
+
+// Punctuality per stop or route, of departures, or of arrivals where there is no departure delay
+// such as at the end of a trip
+pub fn punctuality(
+    feed: &Feed,
+    calls: &[CallDelay],
+    grouping: Grouping,
+    window: OnTimeWindow,
+) -> Vec<Punctuality> {
+    let mut groups: BTreeMap<&str, (Vec<i64>, usize)> = BTreeMap::new();
+    for call in calls {
+        let Some(delay) = call.departure_delay.or(call.arrival_delay) else {
+            continue;
+        };
+        let id = match grouping {
+            Grouping::Stop => call.stop_id.as_str(),
+            Grouping::Route => call.route_id.as_str(),
+        };
+        let (delays, observed) = groups.entry(id).or_default();
+        delays.push(delay);
+        *observed += call.observed as usize;
+    }
+
+    let names: HashMap<&str, &str> = match grouping {
+        Grouping::Stop => feed
+            .stops
+            .iter()
+            .filter_map(|stop| Some((stop.stop_id.as_str(), stop.stop_name.as_deref()?)))
+            .collect(),
+        Grouping::Route => feed
+            .routes
+            .iter()
+            .map(|route| (route.route_id.as_str(), pattern::route_name(route)))
+            .collect(),
+    };
+    groups
+        .into_iter()
+        .map(|(id, (mut delays, observed))| {
+            delays.sort();
+            let share = |count: usize| 100.0 * count as f64 / delays.len() as f64;
+            let early = delays
+                .iter()
+                .filter(|delay| **delay < -window.early_s)
+                .count();
+            let late = delays
+                .iter()
+                .filter(|delay| **delay > window.late_s)
+                .count();
+            Punctuality {
+                id: id.to_owned(),
+                name: names.get(id).map(|name| name.to_string()),
+                calls: delays.len(),
+                observed,
+                on_time_pct: share(delays.len() - early - late),
+                early_pct: share(early),
+                late_pct: share(late),
+                mean_delay_s: delays.iter().sum::<i64>() as f64 / delays.len() as f64,
+                p50_delay_s: nearest_rank(&delays, 50.0).unwrap_or_default(),
+                p90_delay_s: nearest_rank(&delays, 90.0).unwrap_or_default(),
+                p95_delay_s: nearest_rank(&delays, 95.0).unwrap_or_default(),
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_punctuality() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let feed = Feed::load(&mut gtfs_file);
+
+    let observations = parse_observations(
+        "trip_id,service_date,start_time,stop_id,stop_sequence,observed_arrival,observed_departure\n\
+         AB1,20070605,,BEATTY_AIRPORT,,,08:03:00\n\
+         STBA,20070605,06:30:00,,1,,06:31:00\n\
+         CITY1,20070605,08:00:00,,2,08:05:30,\n\
+         GHOST,20070605,,NOWHERE,,08:00:00,\n",
+    )
+    .unwrap();
+    let data = join_observations(&feed, &observations);
+    assert_eq!(data.trips, 3);
+    assert_eq!(data.unmatched, 1);
+    let call = |trip_id: &str, sequence: u64| {
+        data.calls
+            .iter()
+            .find(|call| call.trip_id == trip_id && call.stop_sequence == sequence)
+            .unwrap()
+    };
+    // The shuttle's delay carries on to the airport, and the city loop arrived early enough to
+    // leave on time
+    assert_eq!(call("AB1", 2).arrival_delay, Some(180));
+    assert_eq!(call("STBA", 2).departure_delay, Some(60));
+    assert!(!call("STBA", 2).observed);
+    assert_eq!(call("CITY1", 1).departure_delay, None);
+    assert_eq!(call("CITY1", 2).arrival_delay, Some(30));
+    assert_eq!(call("CITY1", 3).departure_delay, Some(0));
+
+    let window = OnTimeWindow {
+        early_s: 60,
+        late_s: 120,
+    };
+    let stops = punctuality(&feed, &data.calls, Grouping::Stop, window);
+    let airport = stops
+        .iter()
+        .find(|stop| stop.id == "BEATTY_AIRPORT")
+        .unwrap();
+    assert_eq!(airport.calls, 2);
+    assert_eq!(airport.observed, 1);
+    assert_eq!(airport.on_time_pct, 50.0);
+    assert_eq!(airport.mean_delay_s, 120.0);
+    assert_eq!(airport.p50_delay_s, 60);
+    assert_eq!(airport.p95_delay_s, 180);
+
+    let routes = punctuality(&feed, &data.calls, Grouping::Route, window);
+    let ab = routes.iter().find(|route| route.id == "AB").unwrap();
+    assert_eq!((ab.calls, ab.late_pct), (2, 100.0));
+    assert_eq!(ab.name.as_deref(), Some("10"));
+}
@@ -0,0 +1,540 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+#[cfg(test)]
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+
+use crate::objects::feed::Feed;
+use crate::objects::spatial::SpatialIndex;
+
+use super::geodata;
+#[cfg(test)]
+use super::gtfs;
+use super::gtfs::{LocationType, Shape, Stop, StopTime, Time, Trip};
+use super::scenario::{self, ReplacedTrips};
+
+pub struct Detour {
+    pub feed: Feed,
+    pub trips: ReplacedTrips,
+    pub shapes: usize,
+}
+
+// The replacement of every closed stop, from the given list in the same order or else the nearest
+// open stop that isn't a station or entrance
+pub fn replacement_stops(
+    feed: &Feed,
+    closed: &[String],
+    given: &[String],
+) -> Result<BTreeMap<String, String>, String> {
+    let stops: HashMap<&str, &Stop> = feed
+        .stops
+        .iter()
+        .map(|stop| (stop.stop_id.as_str(), stop))
+        .collect();
+    if let Some(stop_id) = closed
+        .iter()
+        .chain(given)
+        .find(|stop_id| !stops.contains_key(stop_id.as_str()))
+    {
+        return Err(format!("Unknown stop {}", stop_id));
+    }
+    if !given.is_empty() {
+        if given.len() != closed.len() {
+            return Err(String::from(
+                "Give a replacement for every closed stop, or none",
+            ));
+        }
+        if let Some(stop_id) = given.iter().find(|stop_id| closed.contains(stop_id)) {
+            return Err(format!("Replacement stop {} is closed", stop_id));
+        }
+        return Ok(closed.iter().cloned().zip(given.iter().cloned()).collect());
+    }
+
+    let index = SpatialIndex::new(&feed.stops, &[]);
+    let mut replacements: BTreeMap<String, String> = BTreeMap::new();
+    for stop_id in closed {
+        let Some(point) = geodata::stop_point(stops[stop_id.as_str()]) else {
+            return Err(format!("Closed stop {} has no coordinates", stop_id));
+        };
+        let nearest = index
+            .nearest_stops(point, closed.len() + 10)
+            .into_iter()
+            .find(|candidate| {
+                let stop = &feed.stops[candidate.index];
+                !closed.contains(&stop.stop_id)
+                    && matches!(stop.location_type, None | Some(LocationType::Stop))
+            });
+        let Some(nearest) = nearest else {
+            return Err(format!("No open stop near {}", stop_id));
+        };
+        replacements.insert(stop_id.to_owned(), nearest.stop_id);
+    }
+    Ok(replacements)
+}
+
+fn seconds(time: &Option<Time>) -> Option<i64> {
+    time.as_ref().map(|time| time.seconds() as i64)
+}
+
+fn time(seconds: i64) -> Option<Time> {
+    Some(Time::from_seconds(seconds.max(0) as u64))
+}
+
+// A call of the detoured trip, of an original call or of the replacement of a closed one
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Call<'a> {
+    Original(usize),
+    Replacement(usize, &'a str),
+}
+
+impl<'a> Call<'a> {
+    fn stop_id(self, stop_times: &'a [StopTime]) -> &'a str {
+        match self {
+            Call::Original(index) => stop_times[index].stop_id.as_deref().unwrap_or_default(),
+            Call::Replacement(_, stop_id) => stop_id,
+        }
+    }
+}
+
+// The trip with its closed stops replaced. Runs of closed stops are driven at the speed the trip
+// had over them, without their dwell times, and later stops move by the time the detour gains or
+// loses. Replacements already called at next to the run are left out. Also the index in
+// `stop_times` of every call, none for replacements.
+fn detour_stop_times(
+    stop_times: &[StopTime],
+    replacements: &BTreeMap<String, String>,
+    points: &HashMap<&str, geo_types::Point>,
+) -> Option<(Vec<StopTime>, Vec<Option<usize>>)> {
+    let closed: Vec<bool> = stop_times
+        .iter()
+        .map(|stop_time| {
+            stop_time
+                .stop_id
+                .as_ref()
+                .is_some_and(|stop_id| replacements.contains_key(stop_id))
+        })
+        .collect();
+    if !closed.contains(&true) {
+        return None;
+    }
+
+    let point = |stop_id: &str| points.get(stop_id).copied();
+    let length = |stop_ids: &[&str]| -> Option<f64> {
+        let line: Vec<geo_types::Point> = stop_ids
+            .iter()
+            .map(|stop_id| point(stop_id))
+            .collect::<Option<_>>()?;
+        Some(geodata::line_length(&line))
+    };
+    let mut calls: Vec<Call> = Vec::new();
+    let mut times: Vec<(Option<i64>, Option<i64>)> = Vec::new();
+    let mut shift: i64 = 0;
+    let mut index = 0;
+    while index < stop_times.len() {
+        let stop_time = &stop_times[index];
+        if !closed[index] {
+            calls.push(Call::Original(index));
+            times.push((
+                seconds(&stop_time.arrival_time).map(|time| time + shift),
+                seconds(&stop_time.departure_time).map(|time| time + shift),
+            ));
+            index += 1;
+            continue;
+        }
+        let end = (index..stop_times.len())
+            .find(|index| !closed[*index])
+            .unwrap_or(stop_times.len());
+        let run = &stop_times[index..end];
+        let previous = calls.last().copied().map(|call| call.stop_id(stop_times));
+        let next = stop_times
+            .get(end)
+            .and_then(|stop_time| stop_time.stop_id.as_deref());
+        let mut replaced: Vec<(usize, &str)> = Vec::new();
+        for (offset, stop_time) in run.iter().enumerate() {
+            let replacement =
+                replacements[stop_time.stop_id.as_deref().unwrap_or_default()].as_str();
+            let last = replaced.last().map(|(_, stop_id)| *stop_id).or(previous);
+            if Some(replacement) != last
+                && Some(replacement) != next.filter(|_| offset == run.len() - 1)
+            {
+                replaced.push((index + offset, replacement));
+            }
+        }
+
+        // Moving time and straight line distance over the closed stops, from the call before
+        // the run or the first closed stop, to the call after it or the last closed stop
+        let dwell = |stop_time: &StopTime| match (
+            seconds(&stop_time.arrival_time),
+            seconds(&stop_time.departure_time),
+        ) {
+            (Some(arrival), Some(departure)) => departure - arrival,
+            _ => 0,
+        };
+        let inner = &run[(previous.is_none() as usize)..run.len() - (next.is_none() as usize)];
+        let start = match index {
+            0 => seconds(&run[0].departure_time),
+            _ => seconds(&stop_times[index - 1].departure_time),
+        };
+        let finish = match next {
+            Some(_) => seconds(&stop_times[end].arrival_time),
+            None => seconds(&run[run.len() - 1].arrival_time),
+        };
+        let mut original: Vec<&str> = previous.into_iter().collect();
+        original.extend(
+            run.iter()
+                .filter_map(|stop_time| stop_time.stop_id.as_deref()),
+        );
+        original.extend(next);
+        let speed = match (start, finish, length(&original)) {
+            (Some(start), Some(finish), Some(distance)) => {
+                let moving = finish - start - inner.iter().map(dwell).sum::<i64>();
+                (moving > 0 && distance > 0.0).then(|| distance / moving as f64)
+            }
+            _ => None,
+        };
+
+        let mut clock = start.map(|start| start + shift);
+        let mut last = previous;
+        for (closed_index, stop_id) in replaced {
+            let travel = match (last, speed) {
+                (Some(last), Some(speed)) => {
+                    length(&[last, stop_id]).map(|distance| (distance / speed).round() as i64)
+                }
+                (None, _) => Some(0),
+                _ => None,
+            };
+            let (arrival, departure) = match clock.zip(travel) {
+                Some((clock, travel)) => (
+                    clock + travel,
+                    clock + travel + dwell(&stop_times[closed_index]),
+                ),
+                // Without a speed the replacement keeps the closed stop's times
+                None => (
+                    seconds(&stop_times[closed_index].arrival_time).unwrap_or_default() + shift,
+                    seconds(&stop_times[closed_index].departure_time).unwrap_or_default() + shift,
+                ),
+            };
+            calls.push(Call::Replacement(closed_index, stop_id));
+            times.push((Some(arrival), Some(departure)));
+            clock = Some(departure);
+            last = Some(stop_id);
+        }
+        if let (Some(next), Some(last), Some(speed), Some(clock), Some(finish)) =
+            (next, last, speed, clock, finish)
+        {
+            if let Some(distance) = length(&[last, next]) {
+                shift = clock + (distance / speed).round() as i64 - finish;
+            }
+        }
+        index = end;
+    }
+
+    let mut detoured: Vec<StopTime> = Vec::new();
+    let mut originals: Vec<Option<usize>> = Vec::new();
+    for (call, (arrival, departure)) in calls.iter().zip(times) {
+        let (source, original, stop_id) = match call {
+            Call::Original(index) => (*index, Some(*index), None),
+            Call::Replacement(index, stop_id) => (*index, None, Some(stop_id.to_string())),
+        };
+        originals.push(original);
+        let stop_time = &stop_times[source];
+        detoured.push(StopTime {
+            stop_id: stop_id.or(stop_time.stop_id.clone()),
+            arrival_time: arrival.and_then(time).or(stop_time.arrival_time.clone()),
+            departure_time: departure
+                .and_then(time)
+                .or(stop_time.departure_time.clone()),
+            shape_dist_travelled: None,
+            ..stop_time.clone()
+        });
+    }
+    Some((detoured, originals))
+}
+
+// The shape of a detoured trip: the original shape between calls that are still next to each
+// other, and straight lines to and from replacement stops, as there is no road network to route on
+fn detour_shape(
+    line: &[geo_types::Point],
+    stop_times: &[StopTime],
+    detoured: &[StopTime],
+    originals: &[Option<usize>],
+    points: &HashMap<&str, geo_types::Point>,
+) -> Vec<geo_types::Point> {
+    let mut segment = 0;
+    let distances: Vec<Option<f64>> = stop_times
+        .iter()
+        .map(|stop_time| {
+            let point = points.get(stop_time.stop_id.as_deref()?)?;
+            let (found, along, _) = geodata::project_onto_line(line, *point, segment)?;
+            segment = found;
+            Some(along)
+        })
+        .collect();
+    let along = |position: usize| {
+        distances
+            .get((*originals.get(position)?)?)
+            .copied()
+            .flatten()
+    };
+    let point = |position: usize| {
+        points
+            .get(detoured[position].stop_id.as_deref().unwrap_or_default())
+            .copied()
+    };
+
+    let mut shape: Vec<geo_types::Point> = Vec::new();
+    if originals.first() == Some(&Some(0)) {
+        if let Some(first) = along(0) {
+            shape.extend(geodata::sub_line(line, 0.0, first));
+        }
+    }
+    for position in 0..detoured.len() {
+        shape.extend(point(position));
+        if position + 1 == detoured.len() {
+            break;
+        }
+        let adjacent = matches!((originals[position], originals[position + 1]), (Some(from), Some(to)) if to == from + 1);
+        if let (true, Some(from), Some(to)) = (adjacent, along(position), along(position + 1)) {
+            shape.extend(geodata::sub_line(line, from, to));
+        }
+    }
+    if originals.last() == Some(&Some(stop_times.len() - 1)) {
+        if let Some(last) = along(detoured.len() - 1) {
+            shape.extend(geodata::sub_line(line, last, f64::INFINITY));
+        }
+    }
+    shape.dedup();
+    shape
+}
+
+// Detours the trips of a route, or of a pattern, around closed stops on the dates from `from` to
+// `to`, as new trips tagged like their services, with new shapes for trips that had one
+pub fn detour(
+    feed: &Feed,
+    id: &str,
+    replacements: &BTreeMap<String, String>,
+    from: NaiveDate,
+    to: NaiveDate,
+    tag: &str,
+) -> Result<Detour, String> {
+    let patterns = feed.stop_patterns();
+    let trip_ids: HashSet<&str> = if feed.routes.iter().any(|route| route.route_id == id) {
+        feed.trips
+            .iter()
+            .filter(|trip| trip.route_id == id)
+            .map(|trip| trip.trip_id.as_str())
+            .collect()
+    } else {
+        patterns
+            .iter()
+            .filter(|pattern| pattern.pattern_id == id)
+            .flat_map(|pattern| pattern.trip_ids.iter().map(String::as_str))
+            .collect()
+    };
+    if trip_ids.is_empty() {
+        return Err(format!("No route or pattern with id {}", id));
+    }
+
+    let points: HashMap<&str, geo_types::Point> = feed
+        .stops
+        .iter()
+        .filter_map(|stop| Some((stop.stop_id.as_str(), geodata::stop_point(stop)?)))
+        .collect();
+    let shape_lines = geodata::shape_lines(&feed.shapes);
+    let mut shapes: Vec<(Vec<geo_types::Point>, String)> = Vec::new();
+    let mut trip_shapes: HashMap<String, String> = HashMap::new();
+    let (mut edited, trips) =
+        scenario::replace_trips(feed, from, to, tag, |trip: &Trip, stop_times| {
+            if !trip_ids.contains(trip.trip_id.as_str()) {
+                return None;
+            }
+            let (detoured, originals) = detour_stop_times(stop_times, replacements, &points)?;
+            if detoured.len() < 2 {
+                return Some(Vec::new());
+            }
+            if let Some((shape_id, line)) = trip
+                .shape_id
+                .as_ref()
+                .and_then(|shape_id| Some((shape_id, shape_lines.get(shape_id)?)))
+            {
+                let shape = detour_shape(line, stop_times, &detoured, &originals, &points);
+                let position = match shapes
+                    .iter()
+                    .position(|(existing, original)| original == shape_id && *existing == shape)
+                {
+                    Some(position) => position,
+                    None => {
+                        shapes.push((shape, shape_id.to_owned()));
+                        shapes.len() - 1
+                    }
+                };
+                trip_shapes.insert(
+                    scenario::part_trip_id(&trip.trip_id, tag, 0, 1),
+                    shape_name(&shapes, position, tag),
+                );
+            }
+            Some(vec![detoured])
+        });
+
+    for trip in &mut edited.trips {
+        if let Some(shape_id) = trip_shapes.get(&trip.trip_id) {
+            trip.shape_id = Some(shape_id.to_owned());
+        }
+    }
+    for position in 0..shapes.len() {
+        let shape_id = shape_name(&shapes, position, tag);
+        edited.shapes.extend(
+            shapes[position]
+                .0
+                .iter()
+                .enumerate()
+                .map(|(sequence, point)| Shape {
+                    shape_id: shape_id.to_owned(),
+                    shape_pt_lat: point.y(),
+                    shape_pt_lon: point.x(),
+                    shape_pt_sequence: sequence as u64 + 1,
+                    shape_dist_travelled: None,
+                }),
+        );
+    }
+    Ok(Detour {
+        feed: edited,
+        trips,
+        shapes: shapes.len(),
+    })
+}
+
+// New shapes are named after the original, numbered where trips of one shape detour differently
+fn shape_name(shapes: &[(Vec<geo_types::Point>, String)], position: usize, tag: &str) -> String {
+    let original = &shapes[position].1;
+    let number = shapes[..position]
+        .iter()
+        .filter(|(_, other)| other == original)
+        .count();
+    match number {
+        0 => format!("{}-{}", original, tag),
+        _ => format!("{}-{}-{}", original, tag, number + 1),
+    }
+}
+
+#[test]
+fn test_detour() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let mut feed = Feed::load(&mut gtfs_file);
+    let (from, to) = (
+        NaiveDate::from_ymd_opt(2007, 6, 5).unwrap(),
+        NaiveDate::from_ymd_opt(2007, 6, 6).unwrap(),
+    );
+    let closed = vec![String::from("NADAV")];
+    let stop_ids = |feed: &Feed, trip_id: &str| -> Vec<String> {
+        feed.stop_times
+            .iter()
+            .filter(|stop_time| stop_time.trip_id == trip_id)
+            .filter_map(|stop_time| stop_time.stop_id.clone())
+            .collect()
+    };
+
+    // The nearest open stop is already called at next to NADAV, so CITY1 drives straight on to
+    // DADAN at the 2 m/s it made over NADAV, arriving about five minutes early
+    let replacements = replacement_stops(&feed, &closed, &[]).unwrap();
+    assert!(["NANAA", "DADAN"].contains(&replacements["NADAV"].as_str()));
+    let detoured = detour(&feed, "CITY", &replacements, from, to, "detour").unwrap();
+    assert_eq!(
+        (detoured.trips.dates, detoured.trips.edited, detoured.shapes),
+        (2, 2, 0)
+    );
+    assert_eq!(
+        stop_ids(&detoured.feed, "CITY1-detour"),
+        vec!["STAGECOACH", "NANAA", "DADAN", "EMSI"]
+    );
+    let times: Vec<&StopTime> = detoured
+        .feed
+        .stop_times
+        .iter()
+        .filter(|stop_time| stop_time.trip_id == "CITY1-detour")
+        .collect();
+    let arrival = times[2].arrival_time.as_ref().unwrap().seconds();
+    assert!(
+        (6 * 3600 + 14 * 60..6 * 3600 + 15 * 60).contains(&arrival),
+        "{}",
+        arrival
+    );
+    let early = 6 * 3600 + 19 * 60 - arrival;
+    assert_eq!(
+        times[3].arrival_time.as_ref().unwrap().seconds(),
+        6 * 3600 + 26 * 60 - early
+    );
+
+    // A temporary stop on a side street, with a shape for CITY1 through its stops
+    let mut temporary = feed
+        .stops
+        .iter()
+        .find(|stop| stop.stop_id == "NADAV")
+        .unwrap()
+        .clone();
+    temporary.stop_id = String::from("NADAV_TEMP");
+    (temporary.stop_lat, temporary.stop_lon) = (Some(36.9125), Some(-116.7705));
+    feed.stops.push(temporary);
+    let through = stop_ids(&feed, "CITY1");
+    for (sequence, stop_id) in through.iter().enumerate() {
+        let stop = feed
+            .stops
+            .iter()
+            .find(|stop| stop.stop_id == *stop_id)
+            .unwrap()
+            .clone();
+        feed.shapes.push(Shape {
+            shape_id: String::from("city1"),
+            shape_pt_lat: stop.stop_lat.unwrap(),
+            shape_pt_lon: stop.stop_lon.unwrap(),
+            shape_pt_sequence: sequence as u64 + 1,
+            shape_dist_travelled: None,
+        });
+    }
+    feed.trips
+        .iter_mut()
+        .find(|trip| trip.trip_id == "CITY1")
+        .unwrap()
+        .shape_id = Some(String::from("city1"));
+
+    let replacements = replacement_stops(&feed, &closed, &[String::from("NADAV_TEMP")]).unwrap();
+    let detoured = detour(&feed, "CITY", &replacements, from, to, "temp").unwrap();
+    assert_eq!(
+        stop_ids(&detoured.feed, "CITY1-temp"),
+        vec!["STAGECOACH", "NANAA", "NADAV_TEMP", "DADAN", "EMSI"]
+    );
+    let trip = detoured
+        .feed
+        .trips
+        .iter()
+        .find(|trip| trip.trip_id == "CITY1-temp")
+        .unwrap();
+    assert_eq!(trip.shape_id.as_deref(), Some("city1-temp"));
+    let shape: Vec<(f64, f64)> = detoured
+        .feed
+        .shapes
+        .iter()
+        .filter(|shape| shape.shape_id == "city1-temp")
+        .map(|shape| (shape.shape_pt_lat, shape.shape_pt_lon))
+        .collect();
+    assert_eq!(shape.len(), 5);
+    assert!(shape.contains(&(36.9125, -116.7705)) && !shape.contains(&(36.914893, -116.76821)));
+
+    let trip_ids = |date: NaiveDate| -> HashSet<String> {
+        let services = detoured.feed.active_services(date);
+        detoured
+            .feed
+            .trips
+            .iter()
+            .filter(|trip| services.contains(&trip.service_id))
+            .map(|trip| trip.trip_id.to_owned())
+            .collect()
+    };
+    assert!(trip_ids(to).contains("CITY1-temp") && !trip_ids(to).contains("CITY1"));
+    let after = trip_ids(NaiveDate::from_ymd_opt(2007, 6, 7).unwrap());
+    assert!(after.contains("CITY1") && !after.contains("CITY1-temp"));
+
+    assert!(replacement_stops(&feed, &closed, &[String::from("NADAV")]).is_err());
+    assert!(detour(&feed, "NOPE", &replacements, from, to, "temp").is_err());
+}
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use serde::Serialize;
 
 use super::gtfs::{LocationType, Shape, Stop, WheelchairAccessibility};
@@ -77,6 +79,238 @@ pub struct GeoShapeLine {
     pub geometry: geo_types::LineString,
 }
 
+const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+// Great circle distance in metres between two (lon, lat) points
+pub fn haversine_distance(a: geo_types::Point, b: geo_types::Point) -> f64 {
+    let (lat_a, lat_b) = (a.y().to_radians(), b.y().to_radians());
+    let d_lat = lat_b - lat_a;
+    let d_lon = (b.x() - a.x()).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * h.sqrt().asin()
+}
+
+pub fn line_length(points: &[geo_types::Point]) -> f64 {
+    points
+        .windows(2)
+        .map(|pair| haversine_distance(pair[0], pair[1]))
+        .sum()
+}
+
+pub fn stop_point(stop: &Stop) -> Option<geo_types::Point> {
+    Some(geo_types::Point::new(stop.stop_lon?, stop.stop_lat?))
+}
+
+// Projects a point onto a line, only considering segments from `from_segment` on so that
+// consecutive stops of a looping route keep their order. Returns the segment index, the
+// distance along the line and the distance from the line, all in metres.
+pub fn project_onto_line(
+    line: &[geo_types::Point],
+    point: geo_types::Point,
+    from_segment: usize,
+) -> Option<(usize, f64, f64)> {
+    let mut along = 0.0;
+    let mut best: Option<(usize, f64, f64)> = None;
+    for (index, pair) in line.windows(2).enumerate() {
+        let length = haversine_distance(pair[0], pair[1]);
+        if index >= from_segment {
+            // Planar approximation around the segment start, fine at segment scale
+            let scale = pair[0].y().to_radians().cos();
+            let (dx, dy) = (
+                (pair[1].x() - pair[0].x()) * scale,
+                pair[1].y() - pair[0].y(),
+            );
+            let (px, py) = ((point.x() - pair[0].x()) * scale, point.y() - pair[0].y());
+            let squared = dx * dx + dy * dy;
+            let fraction = if squared > 0.0 {
+                ((px * dx + py * dy) / squared).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let projected = geo_types::Point::new(
+                pair[0].x() + (pair[1].x() - pair[0].x()) * fraction,
+                pair[0].y() + (pair[1].y() - pair[0].y()) * fraction,
+            );
+            let offset = haversine_distance(point, projected);
+            if best.map_or(true, |(_, _, best_offset)| offset < best_offset) {
+                best = Some((index, along + length * fraction, offset));
+            }
+        }
+        along += length;
+    }
+    best
+}
+
+// The point at a distance in metres along a line, clamped to its ends
+pub fn point_along_line(line: &[geo_types::Point], distance_m: f64) -> Option<geo_types::Point> {
+    let mut along = 0.0;
+    for pair in line.windows(2) {
+        let length = haversine_distance(pair[0], pair[1]);
+        if along + length >= distance_m && length > 0.0 {
+            let fraction = ((distance_m - along) / length).clamp(0.0, 1.0);
+            return Some(geo_types::Point::new(
+                pair[0].x() + (pair[1].x() - pair[0].x()) * fraction,
+                pair[0].y() + (pair[1].y() - pair[0].y()) * fraction,
+            ));
+        }
+        along += length;
+    }
+    line.last().copied()
+}
+
+// The part of a line between two distances along it in metres
+pub fn sub_line(line: &[geo_types::Point], from_m: f64, to_m: f64) -> Vec<geo_types::Point> {
+    let mut points: Vec<geo_types::Point> = point_along_line(line, from_m).into_iter().collect();
+    let mut along = 0.0;
+    for pair in line.windows(2) {
+        along += haversine_distance(pair[0], pair[1]);
+        if along > from_m && along < to_m {
+            points.push(pair[1]);
+        }
+    }
+    points.extend(point_along_line(line, to_m));
+    points
+}
+
+// Polygon approximating a circle of `radius_m` around a (lon, lat) point
+pub fn circle(center: geo_types::Point, radius_m: f64, segments: usize) -> geo_types::Polygon {
+    let d_lat = (radius_m / EARTH_RADIUS_M).to_degrees();
+    let d_lon = d_lat / center.y().to_radians().cos();
+    let mut points: Vec<geo_types::Coord> = (0..segments)
+        .map(|index| {
+            let angle = std::f64::consts::TAU * index as f64 / segments as f64;
+            geo_types::coord! { x: center.x() + d_lon * angle.cos(), y: center.y() + d_lat * angle.sin() }
+        })
+        .collect();
+    points.push(points[0]);
+    geo_types::Polygon::new(geo_types::LineString::new(points), Vec::new())
+}
+
+// Equirectangular projection to metres around the mean latitude of the points it is fitted to,
+// for spatial indices. Distances are only exact along the mean latitude, so queries widen their
+// radius by the stretch of longitudes further from it and check candidates by great circle.
+#[derive(Debug, Clone, Copy)]
+pub struct LocalProjection {
+    scale: f64,
+    stretch: f64,
+}
+
+impl LocalProjection {
+    pub fn fit(points: &[geo_types::Point]) -> LocalProjection {
+        if points.is_empty() {
+            return LocalProjection {
+                scale: 1.0,
+                stretch: 1.0,
+            };
+        }
+        let mean_lat = points.iter().map(|point| point.y()).sum::<f64>() / points.len() as f64;
+        let scale = mean_lat.to_radians().cos();
+        let stretch = points
+            .iter()
+            .map(|point| scale / point.y().to_radians().cos().max(1e-6))
+            .fold(1.0, f64::max);
+        LocalProjection { scale, stretch }
+    }
+
+    pub fn project(&self, point: geo_types::Point) -> [f64; 2] {
+        let metres_per_degree = EARTH_RADIUS_M.to_radians();
+        [
+            point.x() * self.scale * metres_per_degree,
+            point.y() * metres_per_degree,
+        ]
+    }
+
+    // Projected radius that covers a great circle distance anywhere in the fitted area
+    pub fn radius(&self, distance_m: f64) -> f64 {
+        distance_m * self.stretch * 1.01
+    }
+}
+
+// All polygons of a GeoJSON file, whether a geometry, a feature or a feature collection
+pub fn read_polygons(path: &std::path::Path) -> Result<geo_types::MultiPolygon, String> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+    let geojson: geojson::GeoJson = contents
+        .parse()
+        .map_err(|error: geojson::Error| error.to_string())?;
+    let geometries: Vec<&geojson::Geometry> = match &geojson {
+        geojson::GeoJson::Geometry(geometry) => vec![geometry],
+        geojson::GeoJson::Feature(feature) => feature.geometry.iter().collect(),
+        geojson::GeoJson::FeatureCollection(collection) => collection
+            .features
+            .iter()
+            .filter_map(|feature| feature.geometry.as_ref())
+            .collect(),
+    };
+    let mut polygons: Vec<geo_types::Polygon> = Vec::new();
+    for geometry in geometries {
+        match geo_types::Geometry::try_from(&geometry.value).map_err(|error| error.to_string())? {
+            geo_types::Geometry::Polygon(polygon) => polygons.push(polygon),
+            geo_types::Geometry::MultiPolygon(multi) => polygons.extend(multi),
+            _ => {}
+        }
+    }
+    if polygons.is_empty() {
+        return Err(format!("No polygons in {}", path.display()));
+    }
+    Ok(geo_types::MultiPolygon::new(polygons))
+}
+
+// Shape points grouped by shape id, in shape_pt_sequence order
+pub fn shape_lines(shapes: &[Shape]) -> HashMap<String, Vec<geo_types::Point>> {
+    let mut sequences: HashMap<String, Vec<(u64, geo_types::Point)>> = HashMap::new();
+    for shape in shapes {
+        sequences
+            .entry(shape.shape_id.to_owned())
+            .or_default()
+            .push((
+                shape.shape_pt_sequence,
+                geo_types::Point::new(shape.shape_pt_lon, shape.shape_pt_lat),
+            ));
+    }
+
+    sequences
+        .into_iter()
+        .map(|(shape_id, mut points)| {
+            points.sort_by_key(|(sequence, _)| *sequence);
+            (
+                shape_id,
+                points.into_iter().map(|(_, point)| point).collect(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_haversine_distance() {
+    // One degree of latitude is about 111.2 km
+    let distance = haversine_distance(
+        geo_types::Point::new(8.0, 48.0),
+        geo_types::Point::new(8.0, 49.0),
+    );
+    assert!((distance - 111_195.0).abs() < 10.0);
+}
+
+#[test]
+fn test_project_onto_line() {
+    let line = [
+        geo_types::Point::new(8.0, 48.0),
+        geo_types::Point::new(8.0, 49.0),
+        geo_types::Point::new(9.0, 49.0),
+    ];
+    let (segment, along, offset) =
+        project_onto_line(&line, geo_types::Point::new(7.999, 48.5), 0).unwrap();
+    assert_eq!(segment, 0);
+    assert!((along - 111_195.0 / 2.0).abs() < 10.0);
+    assert!(offset > 50.0 && offset < 100.0);
+
+    // Skipping the first segment snaps to the start of the second one
+    let (segment, along, _) =
+        project_onto_line(&line, geo_types::Point::new(7.999, 48.5), 1).unwrap();
+    assert_eq!(segment, 1);
+    assert!((along - 111_195.0).abs() < 10.0);
+}
+
 #[test]
 fn test_from_stop() {
     let stop = Stop {
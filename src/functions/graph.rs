@@ -0,0 +1,334 @@
+use std::fmt::Write;
+#[cfg(test)]
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use petgraph::graph::DiGraph;
+use petgraph::visit::EdgeRef;
+use serde::Serialize;
+
+#[cfg(test)]
+use crate::objects::feed::Feed;
+#[cfg(test)]
+use crate::objects::network;
+use crate::objects::network::{Edge, Station};
+
+#[cfg(test)]
+use super::gtfs;
+use super::round;
+use super::visualize::svg::escape;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum GraphFormat {
+    Graphml,
+    Gexf,
+    Dot,
+    // One GeoJSON feature per line, nodes first
+    GeojsonLines,
+}
+
+pub fn export_graph(graph: &DiGraph<Station, Edge>, format: GraphFormat) -> Result<String, String> {
+    match format {
+        GraphFormat::Graphml => Ok(to_graphml(graph)),
+        GraphFormat::Gexf => Ok(to_gexf(graph)),
+        GraphFormat::Dot => Ok(to_dot(graph)),
+        GraphFormat::GeojsonLines => to_geojson_lines(graph),
+    }
+}
+
+// Attributes shared by all formats, as (key, type, value) with GraphML type names
+fn node_attributes(station: &Station) -> Vec<(&'static str, &'static str, String)> {
+    let mut attributes = vec![("name", "string", station.name.clone().unwrap_or_default())];
+    if let Some(point) = station.point {
+        attributes.push(("lon", "double", point.x().to_string()));
+        attributes.push(("lat", "double", point.y().to_string()));
+    }
+    attributes
+}
+
+fn edge_attributes(edge: &Edge) -> Vec<(&'static str, &'static str, String)> {
+    let mut attributes = vec![
+        ("trip_count", "int", edge.trip_count.to_string()),
+        (
+            "route_ids",
+            "string",
+            edge.route_ids
+                .iter()
+                .cloned()
+                .collect::<Vec<String>>()
+                .join(";"),
+        ),
+        ("min_run_time_s", "int", edge.min_run_time.to_string()),
+        (
+            "avg_run_time_s",
+            "double",
+            format!("{:.1}", edge.avg_run_time()),
+        ),
+    ];
+    if let Some(distance) = edge.distance_m {
+        attributes.push(("distance_m", "double", distance.to_string()));
+    }
+    attributes
+}
+
+const NODE_KEYS: [(&str, &str); 3] = [("name", "string"), ("lon", "double"), ("lat", "double")];
+const EDGE_KEYS: [(&str, &str); 5] = [
+    ("trip_count", "int"),
+    ("route_ids", "string"),
+    ("min_run_time_s", "int"),
+    ("avg_run_time_s", "double"),
+    ("distance_m", "double"),
+];
+
+fn to_graphml(graph: &DiGraph<Station, Edge>) -> String {
+    let mut output = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    output.push_str("\n<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+    for (key, key_type) in NODE_KEYS {
+        let _ = writeln!(
+            output,
+            r#"  <key id="{0}" for="node" attr.name="{0}" attr.type="{1}"/>"#,
+            key, key_type
+        );
+    }
+    for (key, key_type) in EDGE_KEYS {
+        let _ = writeln!(
+            output,
+            r#"  <key id="{0}" for="edge" attr.name="{0}" attr.type="{1}"/>"#,
+            key, key_type
+        );
+    }
+    output.push_str("  <graph id=\"network\" edgedefault=\"directed\">\n");
+    for node in graph.node_indices() {
+        let station = &graph[node];
+        let _ = writeln!(output, r#"    <node id="{}">"#, escape(&station.station_id));
+        for (key, _, value) in node_attributes(station) {
+            let _ = writeln!(
+                output,
+                r#"      <data key="{}">{}</data>"#,
+                key,
+                escape(&value)
+            );
+        }
+        output.push_str("    </node>\n");
+    }
+    for edge in graph.edge_references() {
+        let _ = writeln!(
+            output,
+            r#"    <edge source="{}" target="{}">"#,
+            escape(&graph[edge.source()].station_id),
+            escape(&graph[edge.target()].station_id)
+        );
+        for (key, _, value) in edge_attributes(edge.weight()) {
+            let _ = writeln!(
+                output,
+                r#"      <data key="{}">{}</data>"#,
+                key,
+                escape(&value)
+            );
+        }
+        output.push_str("    </edge>\n");
+    }
+    output.push_str("  </graph>\n</graphml>\n");
+    output
+}
+
+fn to_gexf(graph: &DiGraph<Station, Edge>) -> String {
+    let gexf_type = |key_type: &'static str| {
+        if key_type == "int" {
+            "integer"
+        } else {
+            key_type
+        }
+    };
+    let mut output = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    output.push_str("\n<gexf xmlns=\"http://gexf.net/1.3\" version=\"1.3\">\n");
+    output.push_str("  <graph defaultedgetype=\"directed\">\n    <attributes class=\"node\">\n");
+    for (key, key_type) in NODE_KEYS {
+        let _ = writeln!(
+            output,
+            r#"      <attribute id="{0}" title="{0}" type="{1}"/>"#,
+            key,
+            gexf_type(key_type)
+        );
+    }
+    output.push_str("    </attributes>\n    <attributes class=\"edge\">\n");
+    for (key, key_type) in EDGE_KEYS {
+        let _ = writeln!(
+            output,
+            r#"      <attribute id="{0}" title="{0}" type="{1}"/>"#,
+            key,
+            gexf_type(key_type)
+        );
+    }
+    output.push_str("    </attributes>\n    <nodes>\n");
+    for node in graph.node_indices() {
+        let station = &graph[node];
+        let label = station.name.as_deref().unwrap_or(&station.station_id);
+        let _ = writeln!(
+            output,
+            r#"      <node id="{}" label="{}">"#,
+            escape(&station.station_id),
+            escape(label)
+        );
+        write_gexf_values(&mut output, node_attributes(station));
+        output.push_str("      </node>\n");
+    }
+    output.push_str("    </nodes>\n    <edges>\n");
+    for edge in graph.edge_references() {
+        let _ = writeln!(
+            output,
+            r#"      <edge id="{}" source="{}" target="{}" weight="{}">"#,
+            edge.id().index(),
+            escape(&graph[edge.source()].station_id),
+            escape(&graph[edge.target()].station_id),
+            edge.weight().trip_count
+        );
+        write_gexf_values(&mut output, edge_attributes(edge.weight()));
+        output.push_str("      </edge>\n");
+    }
+    output.push_str("    </edges>\n  </graph>\n</gexf>\n");
+    output
+}
+
+fn write_gexf_values(output: &mut String, attributes: Vec<(&str, &str, String)>) {
+    output.push_str("        <attvalues>\n");
+    for (key, _, value) in attributes {
+        let _ = writeln!(
+            output,
+            r#"          <attvalue for="{}" value="{}"/>"#,
+            key,
+            escape(&value)
+        );
+    }
+    output.push_str("        </attvalues>\n");
+}
+
+fn to_dot(graph: &DiGraph<Station, Edge>) -> String {
+    let quote = |value: &str| format!("\"{}\"", value.replace('"', "\\\""));
+    let mut output = String::from("digraph {\n");
+    for node in graph.node_indices() {
+        let station = &graph[node];
+        let attributes: Vec<String> = node_attributes(station)
+            .into_iter()
+            .map(|(key, _, value)| format!("{} = {}", key, quote(&value)))
+            .collect();
+        let label = station.name.as_deref().unwrap_or(&station.station_id);
+        let _ = writeln!(
+            output,
+            "    {} [ label = {} {} ]",
+            quote(&station.station_id),
+            quote(label),
+            attributes.join(" ")
+        );
+    }
+    for edge in graph.edge_references() {
+        let attributes: Vec<String> = edge_attributes(edge.weight())
+            .into_iter()
+            .map(|(key, _, value)| format!("{} = {}", key, quote(&value)))
+            .collect();
+        let _ = writeln!(
+            output,
+            "    {} -> {} [ {} ]",
+            quote(&graph[edge.source()].station_id),
+            quote(&graph[edge.target()].station_id),
+            attributes.join(" ")
+        );
+    }
+    output.push_str("}\n");
+    output
+}
+
+#[derive(Serialize)]
+struct GraphFeature<'a> {
+    #[serde(serialize_with = "geojson::ser::serialize_geometry")]
+    geometry: geo_types::Geometry,
+    kind: &'a str,
+    id: &'a str,
+    name: Option<&'a str>,
+    target: Option<&'a str>,
+    trip_count: Option<usize>,
+    route_ids: Option<Vec<&'a str>>,
+    min_run_time_s: Option<u64>,
+    avg_run_time_s: Option<f64>,
+    distance_m: Option<f64>,
+}
+
+// Nodes and edges without coordinates can't be written as GeoJSON and are left out
+fn to_geojson_lines(graph: &DiGraph<Station, Edge>) -> Result<String, String> {
+    let mut features: Vec<GraphFeature> = Vec::new();
+    for node in graph.node_indices() {
+        let station = &graph[node];
+        let Some(point) = station.point else {
+            continue;
+        };
+        features.push(GraphFeature {
+            geometry: geo_types::Geometry::Point(point),
+            kind: "node",
+            id: &station.station_id,
+            name: station.name.as_deref(),
+            target: None,
+            trip_count: None,
+            route_ids: None,
+            min_run_time_s: None,
+            avg_run_time_s: None,
+            distance_m: None,
+        });
+    }
+    for edge in graph.edge_references() {
+        let (source, target) = (&graph[edge.source()], &graph[edge.target()]);
+        let (Some(from), Some(to)) = (source.point, target.point) else {
+            continue;
+        };
+        let weight = edge.weight();
+        features.push(GraphFeature {
+            geometry: geo_types::Geometry::LineString(geo_types::LineString::from(vec![from, to])),
+            kind: "edge",
+            id: &source.station_id,
+            name: None,
+            target: Some(&target.station_id),
+            trip_count: Some(weight.trip_count),
+            route_ids: Some(
+                weight
+                    .route_ids
+                    .iter()
+                    .map(|route_id| route_id.as_str())
+                    .collect(),
+            ),
+            min_run_time_s: Some(weight.min_run_time),
+            avg_run_time_s: Some(round(weight.avg_run_time(), 1)),
+            distance_m: weight.distance_m,
+        });
+    }
+
+    let mut output = String::new();
+    for feature in &features {
+        let line = geojson::ser::to_feature_string(feature).map_err(|error| error.to_string())?;
+        output.push_str(&line);
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+#[test]
+fn test_export_graph() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let feed = Feed::load(&mut gtfs_file);
+    let graph = network::network_graph(&feed, true);
+
+    let graphml = export_graph(&graph, GraphFormat::Graphml).unwrap();
+    assert_eq!(graphml.matches("<node ").count(), 9);
+    assert_eq!(graphml.matches("<edge ").count(), 15);
+    assert!(graphml.contains(r#"<data key="route_ids">AB</data>"#));
+
+    let gexf = export_graph(&graph, GraphFormat::Gexf).unwrap();
+    assert_eq!(gexf.matches("<edge ").count(), 15);
+
+    let dot = export_graph(&graph, GraphFormat::Dot).unwrap();
+    assert!(
+        dot.contains("\"BEATTY_AIRPORT\" -> \"BULLFROG\" [ trip_count = \"1\" route_ids = \"AB\"")
+    );
+
+    let lines = export_graph(&graph, GraphFormat::GeojsonLines).unwrap();
+    assert_eq!(lines.lines().count(), 24);
+}
@@ -1,7 +1,8 @@
 use core::{fmt, panic};
+use std::collections::HashSet;
 use std::fmt::Debug;
-use std::path::PathBuf;
-use std::{fs::File, usize};
+use std::fs::File;
+use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
@@ -13,6 +14,7 @@ use csv;
 
 pub use zip::read::ZipArchive;
 use zip::result::ZipError;
+use zip::write::{SimpleFileOptions, ZipWriter};
 
 pub struct GtfsFile {
     archive: ZipArchive<File>,
@@ -26,15 +28,16 @@ impl GtfsFile {
         let archive: ZipArchive<File> = ZipArchive::new(reader).unwrap();
         let mut gtfs_candidate = GtfsFile { archive };
         match gtfs_candidate.check_file_validity() {
-            Ok(_) => return Ok(gtfs_candidate),
+            Ok(_) => Ok(gtfs_candidate),
             Err(error) => {
                 println!("{}", error);
-                return Err(error);
+                Err(error)
             }
         }
     }
 
-    pub fn into_iter<T>(&mut self) -> Iter<T>
+    #[allow(clippy::wrong_self_convention)]
+    pub fn into_iter<T>(&mut self) -> Iter<'_, T>
     where
         T: for<'a> GtfsObject + for<'de> serde::Deserialize<'de>,
     {
@@ -67,6 +70,54 @@ impl GtfsFile {
         output
     }
 
+    // Like read_vec, but rows that fail to parse are returned as errors instead of panicking
+    pub fn read_vec_lossy<T>(&mut self) -> (Vec<T>, Vec<csv::Error>)
+    where
+        T: for<'a> GtfsObject + for<'de> serde::Deserialize<'de>,
+    {
+        let mut output: Vec<T> = vec![];
+        let mut errors: Vec<csv::Error> = vec![];
+        if !self.has_file::<T>() {
+            return (output, errors);
+        }
+        for result in self.into_iter() {
+            match result {
+                Ok(record) => output.push(record),
+                Err(error) => errors.push(error),
+            }
+        }
+        (output, errors)
+    }
+
+    // Number of data rows in a file of the archive, without parsing them
+    pub fn count_rows(&mut self, file_name: &str) -> usize {
+        match self.archive.by_name(file_name) {
+            Ok(file) => csv::ReaderBuilder::new()
+                .flexible(true)
+                .from_reader(file)
+                .records()
+                .count(),
+            Err(_) => 0,
+        }
+    }
+
+    pub fn has_file<T: GtfsObject>(&self) -> bool {
+        self.archive.index_for_name(T::FILE).is_some()
+    }
+
+    // Like read_vec, but an absent optional file reads as an empty table
+    pub fn read_optional_vec<T>(&mut self) -> Vec<T>
+    where
+        T: for<'a> GtfsObject + for<'de> serde::Deserialize<'de>,
+    {
+        if self.has_file::<T>() {
+            self.read_vec()
+        } else {
+            vec![]
+        }
+    }
+
+    #[allow(clippy::wrong_self_convention)]
     pub fn to_stdout<T>(&mut self)
     where
         T: for<'a> GtfsObject + for<'de> serde::Deserialize<'de> + for<'a> Debug,
@@ -91,18 +142,21 @@ impl GtfsFile {
     fn check_file_validity(&mut self) -> Result<bool, GtfsSpecError> {
         let files: Vec<String> = self.list_files();
 
-        if !files.contains(&String::from("agency.txt")) {
-            return Err(GtfsSpecError);
-        } else if !files.contains(&String::from("stops.txt")) {
-            return Err(GtfsSpecError);
-        } else if !files.contains(&String::from("routes.txt")) {
-            return Err(GtfsSpecError);
-        } else if !files.contains(&String::from("trips.txt")) {
-            return Err(GtfsSpecError);
-        } else if !files.contains(&String::from("stop_times.txt")) {
-            return Err(GtfsSpecError);
-        } else if !files.contains(&String::from("calendar.txt"))
-            || !files.contains(&String::from("calendar_dates.txt"))
+        let required = [
+            "agency.txt",
+            "stops.txt",
+            "routes.txt",
+            "trips.txt",
+            "stop_times.txt",
+        ];
+        for file in required {
+            if !files.contains(&String::from(file)) {
+                return Err(GtfsSpecError);
+            }
+        }
+        // Either calendar file may be left out when the other one describes all service
+        if !files.contains(&String::from("calendar.txt"))
+            && !files.contains(&String::from("calendar_dates.txt"))
         {
             return Err(GtfsSpecError);
         }
@@ -111,12 +165,73 @@ impl GtfsFile {
     }
 }
 
+// Writes tables into a new archive, one file per table
+pub struct GtfsWriter {
+    archive: ZipWriter<File>,
+    written: HashSet<String>,
+}
+
+impl GtfsWriter {
+    pub fn new(filepath: &Path) -> Result<Self, String> {
+        let file =
+            File::create(filepath).map_err(|error| format!("{}: {}", filepath.display(), error))?;
+        Ok(GtfsWriter {
+            archive: ZipWriter::new(file),
+            written: HashSet::new(),
+        })
+    }
+
+    // Empty optional tables are left out, but still count as written for copy_other_files
+    pub fn write_vec<T>(&mut self, records: &[T]) -> Result<(), String>
+    where
+        T: GtfsObject + Serialize,
+    {
+        self.written.insert(String::from(T::FILE));
+        if records.is_empty() && !T::REQUIRED {
+            return Ok(());
+        }
+        self.archive
+            .start_file(T::FILE, SimpleFileOptions::default())
+            .map_err(|error| error.to_string())?;
+        let mut writer = csv::Writer::from_writer(&mut self.archive);
+        for record in records {
+            writer
+                .serialize(record)
+                .map_err(|error| error.to_string())?;
+        }
+        writer.flush().map_err(|error| error.to_string())
+    }
+
+    // Copies the files of another feed that weren't written, such as fares, unchanged
+    pub fn copy_other_files(&mut self, source: &mut GtfsFile) -> Result<(), String> {
+        for index in 0..source.archive.len() {
+            let file = source
+                .archive
+                .by_index_raw(index)
+                .map_err(|error| error.to_string())?;
+            if self.written.contains(file.name()) {
+                continue;
+            }
+            self.written.insert(String::from(file.name()));
+            self.archive
+                .raw_copy_file(file)
+                .map_err(|error| error.to_string())?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<(), String> {
+        self.archive.finish().map_err(|error| error.to_string())?;
+        Ok(())
+    }
+}
+
 pub trait GtfsObject {
     const FILE: &'static str;
     const REQUIRED: bool;
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Agency {
     pub agency_id: Option<String>,
     pub agency_name: String,
@@ -134,7 +249,7 @@ impl GtfsObject for Agency {
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
-pub struct  Stop {
+pub struct Stop {
     pub stop_id: String,
     pub stop_code: Option<String>,
     pub stop_name: Option<String>,
@@ -157,7 +272,6 @@ impl GtfsObject for Stop {
     const REQUIRED: bool = true;
 }
 
-
 #[derive(Debug, Serialize_repr, Deserialize_repr, PartialEq, Clone)]
 #[repr(u8)]
 pub enum LocationType {
@@ -202,11 +316,28 @@ impl GtfsObject for Route {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(untagged)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum RouteType {
     Standard(StandardRouteType),
     HVT(HVTRouteType),
 }
 
+impl RouteType {
+    pub fn code(&self) -> u16 {
+        match self {
+            RouteType::Standard(route_type) => route_type.clone() as u16,
+            RouteType::HVT(route_type) => route_type.clone() as u16,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            RouteType::Standard(route_type) => format!("{:?}", route_type),
+            RouteType::HVT(route_type) => format!("{:?}", route_type),
+        }
+    }
+}
+
 #[derive(Debug, Serialize_repr, Deserialize_repr, Clone)]
 #[repr(u8)]
 pub enum StandardRouteType {
@@ -293,7 +424,7 @@ impl GtfsObject for Trip {
     const REQUIRED: bool = true;
 }
 
-#[derive(Debug, Serialize_repr, Deserialize_repr, Clone)]
+#[derive(Debug, Serialize_repr, Deserialize_repr, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum Direction {
     Outbound = 0,
@@ -319,7 +450,8 @@ pub struct StopTime {
     pub pickup_type: Option<PickupType>,
     pub drop_off_type: Option<PickupType>,
     pub continuous_pickup: Option<PickupType>,
-    pub contiuous_drop_off: Option<PickupType>,
+    pub continuous_drop_off: Option<PickupType>,
+    #[serde(rename = "shape_dist_traveled", alias = "shape_dist_travelled")]
     pub shape_dist_travelled: Option<f64>,
     pub timepoint: Option<TimepointType>,
 }
@@ -334,13 +466,32 @@ pub struct Time {
     pub s: u64,
 }
 
+impl Time {
+    pub fn seconds(&self) -> u64 {
+        self.h * 3600 + self.m * 60 + self.s
+    }
+
+    pub fn from_seconds(seconds: u64) -> Time {
+        Time {
+            h: seconds / 3600,
+            m: seconds % 3600 / 60,
+            s: seconds % 60,
+        }
+    }
+}
+
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:{:02}:{:02}", self.h, self.m, self.s)
+    }
+}
+
 impl Serialize for Time {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        let s = format!("{}:{}:{}", self.h, self.m, self.s);
-        serializer.serialize_str(&s)
+        serializer.serialize_str(&self.to_string())
     }
 }
 
@@ -377,7 +528,7 @@ impl GtfsObject for StopTime {
 }
 
 #[serde_as]
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Calendar {
     pub service_id: String,
     #[serde_as(as = "BoolFromInt")]
@@ -400,7 +551,7 @@ pub struct Calendar {
     pub end_date: NaiveDate,
 }
 
-mod date {
+pub mod date {
     use chrono::NaiveDate;
     use serde::{self, Deserialize, Deserializer, Serializer};
 
@@ -443,7 +594,7 @@ impl fmt::Debug for GtfsSpecError {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct CalendarDate {
     pub service_id: String,
     #[serde(with = "date")]
@@ -451,7 +602,7 @@ pub struct CalendarDate {
     pub exception_type: CalendarException,
 }
 
-#[derive(Debug, Serialize_repr, Deserialize_repr)]
+#[derive(Debug, Serialize_repr, Deserialize_repr, Clone, Copy, PartialEq)]
 #[repr(u8)]
 pub enum CalendarException {
     Added = 1,
@@ -598,6 +749,7 @@ pub enum DurationLimitType {
 
 #[derive(Debug, Serialize_repr, Deserialize_repr)]
 #[repr(u8)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum FareTransferType {
     // The fuck does this mean
     AAB = 0,
@@ -654,12 +806,13 @@ impl GtfsObject for RouteNetwork {
     const REQUIRED: bool = false;
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Shape {
     pub shape_id: String,
     pub shape_pt_lat: f64,
     pub shape_pt_lon: f64,
     pub shape_pt_sequence: u64,
+    #[serde(rename = "shape_dist_traveled", alias = "shape_dist_travelled")]
     pub shape_dist_travelled: Option<f64>,
 }
 
@@ -669,7 +822,7 @@ impl GtfsObject for Shape {
 }
 
 #[serde_as]
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Frequency {
     pub trip_id: String,
     pub start_time: Time,
@@ -684,7 +837,7 @@ impl GtfsObject for Frequency {
     const REQUIRED: bool = false;
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Transfer {
     pub from_stop_id: Option<String>,
     pub to_stop_id: Option<String>,
@@ -696,7 +849,7 @@ pub struct Transfer {
     pub min_transfer_time: Option<u64>,
 }
 
-#[derive(Debug, Serialize_repr, Deserialize_repr)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize_repr, Deserialize_repr)]
 #[repr(u8)]
 pub enum TransferType {
     Recommended = 0,
@@ -940,8 +1093,32 @@ fn test_new_gtfsfile_loading() {
 #[test]
 fn test_new_broken_gtfs() {
     let path: PathBuf = PathBuf::from("test_data/sample-feed-1-broken.zip");
-    let _gtfs_file = match GtfsFile::new(&path) {
+    match GtfsFile::new(&path) {
         Ok(_) => panic!("This gtfs file should have been rejected as invalid"),
         Err(error) => println!("correctly rejected invalid gtfs file, with error {}", error),
     };
 }
+
+#[test]
+fn test_calendar_dates_only_gtfs() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-calendar-dates.zip");
+    let mut gtfs_file = GtfsFile::new(&path).unwrap();
+    assert!(gtfs_file.read_optional_vec::<Calendar>().is_empty());
+    assert_eq!(gtfs_file.read_vec::<CalendarDate>().len(), 1);
+}
+
+#[test]
+fn test_continuous_drop_off_column() {
+    let data =
+        "trip_id,stop_id,stop_sequence,continuous_pickup,continuous_drop_off\nAB1,BULLFROG,2,1,3\n";
+    let mut reader = csv::Reader::from_reader(data.as_bytes());
+    let stop_time: StopTime = reader.deserialize().next().unwrap().unwrap();
+    assert!(matches!(
+        stop_time.continuous_pickup,
+        Some(PickupType::NoPickup)
+    ));
+    assert!(matches!(
+        stop_time.continuous_drop_off,
+        Some(PickupType::CoordinateWithDriver)
+    ));
+}
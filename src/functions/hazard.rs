@@ -0,0 +1,404 @@
+use std::collections::{HashMap, HashSet};
+#[cfg(test)]
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use geo::Intersects;
+use serde::Serialize;
+
+use crate::objects::feed::Feed;
+use crate::objects::pattern;
+use crate::objects::spatial::SpatialIndex;
+
+use super::geodata;
+#[cfg(test)]
+use super::gtfs;
+use super::gtfs::{StopTime, Trip};
+use super::round;
+use super::scenario::{self, ReplacedTrips};
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum HazardFormat {
+    Csv,
+    Json,
+    // Impacted stops as points
+    GeoJson,
+}
+
+// Trip instances of a date, and how many of them run through the hazard
+#[derive(Debug, Serialize)]
+pub struct DayImpact {
+    pub date: String,
+    pub trips: usize,
+    pub impacted_trips: usize,
+    pub impacted_pct: f64,
+}
+
+// Trip instances of a route over all dates, the impacted ones and those with nothing left to run
+#[derive(Debug, Serialize)]
+pub struct RouteImpact {
+    pub route_id: String,
+    pub route_name: String,
+    pub trips: usize,
+    pub impacted_trips: usize,
+    pub canceled_trips: usize,
+    pub flooded_stops: usize,
+}
+
+// Service at a stop on the first date with and without the hazard. Destinations are the stops
+// reachable without changing.
+#[derive(Debug, Serialize)]
+pub struct StopImpact {
+    pub stop_id: String,
+    pub stop_name: Option<String>,
+    pub flooded: bool,
+    pub departures_before: usize,
+    pub departures_after: usize,
+    pub routes_before: usize,
+    pub routes_after: usize,
+    pub destinations_before: usize,
+    pub destinations_after: usize,
+}
+
+pub struct HazardImpact {
+    pub flooded_stops: usize,
+    pub days: Vec<DayImpact>,
+    pub routes: Vec<RouteImpact>,
+    // Only stops with less service
+    pub stops: Vec<StopImpact>,
+    // The feed with trips cut where they call at flooded stops or run through the hazard, on the
+    // dates of the analysis. Flooded stops stay in stops.txt, as trips still call at them on other
+    // dates.
+    pub reduced: Feed,
+    pub replaced: ReplacedTrips,
+}
+
+// Whether the way between each call of a trip and the next runs through the polygons, along the
+// trip's shape where it crosses them at all, or else straight between the stops
+fn crossings(
+    trip: &Trip,
+    stop_times: &[StopTime],
+    polygons: &geo_types::MultiPolygon,
+    stop_points: &HashMap<&str, geo_types::Point>,
+    crossing_shapes: &HashMap<String, Vec<geo_types::Point>>,
+) -> Vec<bool> {
+    let points: Vec<Option<geo_types::Point>> = stop_times
+        .iter()
+        .map(|stop_time| stop_points.get(stop_time.stop_id.as_deref()?).copied())
+        .collect();
+    let line = trip
+        .shape_id
+        .as_ref()
+        .and_then(|shape_id| crossing_shapes.get(shape_id));
+    if trip.shape_id.is_some() && line.is_none() {
+        return vec![false; stop_times.len().saturating_sub(1)];
+    }
+
+    let mut segment = 0;
+    let distances: Vec<Option<f64>> = points
+        .iter()
+        .map(|point| {
+            let (found, along, _) = geodata::project_onto_line(line?, (*point)?, segment)?;
+            segment = found;
+            Some(along)
+        })
+        .collect();
+    (0..stop_times.len().saturating_sub(1))
+        .map(|index| {
+            let way: Vec<geo_types::Point> = match (line, distances[index], distances[index + 1]) {
+                (Some(line), Some(from), Some(to)) => geodata::sub_line(line, from, to),
+                _ => points[index..=index + 1]
+                    .iter()
+                    .flatten()
+                    .copied()
+                    .collect(),
+            };
+            way.len() >= 2 && geo_types::LineString::from(way).intersects(polygons)
+        })
+        .collect()
+}
+
+// Routes and one-seat destinations of every stop, and its departures, on a date. Destinations
+// reached with a transfer are not counted.
+fn stop_service(
+    feed: &Feed,
+    date: NaiveDate,
+) -> HashMap<String, (usize, HashSet<String>, HashSet<String>)> {
+    let mut service: HashMap<String, (usize, HashSet<String>, HashSet<String>)> = HashMap::new();
+    for instance in feed.trip_instances(&feed.active_services(date)) {
+        for (index, stop) in instance
+            .stops
+            .iter()
+            .enumerate()
+            .take(instance.stops.len().saturating_sub(1))
+        {
+            let (departures, routes, destinations) =
+                service.entry(stop.stop_id.to_owned()).or_default();
+            *departures += 1;
+            routes.insert(instance.trip.route_id.to_owned());
+            destinations.extend(
+                instance.stops[index + 1..]
+                    .iter()
+                    .map(|stop| stop.stop_id.to_owned()),
+            );
+        }
+    }
+    service
+}
+
+// Stops inside the polygons, and trips calling at them or running through the polygons between
+// two stops, on the dates from `from` to `to`
+pub fn hazard_impact(
+    feed: &Feed,
+    polygons: &geo_types::MultiPolygon,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> HazardImpact {
+    let index = SpatialIndex::new(&feed.stops, &feed.shapes);
+    let flooded: HashSet<&str> = index
+        .stops_within_polygon(polygons)
+        .into_iter()
+        .map(|stop| feed.stops[stop.index].stop_id.as_str())
+        .collect();
+    let mut shape_lines = geodata::shape_lines(&feed.shapes);
+    let crossing: HashSet<String> = index
+        .shapes_within_polygon(polygons)
+        .into_iter()
+        .map(|shape| shape.shape_id)
+        .collect();
+    shape_lines.retain(|shape_id, _| crossing.contains(shape_id));
+    let stop_points: HashMap<&str, geo_types::Point> = feed
+        .stops
+        .iter()
+        .filter_map(|stop| Some((stop.stop_id.as_str(), geodata::stop_point(stop)?)))
+        .collect();
+
+    let mut impacted: HashSet<String> = HashSet::new();
+    let mut canceled: HashSet<String> = HashSet::new();
+    let (reduced, replaced) =
+        scenario::replace_trips(feed, from, to, "hazard", |trip, stop_times| {
+            let removed: Vec<bool> = stop_times
+                .iter()
+                .map(|stop_time| {
+                    stop_time
+                        .stop_id
+                        .as_deref()
+                        .is_some_and(|stop_id| flooded.contains(stop_id))
+                })
+                .collect();
+            let splits: HashSet<usize> =
+                crossings(trip, stop_times, polygons, &stop_points, &shape_lines)
+                    .into_iter()
+                    .enumerate()
+                    .filter_map(|(index, crosses)| crosses.then_some(index + 1))
+                    .collect();
+            if !removed.contains(&true) && splits.is_empty() {
+                return None;
+            }
+            impacted.insert(trip.trip_id.to_owned());
+            let parts = scenario::cut_trip(stop_times, &removed, &splits);
+            if parts.is_empty() {
+                canceled.insert(trip.trip_id.to_owned());
+            }
+            Some(parts)
+        });
+
+    let mut days: Vec<DayImpact> = Vec::new();
+    let mut route_trips: HashMap<String, (usize, usize, usize)> = HashMap::new();
+    for date in from.iter_days().take_while(|date| *date <= to) {
+        let instances = feed.trip_instances(&feed.active_services(date));
+        let mut impacted_trips = 0;
+        for instance in &instances {
+            let trip = &instance.trip;
+            let (trips, impacted_route_trips, canceled_trips) =
+                route_trips.entry(trip.route_id.to_owned()).or_default();
+            *trips += 1;
+            if impacted.contains(&trip.trip_id) {
+                impacted_trips += 1;
+                *impacted_route_trips += 1;
+                *canceled_trips += canceled.contains(&trip.trip_id) as usize;
+            }
+        }
+        days.push(DayImpact {
+            date: date.format("%Y%m%d").to_string(),
+            trips: instances.len(),
+            impacted_trips,
+            impacted_pct: match instances.len() {
+                0 => 0.0,
+                trips => round(100.0 * impacted_trips as f64 / trips as f64, 2),
+            },
+        });
+    }
+
+    let route_of: HashMap<&str, &str> = feed
+        .trips
+        .iter()
+        .map(|trip| (trip.trip_id.as_str(), trip.route_id.as_str()))
+        .collect();
+    let mut route_stops: HashMap<&str, HashSet<&str>> = HashMap::new();
+    for stop_time in &feed.stop_times {
+        if let (Some(route_id), Some(stop_id)) = (
+            route_of.get(stop_time.trip_id.as_str()),
+            stop_time.stop_id.as_deref(),
+        ) {
+            if flooded.contains(stop_id) {
+                route_stops.entry(route_id).or_default().insert(stop_id);
+            }
+        }
+    }
+    let routes: Vec<RouteImpact> = feed
+        .routes
+        .iter()
+        .filter_map(|route| {
+            let (trips, impacted_trips, canceled_trips) = route_trips
+                .get(&route.route_id)
+                .copied()
+                .unwrap_or_default();
+            let flooded_stops = route_stops
+                .get(route.route_id.as_str())
+                .map_or(0, HashSet::len);
+            (impacted_trips > 0 || flooded_stops > 0).then(|| RouteImpact {
+                route_id: route.route_id.to_owned(),
+                route_name: pattern::route_name(route).to_owned(),
+                trips,
+                impacted_trips,
+                canceled_trips,
+                flooded_stops,
+            })
+        })
+        .collect();
+
+    let before = stop_service(feed, from);
+    let after = stop_service(&reduced, from);
+    let empty = (0, HashSet::new(), HashSet::new());
+    let stops: Vec<StopImpact> = feed
+        .stops
+        .iter()
+        .filter_map(|stop| {
+            let (departures_before, routes_before, destinations_before) =
+                before.get(&stop.stop_id).unwrap_or(&empty);
+            let (departures_after, routes_after, destinations_after) =
+                after.get(&stop.stop_id).unwrap_or(&empty);
+            let is_flooded = flooded.contains(stop.stop_id.as_str());
+            let changed = departures_after < departures_before
+                || destinations_after.len() < destinations_before.len();
+            (is_flooded || changed).then(|| StopImpact {
+                stop_id: stop.stop_id.to_owned(),
+                stop_name: stop.stop_name.clone(),
+                flooded: is_flooded,
+                departures_before: *departures_before,
+                departures_after: *departures_after,
+                routes_before: routes_before.len(),
+                routes_after: routes_after.len(),
+                destinations_before: destinations_before.len(),
+                destinations_after: destinations_after.len(),
+            })
+        })
+        .collect();
+
+    HazardImpact {
+        flooded_stops: flooded.len(),
+        days,
+        routes,
+        stops,
+        reduced,
+        replaced,
+    }
+}
+
+#[derive(Serialize)]
+struct StopImpactJson<'a> {
+    #[serde(serialize_with = "geojson::ser::serialize_geometry")]
+    geometry: geo_types::Point,
+    #[serde(flatten)]
+    impact: &'a StopImpact,
+}
+
+// Impacted stops as GeoJSON points, leaving out stops without coordinates
+pub fn stop_impacts_geojson(feed: &Feed, stops: &[StopImpact]) -> Result<String, String> {
+    let points: HashMap<&str, geo_types::Point> = feed
+        .stops
+        .iter()
+        .filter_map(|stop| Some((stop.stop_id.as_str(), geodata::stop_point(stop)?)))
+        .collect();
+    let features: Vec<StopImpactJson> = stops
+        .iter()
+        .filter_map(|impact| {
+            Some(StopImpactJson {
+                geometry: *points.get(impact.stop_id.as_str())?,
+                impact,
+            })
+        })
+        .collect();
+
+    super::to_geojson_string(&features)
+}
+
+#[test]
+fn test_hazard_impact() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let feed = Feed::load(&mut gtfs_file);
+    let date = NaiveDate::from_ymd_opt(2007, 6, 5).unwrap();
+
+    // Around North Ave / D Ave, and across the road from the airport to Bullfrog
+    let square = |west: f64, south: f64, east: f64, north: f64| {
+        geo_types::Polygon::new(
+            geo_types::LineString::from(vec![
+                (west, south),
+                (east, south),
+                (east, north),
+                (west, north),
+                (west, south),
+            ]),
+            Vec::new(),
+        )
+    };
+    let polygons = geo_types::MultiPolygon::new(vec![
+        square(-116.769, 36.914, -116.7675, 36.9155),
+        square(-116.803, 36.86, -116.8, 36.89),
+    ]);
+    let impact = hazard_impact(&feed, &polygons, date, date);
+    assert_eq!(impact.flooded_stops, 1);
+    assert_eq!((impact.replaced.split, impact.replaced.canceled), (2, 2));
+
+    let route = |route_id: &str| {
+        impact
+            .routes
+            .iter()
+            .find(|route| route.route_id == route_id)
+    };
+    let city = route("CITY").unwrap();
+    assert_eq!(
+        (city.impacted_trips, city.canceled_trips, city.flooded_stops),
+        (city.trips, 0, 1)
+    );
+    let ab = route("AB").unwrap();
+    assert_eq!((ab.trips, ab.impacted_trips, ab.canceled_trips), (2, 2, 2));
+    assert!(route("BFC").is_none());
+    assert_eq!(impact.days[0].impacted_trips, city.trips + 2);
+
+    let stop = |stop_id: &str| {
+        impact
+            .stops
+            .iter()
+            .find(|stop| stop.stop_id == stop_id)
+            .unwrap()
+    };
+    assert!(stop("NADAV").flooded);
+    assert_eq!(stop("NADAV").departures_after, 0);
+    // Only the shuttle and the city loop as far as North Ave are left from the hotel
+    let stagecoach = stop("STAGECOACH");
+    assert_eq!(
+        (
+            stagecoach.destinations_before,
+            stagecoach.destinations_after
+        ),
+        (5, 2)
+    );
+    assert_eq!((stagecoach.routes_before, stagecoach.routes_after), (2, 2));
+    assert!(stop_impacts_geojson(&feed, &impact.stops)
+        .unwrap()
+        .contains("\"flooded\":true"));
+}
@@ -0,0 +1,225 @@
+#[cfg(test)]
+use std::path::PathBuf;
+
+use geo::BooleanOps;
+use serde::Serialize;
+
+#[cfg(test)]
+use chrono::NaiveDate;
+
+#[cfg(test)]
+use crate::objects::feed::Feed;
+
+use super::geodata;
+#[cfg(test)]
+use super::gtfs;
+use super::routing::{self, Place, Timetable, Window};
+
+#[derive(Debug, Serialize)]
+pub struct ReachedStop {
+    pub stop_id: String,
+    pub stop_name: Option<String>,
+    // Fastest of the departures in the window
+    pub min_travel_time_min: f64,
+    // At the chosen percentile of the departures in the window
+    pub travel_time_min: f64,
+    #[serde(skip)]
+    point: Option<geo_types::Point>,
+    #[serde(skip)]
+    travel_time: u64,
+}
+
+pub struct Isochrone {
+    // Where the origin is a coordinate, walking straight from it counts too
+    origin: Option<geo_types::Point>,
+    pub stops: Vec<ReachedStop>,
+}
+
+// Travel times from a place to every stop reached at the given percentile of departures in the
+// window. None if the place is an unknown stop.
+pub fn isochrone(
+    timetable: &Timetable,
+    from: &Place,
+    window: Window,
+    max_transfers: usize,
+    percentile: f64,
+) -> Option<Isochrone> {
+    let origin = timetable.access(from)?;
+    let times = timetable.window_travel_times(&origin, window, max_transfers + 1);
+
+    let mut stops: Vec<ReachedStop> = Vec::new();
+    for (stop, stop_id) in timetable.stop_ids.iter().enumerate() {
+        let stop_times: Vec<Option<u64>> = times.iter().map(|times| times[stop]).collect();
+        let Some(travel_time) = routing::percentile(&stop_times, percentile) else {
+            continue;
+        };
+        let fastest = stop_times
+            .iter()
+            .flatten()
+            .min()
+            .copied()
+            .unwrap_or(travel_time);
+        stops.push(ReachedStop {
+            stop_id: stop_id.to_owned(),
+            stop_name: timetable.stop_names[stop].clone(),
+            min_travel_time_min: fastest as f64 / 60.0,
+            travel_time_min: travel_time as f64 / 60.0,
+            point: timetable.stop_points[stop],
+            travel_time,
+        });
+    }
+    stops.sort_by(|a, b| {
+        a.travel_time
+            .cmp(&b.travel_time)
+            .then_with(|| a.stop_id.cmp(&b.stop_id))
+    });
+
+    let origin = match from {
+        Place::Coordinate(point) => Some(*point),
+        Place::Stop(_) => None,
+    };
+    Some(Isochrone { origin, stops })
+}
+
+#[derive(Serialize)]
+struct ReachedStopJson<'a> {
+    #[serde(serialize_with = "geojson::ser::serialize_geometry")]
+    geometry: geo_types::Point,
+    #[serde(flatten)]
+    stop: &'a ReachedStop,
+}
+
+// Reached stops as GeoJSON points, leaving out stops without coordinates
+pub fn reached_stops_geojson(isochrone: &Isochrone) -> Result<String, String> {
+    let features: Vec<ReachedStopJson> = isochrone
+        .stops
+        .iter()
+        .filter_map(|stop| {
+            Some(ReachedStopJson {
+                geometry: stop.point?,
+                stop,
+            })
+        })
+        .collect();
+
+    super::to_geojson_string(&features)
+}
+
+#[derive(Serialize)]
+struct BandJson {
+    #[serde(serialize_with = "geojson::ser::serialize_geometry")]
+    geometry: geo_types::MultiPolygon,
+    minutes: u64,
+}
+
+// The area within each band's minutes, walking on from every reached stop for the time left
+// and no further than the walking distance
+pub fn isochrone_bands(
+    isochrone: &Isochrone,
+    walk: routing::WalkSettings,
+    minutes: &[u64],
+) -> Vec<(u64, geo_types::MultiPolygon)> {
+    let mut minutes = minutes.to_vec();
+    minutes.sort_unstable();
+    minutes.dedup();
+
+    minutes
+        .into_iter()
+        .map(|band| {
+            let cutoff = band * 60;
+            let radius = |left: u64| (left as f64 * walk.speed_m_s).min(walk.max_distance_m);
+            let mut circles: Vec<geo_types::Polygon> = isochrone
+                .stops
+                .iter()
+                .filter(|stop| stop.travel_time < cutoff)
+                .filter_map(|stop| {
+                    Some(geodata::circle(
+                        stop.point?,
+                        radius(cutoff - stop.travel_time),
+                        32,
+                    ))
+                })
+                .collect();
+            if let Some(origin) = isochrone.origin {
+                circles.push(geodata::circle(origin, radius(cutoff), 32));
+            }
+            (band, union(circles))
+        })
+        .collect()
+}
+
+// Pairwise, so polygons being merged stay about the same size
+fn union(polygons: Vec<geo_types::Polygon>) -> geo_types::MultiPolygon {
+    let mut parts: Vec<geo_types::MultiPolygon> = polygons
+        .into_iter()
+        .map(|polygon| geo_types::MultiPolygon::new(vec![polygon]))
+        .collect();
+    while parts.len() > 1 {
+        parts = parts
+            .chunks(2)
+            .map(|pair| match pair {
+                [a, b] => a.union(b),
+                _ => pair[0].clone(),
+            })
+            .collect();
+    }
+    parts
+        .pop()
+        .unwrap_or_else(|| geo_types::MultiPolygon::new(Vec::new()))
+}
+
+// Bands as GeoJSON polygons, the widest first so that narrower ones are drawn on top
+pub fn bands_geojson(bands: Vec<(u64, geo_types::MultiPolygon)>) -> Result<String, String> {
+    let mut features: Vec<BandJson> = bands
+        .into_iter()
+        .map(|(minutes, geometry)| BandJson { geometry, minutes })
+        .collect();
+    features.sort_by_key(|band| std::cmp::Reverse(band.minutes));
+
+    super::to_geojson_string(&features)
+}
+
+#[test]
+fn test_isochrone() {
+    use geo::Contains;
+
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let feed = Feed::load(&mut gtfs_file);
+    let timetable = Timetable::new(
+        &feed,
+        NaiveDate::from_ymd_opt(2007, 6, 5).unwrap(),
+        routing::WalkSettings::default(),
+    );
+    let window = Window {
+        start: 6 * 3600,
+        end: 6 * 3600 + 30 * 60,
+        step: 60,
+    };
+
+    let from = Place::Stop(String::from("STAGECOACH"));
+    let isochrone = isochrone(&timetable, &from, window, 0, 50.0).unwrap();
+    assert_eq!(isochrone.stops[0].stop_id, "STAGECOACH");
+    let airport = isochrone
+        .stops
+        .iter()
+        .find(|stop| stop.stop_id == "BEATTY_AIRPORT")
+        .unwrap();
+    assert_eq!(airport.min_travel_time_min, 20.0);
+    assert_eq!(airport.travel_time_min, 34.0);
+    assert_eq!(
+        reached_stops_geojson(&isochrone)
+            .unwrap()
+            .matches("\"Feature\"")
+            .count(),
+        isochrone.stops.len()
+    );
+
+    let bands = isochrone_bands(&isochrone, timetable.walk, &[45, 15, 30]);
+    let minutes: Vec<u64> = bands.iter().map(|(minutes, _)| *minutes).collect();
+    assert_eq!(minutes, vec![15, 30, 45]);
+    let airport_point = geo_types::Point::new(-116.784582, 36.868446);
+    assert!(!bands[0].1.contains(&airport_point));
+    assert!(bands[2].1.contains(&airport_point));
+    assert!(bands_geojson(bands).unwrap().contains("\"minutes\":45"));
+}
@@ -0,0 +1,341 @@
+use std::path::Path;
+#[cfg(test)]
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use geo::Centroid;
+use rayon::prelude::*;
+use serde::Serialize;
+
+#[cfg(test)]
+use chrono::NaiveDate;
+
+#[cfg(test)]
+use crate::objects::feed::Feed;
+
+use super::geodata;
+#[cfg(test)]
+use super::gtfs;
+use super::routing::{self, Place, Timetable, Window};
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum MatrixFormat {
+    // One row per origin, destination and percentile
+    Csv,
+    // Minutes as little endian u16 after a header, see matrix_binary
+    Binary,
+}
+
+// An origin or destination, either a stop or a point standing for a zone
+#[derive(Debug, Clone)]
+pub struct Location {
+    pub id: String,
+    place: Place,
+    point: Option<geo_types::Point>,
+}
+
+// Every stop of the timetable served by a trip
+pub fn stop_locations(timetable: &Timetable) -> Vec<Location> {
+    timetable
+        .stop_ids
+        .iter()
+        .enumerate()
+        .filter(|(stop, _)| timetable.is_served(*stop))
+        .map(|(stop, stop_id)| Location {
+            id: stop_id.to_owned(),
+            place: Place::Stop(stop_id.to_owned()),
+            point: timetable.stop_points[stop],
+        })
+        .collect()
+}
+
+// Locations from GeoJSON features, polygons standing for zones at their centroid, or from a CSV
+// file with an id, lat and lon column
+pub fn read_locations(path: &Path) -> Result<Vec<Location>, String> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+    let is_geojson = path
+        .extension()
+        .is_some_and(|extension| extension == "geojson" || extension == "json");
+    if is_geojson {
+        geojson_locations(&contents)
+    } else {
+        csv_locations(&contents)
+    }
+}
+
+fn location(id: String, point: geo_types::Point) -> Location {
+    Location {
+        id,
+        place: Place::Coordinate(point),
+        point: Some(point),
+    }
+}
+
+fn csv_locations(contents: &str) -> Result<Vec<Location>, String> {
+    let mut reader = csv::Reader::from_reader(contents.as_bytes());
+    let headers = reader.headers().map_err(|error| error.to_string())?.clone();
+    let column = |names: &[&str]| {
+        headers
+            .iter()
+            .position(|header| names.contains(&header.trim().to_lowercase().as_str()))
+            .ok_or_else(|| format!("No {} column in {:?}", names[0], headers))
+    };
+    let id = column(&["id", "stop_id", "zone_id", "name"])?;
+    let lat = column(&["lat", "latitude", "stop_lat", "y"])?;
+    let lon = column(&["lon", "lng", "longitude", "stop_lon", "x"])?;
+
+    let mut locations: Vec<Location> = Vec::new();
+    for (row, record) in reader.records().enumerate() {
+        let record = record.map_err(|error| error.to_string())?;
+        let coordinate = |index: usize| {
+            record
+                .get(index)
+                .and_then(|value| value.trim().parse::<f64>().ok())
+        };
+        let (Some(lat), Some(lon)) = (coordinate(lat), coordinate(lon)) else {
+            return Err(format!("Row {} has no valid coordinates", row + 1));
+        };
+        locations.push(location(
+            record[id].to_owned(),
+            geo_types::Point::new(lon, lat),
+        ));
+    }
+    Ok(locations)
+}
+
+fn geojson_locations(contents: &str) -> Result<Vec<Location>, String> {
+    let collection: geojson::FeatureCollection = contents
+        .parse()
+        .map_err(|error: geojson::Error| error.to_string())?;
+    let mut locations: Vec<Location> = Vec::new();
+    for (index, feature) in collection.features.into_iter().enumerate() {
+        let id = match (feature.property("id"), &feature.id) {
+            (Some(serde_json::Value::String(id)), _) => id.to_owned(),
+            (Some(id), _) => id.to_string(),
+            (None, Some(geojson::feature::Id::String(id))) => id.to_owned(),
+            (None, Some(geojson::feature::Id::Number(id))) => id.to_string(),
+            (None, None) => index.to_string(),
+        };
+        let geometry: Option<geo_types::Geometry> = feature
+            .geometry
+            .and_then(|geometry| geo_types::Geometry::try_from(geometry).ok());
+        let Some(point) = geometry.and_then(|geometry| geometry.centroid()) else {
+            return Err(format!("Feature {} has no geometry", id));
+        };
+        locations.push(location(id, point));
+    }
+    Ok(locations)
+}
+
+// Travel times between every origin and destination at each percentile of the departures in
+// the window, indexed by percentile, then origin, then destination
+pub struct Matrix {
+    pub origin_ids: Vec<String>,
+    pub destination_ids: Vec<String>,
+    pub percentiles: Vec<f64>,
+    times: Vec<Option<u64>>,
+}
+
+impl Matrix {
+    pub fn get(&self, percentile: usize, origin: usize, destination: usize) -> Option<u64> {
+        let (origins, destinations) = (self.origin_ids.len(), self.destination_ids.len());
+        self.times[(percentile * origins + origin) * destinations + destination]
+    }
+}
+
+// Origins are searched in parallel. Locations that are unknown stops are never reached.
+pub fn travel_time_matrix(
+    timetable: &Timetable,
+    origins: &[Location],
+    destinations: &[Location],
+    window: Window,
+    max_transfers: usize,
+    percentiles: &[f64],
+) -> Matrix {
+    let egress: Vec<Vec<(usize, u64)>> = destinations
+        .iter()
+        .map(|destination| timetable.access(&destination.place).unwrap_or_default())
+        .collect();
+    let departures = window.departures().count();
+
+    // Per origin, the percentiles for every destination
+    let rows: Vec<Vec<Vec<Option<u64>>>> = origins
+        .par_iter()
+        .map(|origin| {
+            let access = timetable.access(&origin.place).unwrap_or_default();
+            let times = if access.is_empty() {
+                vec![vec![None; timetable.stop_ids.len()]; departures]
+            } else {
+                timetable.window_travel_times(&access, window, max_transfers + 1)
+            };
+            destinations
+                .iter()
+                .zip(&egress)
+                .map(|(destination, egress)| {
+                    // Walking all the way, where the two are close enough
+                    let walk = match (origin.point, destination.point) {
+                        (Some(a), Some(b)) => {
+                            let distance = geodata::haversine_distance(a, b);
+                            (distance <= timetable.walk.max_distance_m)
+                                .then(|| timetable.walk.seconds(distance))
+                        }
+                        _ => None,
+                    };
+                    let by_departure: Vec<Option<u64>> = times
+                        .iter()
+                        .map(|times| {
+                            egress
+                                .iter()
+                                .filter_map(|(stop, egress)| Some(times[*stop]? + egress))
+                                .chain(walk)
+                                .min()
+                        })
+                        .collect();
+                    percentiles
+                        .iter()
+                        .map(|percentile| routing::percentile(&by_departure, *percentile))
+                        .collect()
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut times: Vec<Option<u64>> =
+        Vec::with_capacity(percentiles.len() * origins.len() * destinations.len());
+    for percentile in 0..percentiles.len() {
+        for row in &rows {
+            times.extend(row.iter().map(|cell| cell[percentile]));
+        }
+    }
+    Matrix {
+        origin_ids: origins.iter().map(|origin| origin.id.to_owned()).collect(),
+        destination_ids: destinations
+            .iter()
+            .map(|destination| destination.id.to_owned())
+            .collect(),
+        percentiles: percentiles.to_vec(),
+        times,
+    }
+}
+
+#[derive(Serialize)]
+struct MatrixRow<'a> {
+    origin_id: &'a str,
+    destination_id: &'a str,
+    percentile: f64,
+    // Empty where the destination isn't reached
+    travel_time_min: Option<f64>,
+}
+
+pub fn matrix_csv(matrix: &Matrix) -> String {
+    let mut rows: Vec<MatrixRow> = Vec::new();
+    for (origin, origin_id) in matrix.origin_ids.iter().enumerate() {
+        for (destination, destination_id) in matrix.destination_ids.iter().enumerate() {
+            for (index, percentile) in matrix.percentiles.iter().enumerate() {
+                rows.push(MatrixRow {
+                    origin_id,
+                    destination_id,
+                    percentile: *percentile,
+                    travel_time_min: matrix
+                        .get(index, origin, destination)
+                        .map(|time| time as f64 / 60.0),
+                });
+            }
+        }
+    }
+    super::to_csv_string(&rows)
+}
+
+// The magic bytes GTFSMTX1, the number of origins, destinations and percentiles as u32, the
+// percentiles as f32, then the origin and destination ids each as a u32 byte length and UTF-8.
+// Travel times follow as whole minutes in u16 by percentile, origin and destination, with
+// u16::MAX where the destination isn't reached. All numbers are little endian.
+pub fn matrix_binary(matrix: &Matrix) -> Vec<u8> {
+    let mut output: Vec<u8> = b"GTFSMTX1".to_vec();
+    for count in [
+        matrix.origin_ids.len(),
+        matrix.destination_ids.len(),
+        matrix.percentiles.len(),
+    ] {
+        output.extend((count as u32).to_le_bytes());
+    }
+    for percentile in &matrix.percentiles {
+        output.extend((*percentile as f32).to_le_bytes());
+    }
+    for id in matrix.origin_ids.iter().chain(&matrix.destination_ids) {
+        output.extend((id.len() as u32).to_le_bytes());
+        output.extend(id.as_bytes());
+    }
+    for time in &matrix.times {
+        let minutes = time.map_or(u16::MAX, |time| {
+            ((time + 30) / 60).min(u16::MAX as u64 - 1) as u16
+        });
+        output.extend(minutes.to_le_bytes());
+    }
+    output
+}
+
+#[test]
+fn test_travel_time_matrix() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let feed = Feed::load(&mut gtfs_file);
+    let timetable = Timetable::new(
+        &feed,
+        NaiveDate::from_ymd_opt(2007, 6, 5).unwrap(),
+        routing::WalkSettings::default(),
+    );
+    let window = Window {
+        start: 6 * 3600,
+        end: 6 * 3600 + 30 * 60,
+        step: 60,
+    };
+
+    let origins = csv_locations("id,lat,lon\nhotel,36.9160,-116.7525\nnowhere,0,0\n").unwrap();
+    let destinations: Vec<Location> = stop_locations(&timetable);
+    let matrix = travel_time_matrix(&timetable, &origins, &destinations, window, 0, &[0.0, 50.0]);
+    let airport = matrix
+        .destination_ids
+        .iter()
+        .position(|id| id == "BEATTY_AIRPORT")
+        .unwrap();
+    let stagecoach = matrix
+        .destination_ids
+        .iter()
+        .position(|id| id == "STAGECOACH")
+        .unwrap();
+    // A minute's walk to the hotel, so the best is leaving two minutes before the 06:30 shuttle
+    assert_eq!(matrix.get(0, 0, airport), Some(22 * 60));
+    assert!(matrix.get(1, 0, airport).unwrap() > 30 * 60);
+    assert!(matrix.get(0, 0, stagecoach).unwrap() < 2 * 60);
+    assert_eq!(matrix.get(0, 1, airport), None);
+
+    let csv = matrix_csv(&matrix);
+    assert_eq!(csv.lines().count(), 1 + 2 * destinations.len() * 2);
+    assert!(csv.contains("nowhere,BEATTY_AIRPORT,0.0,\n"));
+
+    let binary = matrix_binary(&matrix);
+    let ids: usize = matrix
+        .origin_ids
+        .iter()
+        .chain(&matrix.destination_ids)
+        .map(|id| 4 + id.len())
+        .sum();
+    assert_eq!(
+        binary.len(),
+        8 + 12 + 2 * 4 + ids + 2 * 2 * 2 * destinations.len()
+    );
+}
+
+#[test]
+fn test_geojson_locations() {
+    let geojson = r#"{"type":"FeatureCollection","features":[
+        {"type":"Feature","id":7,"properties":{},"geometry":{"type":"Point","coordinates":[9.0,49.0]}},
+        {"type":"Feature","properties":{"id":"zone"},"geometry":{"type":"Polygon","coordinates":[[[0,0],[2,0],[2,2],[0,2],[0,0]]]}}
+    ]}"#;
+    let locations = geojson_locations(geojson).unwrap();
+    assert_eq!(locations[0].id, "7");
+    assert_eq!(locations[1].id, "zone");
+    assert_eq!(locations[1].point, Some(geo_types::Point::new(1.0, 1.0)));
+}
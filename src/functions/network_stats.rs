@@ -0,0 +1,329 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap, HashMap};
+#[cfg(test)]
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::unionfind::UnionFind;
+use petgraph::visit::EdgeRef;
+use serde::Serialize;
+
+#[cfg(test)]
+use crate::objects::feed::Feed;
+#[cfg(test)]
+use crate::objects::network;
+use crate::objects::network::{Edge, Station};
+
+#[cfg(test)]
+use super::gtfs;
+use super::round;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum NetworkStatsFormat {
+    Csv,
+    Json,
+    // Stations as points
+    GeoJson,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct StationStats {
+    pub station_id: String,
+    pub name: Option<String>,
+    // Number of neighbouring stations, in either direction
+    pub degree: usize,
+    pub routes: usize,
+    // Sum over the links to neighbouring stations of the routes running on them, so a station
+    // where many routes branch off ranks above one on a busy trunk with few neighbours
+    pub transfer_weighted_degree: usize,
+    // Share of shortest paths by run time between other stations passing through this one
+    pub betweenness: f64,
+    // Harmonic closeness, the mean of 1 / minutes to every other station
+    pub closeness: f64,
+    // Weakly connected components numbered by size, 0 being the largest
+    pub component: usize,
+    pub component_size: usize,
+    // Removing the station would split its component
+    pub articulation_point: bool,
+}
+
+pub fn station_stats(graph: &DiGraph<Station, Edge>) -> Vec<StationStats> {
+    let count = graph.node_count();
+    let (betweenness, closeness) = centrality(graph);
+    let articulation_points = articulation_points(graph);
+
+    let mut components = UnionFind::new(count);
+    for edge in graph.edge_references() {
+        components.union(edge.source().index(), edge.target().index());
+    }
+    let labels = components.into_labeling();
+    let mut sizes: HashMap<usize, usize> = HashMap::new();
+    for label in &labels {
+        *sizes.entry(*label).or_default() += 1;
+    }
+    let mut ranked: Vec<(usize, usize)> =
+        sizes.iter().map(|(label, size)| (*label, *size)).collect();
+    ranked.sort_by_key(|(label, size)| (Reverse(*size), *label));
+    let component_of: HashMap<usize, usize> = ranked
+        .iter()
+        .enumerate()
+        .map(|(rank, (label, _))| (*label, rank))
+        .collect();
+
+    let normalisation = if count > 2 {
+        ((count - 1) * (count - 2)) as f64
+    } else {
+        1.0
+    };
+    graph
+        .node_indices()
+        .map(|node| {
+            // Routes on the links to every neighbour, whichever way they run
+            let mut neighbours: HashMap<NodeIndex, BTreeSet<&str>> = HashMap::new();
+            let edges = graph
+                .edges_directed(node, petgraph::Direction::Outgoing)
+                .map(|edge| (edge.target(), edge.weight()))
+                .chain(
+                    graph
+                        .edges_directed(node, petgraph::Direction::Incoming)
+                        .map(|edge| (edge.source(), edge.weight())),
+                );
+            for (neighbour, edge) in edges {
+                neighbours
+                    .entry(neighbour)
+                    .or_default()
+                    .extend(edge.route_ids.iter().map(|route_id| route_id.as_str()));
+            }
+            let routes: BTreeSet<&str> = neighbours.values().flatten().copied().collect();
+            let label = labels[node.index()];
+
+            StationStats {
+                station_id: graph[node].station_id.to_owned(),
+                name: graph[node].name.clone(),
+                degree: neighbours.len(),
+                routes: routes.len(),
+                transfer_weighted_degree: neighbours.values().map(|routes| routes.len()).sum(),
+                betweenness: round(betweenness[node.index()] / normalisation, 4),
+                closeness: round(closeness[node.index()], 4),
+                component: component_of[&label],
+                component_size: sizes[&label],
+                articulation_point: articulation_points[node.index()],
+            }
+        })
+        .collect()
+}
+
+// Brandes' algorithm over run times, with Dijkstra from every station. Closeness comes from the
+// same shortest path trees.
+fn centrality(graph: &DiGraph<Station, Edge>) -> (Vec<f64>, Vec<f64>) {
+    let count = graph.node_count();
+    let mut betweenness = vec![0.0; count];
+    let mut closeness = vec![0.0; count];
+
+    for source in graph.node_indices() {
+        let mut order: Vec<NodeIndex> = Vec::new();
+        let mut predecessors: Vec<Vec<NodeIndex>> = vec![Vec::new(); count];
+        let mut paths = vec![0.0; count];
+        let mut distances: Vec<Option<u64>> = vec![None; count];
+        paths[source.index()] = 1.0;
+        distances[source.index()] = Some(0);
+
+        let mut queue = BinaryHeap::from([Reverse((0, source))]);
+        while let Some(Reverse((distance, node))) = queue.pop() {
+            if distances[node.index()].is_some_and(|known| distance > known) {
+                continue;
+            }
+            order.push(node);
+            for edge in graph.edges(node) {
+                // Run times are rounded to whole seconds and at least one
+                let next = distance + (edge.weight().avg_run_time().round() as u64).max(1);
+                let target = edge.target();
+                match distances[target.index()] {
+                    Some(known) if next > known => {}
+                    Some(known) if next == known => {
+                        paths[target.index()] += paths[node.index()];
+                        predecessors[target.index()].push(node);
+                    }
+                    _ => {
+                        distances[target.index()] = Some(next);
+                        paths[target.index()] = paths[node.index()];
+                        predecessors[target.index()] = vec![node];
+                        queue.push(Reverse((next, target)));
+                    }
+                }
+            }
+        }
+
+        closeness[source.index()] = distances
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| *index != source.index())
+            .filter_map(|(_, distance)| *distance)
+            .map(|seconds| 60.0 / seconds as f64)
+            .sum::<f64>()
+            / (count.max(2) - 1) as f64;
+
+        let mut dependencies = vec![0.0; count];
+        while let Some(node) = order.pop() {
+            for predecessor in &predecessors[node.index()] {
+                dependencies[predecessor.index()] += paths[predecessor.index()]
+                    / paths[node.index()]
+                    * (1.0 + dependencies[node.index()]);
+            }
+            if node != source {
+                betweenness[node.index()] += dependencies[node.index()];
+            }
+        }
+    }
+
+    (betweenness, closeness)
+}
+
+// Cut vertices of the undirected network, found with an iterative depth first search
+fn articulation_points(graph: &DiGraph<Station, Edge>) -> Vec<bool> {
+    let count = graph.node_count();
+    let mut neighbours: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); count];
+    for edge in graph.edge_references() {
+        let (a, b) = (edge.source().index(), edge.target().index());
+        neighbours[a].insert(b);
+        neighbours[b].insert(a);
+    }
+    let neighbours: Vec<Vec<usize>> = neighbours
+        .into_iter()
+        .map(|set| set.into_iter().collect())
+        .collect();
+
+    let mut discovery: Vec<Option<usize>> = vec![None; count];
+    let mut low = vec![0; count];
+    let mut points = vec![false; count];
+    let mut time = 0;
+    for root in 0..count {
+        if discovery[root].is_some() {
+            continue;
+        }
+        discovery[root] = Some(time);
+        low[root] = time;
+        time += 1;
+        let mut root_children = 0;
+        // Node, parent and the position in its neighbour list
+        let mut stack: Vec<(usize, Option<usize>, usize)> = vec![(root, None, 0)];
+        while let Some((node, parent, position)) = stack.pop() {
+            if let Some(&next) = neighbours[node].get(position) {
+                stack.push((node, parent, position + 1));
+                if Some(next) == parent {
+                    continue;
+                }
+                match discovery[next] {
+                    Some(found) => low[node] = low[node].min(found),
+                    None => {
+                        discovery[next] = Some(time);
+                        low[next] = time;
+                        time += 1;
+                        if node == root {
+                            root_children += 1;
+                        }
+                        stack.push((next, Some(node), 0));
+                    }
+                }
+            } else if let Some(parent) = parent {
+                low[parent] = low[parent].min(low[node]);
+                if parent != root && discovery[parent].is_some_and(|found| low[node] >= found) {
+                    points[parent] = true;
+                }
+            }
+        }
+        points[root] = root_children > 1;
+    }
+    points
+}
+
+#[derive(Serialize)]
+struct StationStatsJson<'a> {
+    #[serde(serialize_with = "geojson::ser::serialize_geometry")]
+    geometry: geo_types::Point,
+    #[serde(flatten)]
+    stats: &'a StationStats,
+}
+
+// Station statistics as GeoJSON points, leaving out stations without coordinates
+pub fn station_stats_geojson(
+    stats: &[StationStats],
+    graph: &DiGraph<Station, Edge>,
+) -> Result<String, String> {
+    let points: HashMap<&str, geo_types::Point> = graph
+        .node_weights()
+        .filter_map(|station| Some((station.station_id.as_str(), station.point?)))
+        .collect();
+    let features: Vec<StationStatsJson> = stats
+        .iter()
+        .filter_map(|stats| {
+            Some(StationStatsJson {
+                geometry: *points.get(stats.station_id.as_str())?,
+                stats,
+            })
+        })
+        .collect();
+
+    super::to_geojson_string(&features)
+}
+
+#[test]
+fn test_station_stats() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let feed = Feed::load(&mut gtfs_file);
+    let graph = network::network_graph(&feed, true);
+
+    let stats = station_stats(&graph);
+    let find = |id: &str| stats.iter().find(|stats| stats.station_id == id).unwrap();
+
+    // The sample network is a tree, every station but the ends is a cut vertex
+    let cut: Vec<&str> = stats
+        .iter()
+        .filter(|stats| stats.articulation_point)
+        .map(|stats| stats.station_id.as_str())
+        .collect();
+    assert_eq!(cut.len(), 6);
+    assert!(!find("EMSI").articulation_point && !find("AMV").articulation_point);
+    assert!(stats
+        .iter()
+        .all(|stats| stats.component == 0 && stats.component_size == 9));
+
+    let airport = find("BEATTY_AIRPORT");
+    assert_eq!(airport.degree, 3);
+    assert_eq!(airport.routes, 3);
+    assert_eq!(airport.transfer_weighted_degree, 3);
+    let busiest = stats
+        .iter()
+        .max_by(|a, b| a.betweenness.total_cmp(&b.betweenness))
+        .unwrap();
+    assert_eq!(busiest.station_id, "BEATTY_AIRPORT");
+    assert_eq!(find("EMSI").betweenness, 0.0);
+    assert!(find("STAGECOACH").closeness > 0.0);
+}
+
+#[test]
+fn test_articulation_points() {
+    // Two triangles joined at C, and a separate pair
+    let mut graph: DiGraph<Station, Edge> = DiGraph::new();
+    let nodes: Vec<NodeIndex> = ["A", "B", "C", "D", "E", "F", "G"]
+        .iter()
+        .map(|id| {
+            graph.add_node(Station {
+                station_id: id.to_string(),
+                name: None,
+                point: None,
+            })
+        })
+        .collect();
+    for (a, b) in [(0, 1), (1, 2), (2, 0), (2, 3), (3, 4), (4, 2), (5, 6)] {
+        graph.add_edge(nodes[a], nodes[b], Edge::default());
+    }
+
+    let points = articulation_points(&graph);
+    assert_eq!(points, vec![false, false, true, false, false, false, false]);
+    let stats = station_stats(&graph);
+    assert_eq!(stats[5].component, 1);
+    assert_eq!(stats[5].component_size, 2);
+}
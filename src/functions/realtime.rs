@@ -0,0 +1,528 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+#[cfg(test)]
+use std::path::PathBuf;
+
+use chrono::{DateTime, NaiveDate, TimeZone};
+use chrono_tz::Tz;
+use prost::Message;
+
+use crate::objects::feed::Feed;
+use crate::objects::pattern;
+
+use super::geodata;
+use super::gtfs;
+use super::gtfs::{Route, Stop, StopTime, Trip};
+
+pub mod proto;
+pub mod realized;
+pub mod replay;
+pub mod synthetic;
+pub mod trajectories;
+pub mod validate;
+
+use proto::{FeedMessage, NamedEnum};
+
+// A FeedMessage from protobuf, or from its JSON form when the file starts with a brace
+pub fn read_feed_message(path: &Path) -> Result<FeedMessage, String> {
+    let bytes = std::fs::read(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+    decode_feed_message(&bytes).map_err(|error| format!("{}: {}", path.display(), error))
+}
+
+// A snapshot file, or every file of a directory of snapshots, in order of their header timestamps
+pub fn read_snapshots(path: &Path) -> Result<Vec<FeedMessage>, String> {
+    let mut messages: Vec<FeedMessage> = Vec::new();
+    if path.is_dir() {
+        let entries =
+            std::fs::read_dir(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+        for entry in entries {
+            let entry = entry.map_err(|error| error.to_string())?;
+            if entry.path().is_file() {
+                messages.push(read_feed_message(&entry.path())?);
+            }
+        }
+    } else {
+        messages.push(read_feed_message(path)?);
+    }
+    messages.sort_by_key(|message| message.header.timestamp);
+    Ok(messages)
+}
+
+pub fn decode_feed_message(bytes: &[u8]) -> Result<FeedMessage, String> {
+    let is_json = bytes.iter().find(|byte| !byte.is_ascii_whitespace()) == Some(&b'{');
+    if is_json {
+        let mut value: serde_json::Value =
+            serde_json::from_slice(bytes).map_err(|error| error.to_string())?;
+        camel_case_keys(&mut value);
+        serde_json::from_value(value).map_err(|error| error.to_string())
+    } else {
+        FeedMessage::decode(bytes).map_err(|error| error.to_string())
+    }
+}
+
+// JSON written with the original proto field names is read like the camelCase mapping
+fn camel_case_keys(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            let entries: Vec<(String, serde_json::Value)> =
+                std::mem::take(map).into_iter().collect();
+            for (key, mut value) in entries {
+                camel_case_keys(&mut value);
+                let mut parts = key.split('_');
+                let mut camel = parts.next().unwrap_or_default().to_owned();
+                for part in parts {
+                    let mut chars = part.chars();
+                    if let Some(first) = chars.next() {
+                        camel.push(first.to_ascii_uppercase());
+                        camel.push_str(chars.as_str());
+                    }
+                }
+                map.insert(camel, value);
+            }
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(camel_case_keys),
+        _ => {}
+    }
+}
+
+pub fn format_timestamp(timestamp: i64) -> String {
+    match DateTime::from_timestamp(timestamp, 0) {
+        Some(time) => time.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+        None => timestamp.to_string(),
+    }
+}
+
+pub fn parse_start_date(start_date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(start_date, "%Y%m%d").ok()
+}
+
+// Seconds after the start of the service day, which may be past 24:00:00 like in stop_times.txt
+pub fn parse_start_time(start_time: &str) -> Option<u64> {
+    let parts: Vec<u64> = start_time
+        .split(':')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    match parts[..] {
+        [h, m, s] if m < 60 && s < 60 => Some(h * 3600 + m * 60 + s),
+        _ => None,
+    }
+}
+
+// Times in stop_times.txt count from noon minus twelve hours, which is midnight except on the
+// days the clocks change
+pub fn service_day_start(date: NaiveDate, timezone: Tz) -> i64 {
+    let noon = date.and_hms_opt(12, 0, 0).unwrap_or_default();
+    let noon = match timezone.from_local_datetime(&noon).earliest() {
+        Some(noon) => noon.timestamp(),
+        None => noon.and_utc().timestamp(),
+    };
+    noon - 12 * 3600
+}
+
+fn enum_name<E: NamedEnum>(value: i32) -> String {
+    E::name(value).map_or_else(|| value.to_string(), str::to_owned)
+}
+
+// The position among a trip's calls, given by stop sequence and stop id, of the call each update
+// refers to. Updates are matched by stop_sequence, or else by the next call at their stop after
+// the previous match.
+pub fn match_calls(
+    calls: &[(Option<u64>, Option<&str>)],
+    updates: &[proto::StopTimeUpdate],
+) -> Vec<Option<usize>> {
+    let mut next = 0;
+    updates
+        .iter()
+        .map(|update| {
+            let position = match (update.stop_sequence, &update.stop_id) {
+                (Some(sequence), _) => calls
+                    .iter()
+                    .position(|(call, _)| *call == Some(sequence as u64)),
+                (None, Some(stop_id)) => calls[next.min(calls.len())..]
+                    .iter()
+                    .position(|(_, call)| *call == Some(stop_id.as_str()))
+                    .map(|position| position + next),
+                (None, None) => None,
+            };
+            if let Some(position) = position {
+                next = position + 1;
+            }
+            position
+        })
+        .collect()
+}
+
+// Lookups of the static feed that realtime messages refer to
+pub struct StaticIndex<'a> {
+    pub feed: &'a Feed,
+    pub trips: HashMap<&'a str, &'a Trip>,
+    pub routes: HashMap<&'a str, &'a Route>,
+    pub stops: HashMap<&'a str, &'a Stop>,
+    // Stop times of each trip in stop_sequence order
+    pub stop_times: HashMap<&'a str, Vec<&'a StopTime>>,
+    pub timezone: Tz,
+}
+
+impl<'a> StaticIndex<'a> {
+    pub fn new(feed: &'a Feed) -> StaticIndex<'a> {
+        let mut stop_times: HashMap<&str, Vec<&StopTime>> = HashMap::new();
+        for stop_time in &feed.stop_times {
+            stop_times
+                .entry(stop_time.trip_id.as_str())
+                .or_default()
+                .push(stop_time);
+        }
+        for trip_stop_times in stop_times.values_mut() {
+            trip_stop_times.sort_by_key(|stop_time| stop_time.stop_sequence);
+        }
+        StaticIndex {
+            feed,
+            trips: feed
+                .trips
+                .iter()
+                .map(|trip| (trip.trip_id.as_str(), trip))
+                .collect(),
+            routes: feed
+                .routes
+                .iter()
+                .map(|route| (route.route_id.as_str(), route))
+                .collect(),
+            stops: feed
+                .stops
+                .iter()
+                .map(|stop| (stop.stop_id.as_str(), stop))
+                .collect(),
+            stop_times,
+            timezone: feed.timezone(),
+        }
+    }
+
+    // The scheduled stop time each update refers to, see match_calls
+    pub fn match_stop_times(
+        &self,
+        trip_id: &str,
+        updates: &[proto::StopTimeUpdate],
+    ) -> Vec<Option<usize>> {
+        let calls: Vec<(Option<u64>, Option<&str>)> = self
+            .stop_times
+            .get(trip_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .map(|stop_time| (stop_time.stop_sequence, stop_time.stop_id.as_deref()))
+            .collect();
+        match_calls(&calls, updates)
+    }
+
+    // Unix time of a scheduled stop time, for frequency based trips shifted to the instance's
+    // start time
+    pub fn scheduled_time(
+        &self,
+        trip_id: &str,
+        date: NaiveDate,
+        start_time: Option<u64>,
+        seconds: u64,
+    ) -> i64 {
+        let first = self
+            .stop_times
+            .get(trip_id)
+            .and_then(|stop_times| stop_times.first())
+            .and_then(|stop_time| {
+                stop_time
+                    .departure_time
+                    .as_ref()
+                    .or(stop_time.arrival_time.as_ref())
+            })
+            .map(gtfs::Time::seconds);
+        let shift = match (start_time, first) {
+            (Some(start_time), Some(first)) => start_time as i64 - first as i64,
+            _ => 0,
+        };
+        service_day_start(date, self.timezone) + seconds as i64 + shift
+    }
+
+    // The trip's shape, or the line through its stops for trips without one
+    pub fn trip_line(
+        &self,
+        trip: &Trip,
+        shape_lines: &HashMap<String, Vec<geo_types::Point>>,
+    ) -> Vec<geo_types::Point> {
+        match trip
+            .shape_id
+            .as_ref()
+            .and_then(|shape_id| shape_lines.get(shape_id))
+        {
+            Some(line) => line.clone(),
+            None => self
+                .stop_times
+                .get(trip.trip_id.as_str())
+                .map(Vec::as_slice)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|stop_time| {
+                    geodata::stop_point(self.stops.get(stop_time.stop_id.as_deref()?)?)
+                })
+                .collect(),
+        }
+    }
+
+    fn trip(&self, descriptor: &proto::TripDescriptor) -> String {
+        let mut text = match &descriptor.trip_id {
+            Some(trip_id) => match self.trips.get(trip_id.as_str()) {
+                Some(trip) => format!(
+                    "trip {} ({}{})",
+                    trip_id,
+                    self.route(&trip.route_id),
+                    trip.trip_headsign
+                        .as_ref()
+                        .map(|headsign| format!(", {}", headsign))
+                        .unwrap_or_default()
+                ),
+                None => format!("trip {} (unknown)", trip_id),
+            },
+            None => match &descriptor.route_id {
+                Some(route_id) => format!("a trip of {}", self.route(route_id)),
+                None => String::from("an unidentified trip"),
+            },
+        };
+        if let Some(date) = &descriptor.start_date {
+            let _ = write!(text, " on {}", date);
+        }
+        if let Some(time) = &descriptor.start_time {
+            let _ = write!(text, " at {}", time);
+        }
+        if let Some(relationship) = descriptor.schedule_relationship.filter(|value| *value != 0) {
+            let _ = write!(
+                text,
+                ", {}",
+                enum_name::<proto::TripScheduleRelationship>(relationship)
+            );
+        }
+        text
+    }
+
+    fn route(&self, route_id: &str) -> String {
+        match self.routes.get(route_id) {
+            Some(route) => format!("route {} {}", route_id, pattern::route_name(route)),
+            None => format!("route {} (unknown)", route_id),
+        }
+    }
+
+    fn stop(&self, stop_id: &str) -> String {
+        match self.stops.get(stop_id) {
+            Some(stop) => format!(
+                "stop {} {}",
+                stop_id,
+                stop.stop_name.as_deref().unwrap_or_default()
+            ),
+            None => format!("stop {} (unknown)", stop_id),
+        }
+    }
+}
+
+fn stop_time_event(event: &proto::StopTimeEvent) -> String {
+    match (event.time, event.delay) {
+        (Some(time), Some(delay)) => format!("{} ({:+} s)", format_timestamp(time), delay),
+        (Some(time), None) => format_timestamp(time),
+        (None, Some(delay)) => format!("{:+} s", delay),
+        (None, None) => String::from("no prediction"),
+    }
+}
+
+fn translated(text: &proto::TranslatedString) -> String {
+    let translations: Vec<String> = text
+        .translation
+        .iter()
+        .map(|translation| match &translation.language {
+            Some(language) => format!("[{}] {}", language, translation.text),
+            None => translation.text.to_owned(),
+        })
+        .collect();
+    translations.join(" ")
+}
+
+// One line per entity and per stop time update, with ids resolved against the static feed
+pub fn describe_feed_message(message: &FeedMessage, index: &StaticIndex) -> String {
+    let header = &message.header;
+    let mut output = format!(
+        "GTFS-RT {} {}",
+        header.gtfs_realtime_version,
+        enum_name::<proto::Incrementality>(header.incrementality.unwrap_or_default())
+    );
+    if let Some(timestamp) = header.timestamp {
+        let _ = write!(output, " at {}", format_timestamp(timestamp as i64));
+    }
+    let _ = writeln!(output, ", {} entities", message.entity.len());
+
+    for entity in &message.entity {
+        if entity.is_deleted == Some(true) {
+            let _ = writeln!(output, "{}: deleted", entity.id);
+            continue;
+        }
+        if let Some(update) = &entity.trip_update {
+            let _ = write!(
+                output,
+                "{}: trip update for {}",
+                entity.id,
+                index.trip(&update.trip)
+            );
+            if let Some(delay) = update.delay {
+                let _ = write!(output, ", delay {:+} s", delay);
+            }
+            output.push('\n');
+            for stop_time in &update.stop_time_update {
+                let sequence = stop_time
+                    .stop_sequence
+                    .map(|sequence| format!("#{} ", sequence))
+                    .unwrap_or_default();
+                let stop = stop_time
+                    .stop_id
+                    .as_deref()
+                    .map_or_else(|| String::from("stop ?"), |stop_id| index.stop(stop_id));
+                let _ = write!(output, "  {}{}", sequence, stop);
+                match stop_time.schedule_relationship.filter(|value| *value != 0) {
+                    Some(relationship) => {
+                        let _ = write!(
+                            output,
+                            ": {}",
+                            enum_name::<proto::StopScheduleRelationship>(relationship)
+                        );
+                    }
+                    None => {
+                        if let Some(arrival) = &stop_time.arrival {
+                            let _ = write!(output, ", arrival {}", stop_time_event(arrival));
+                        }
+                        if let Some(departure) = &stop_time.departure {
+                            let _ = write!(output, ", departure {}", stop_time_event(departure));
+                        }
+                    }
+                }
+                output.push('\n');
+            }
+        }
+        if let Some(vehicle) = &entity.vehicle {
+            let label = vehicle
+                .vehicle
+                .as_ref()
+                .and_then(|vehicle| vehicle.id.clone().or(vehicle.label.clone()));
+            let _ = write!(
+                output,
+                "{}: vehicle {}",
+                entity.id,
+                label.as_deref().unwrap_or("?")
+            );
+            if let Some(position) = &vehicle.position {
+                let _ = write!(
+                    output,
+                    " at {:.5},{:.5}",
+                    position.latitude, position.longitude
+                );
+            }
+            if let Some(trip) = &vehicle.trip {
+                let _ = write!(output, " on {}", index.trip(trip));
+            }
+            if let Some(stop_id) = &vehicle.stop_id {
+                let status =
+                    enum_name::<proto::VehicleStopStatus>(vehicle.current_status.unwrap_or(2));
+                let _ = write!(output, ", {} {}", status, index.stop(stop_id));
+            }
+            if let Some(timestamp) = vehicle.timestamp {
+                let _ = write!(output, " at {}", format_timestamp(timestamp as i64));
+            }
+            output.push('\n');
+        }
+        if let Some(alert) = &entity.alert {
+            let effect = enum_name::<proto::Effect>(alert.effect.unwrap_or(8));
+            let cause = enum_name::<proto::Cause>(alert.cause.unwrap_or(1));
+            let _ = write!(output, "{}: alert {} due to {}", entity.id, effect, cause);
+            if let Some(header) = &alert.header_text {
+                let _ = write!(output, ": {}", translated(header));
+            }
+            output.push('\n');
+            for selector in &alert.informed_entity {
+                let mut parts: Vec<String> = Vec::new();
+                if let Some(agency_id) = &selector.agency_id {
+                    parts.push(format!("agency {}", agency_id));
+                }
+                if let Some(route_id) = &selector.route_id {
+                    parts.push(index.route(route_id));
+                }
+                if let Some(trip) = &selector.trip {
+                    parts.push(index.trip(trip));
+                }
+                if let Some(stop_id) = &selector.stop_id {
+                    parts.push(index.stop(stop_id));
+                }
+                let _ = writeln!(output, "  affects {}", parts.join(", "));
+            }
+        }
+    }
+    output
+}
+
+#[test]
+fn test_decode_feed_message() {
+    let path: PathBuf = PathBuf::from("test_data/sample-rt-1.json");
+    let message = read_feed_message(&path).unwrap();
+    assert_eq!(message.header.timestamp, Some(1181055900));
+    assert_eq!(message.entity.len(), 4);
+    let update = message.entity[0].trip_update.as_ref().unwrap();
+    assert_eq!(
+        update.stop_time_update[1].arrival.as_ref().unwrap().time,
+        Some(1181056320)
+    );
+    let alert = message.entity[3].alert.as_ref().unwrap();
+    assert_eq!(alert.effect, Some(proto::Effect::SignificantDelays as i32));
+
+    // Protobuf round trip, and the JSON form with proto field names
+    let decoded = decode_feed_message(&message.encode_to_vec()).unwrap();
+    assert!(decoded == message);
+    let snake = r#"{"header": {"gtfs_realtime_version": "2.0", "timestamp": 1181055900},
+        "entity": [{"id": "1", "trip_update": {"trip": {"trip_id": "AB1", "schedule_relationship": 3}}}]}"#;
+    let message = decode_feed_message(snake.as_bytes()).unwrap();
+    let trip = &message.entity[0].trip_update.as_ref().unwrap().trip;
+    assert_eq!(trip.trip_id.as_deref(), Some("AB1"));
+    assert_eq!(
+        trip.schedule_relationship,
+        Some(proto::TripScheduleRelationship::Canceled as i32)
+    );
+    assert!(decode_feed_message(b"{\"header\": {\"incrementality\": \"SOMETIMES\"}}").is_err());
+}
+
+#[test]
+fn test_describe_feed_message() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let feed = Feed::load(&mut gtfs_file);
+    let message = read_feed_message(&PathBuf::from("test_data/sample-rt-1.json")).unwrap();
+    let index = StaticIndex::new(&feed);
+    // Eight in the morning in Nevada's Pacific Daylight Time
+    let date = NaiveDate::from_ymd_opt(2007, 6, 5).unwrap();
+    assert_eq!(
+        index.scheduled_time("AB1", date, None, 8 * 3600),
+        1181055600
+    );
+
+    let output = describe_feed_message(&message, &StaticIndex::new(&feed));
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines[0],
+        "GTFS-RT 2.0 FULL_DATASET at 2007-06-05 15:05:00 UTC, 4 entities"
+    );
+    assert_eq!(
+        lines[1],
+        "trip-AB1: trip update for trip AB1 (route AB 10, to Bullfrog) on 20070605"
+    );
+    assert_eq!(
+        lines[2],
+        "  #1 stop BEATTY_AIRPORT Nye County Airport (Demo), departure +120 s"
+    );
+    assert!(lines[4].contains("trip GHOST (unknown)"));
+    assert_eq!(lines[5], "  #1 stop NOWHERE (unknown): SKIPPED");
+    assert!(lines[6].starts_with("vehicle-bus-7: vehicle bus-7 at 36.87450,-116.80120 on trip AB1"));
+    assert_eq!(
+        lines[7],
+        "alert-1: alert SIGNIFICANT_DELAYS due to WEATHER: [en] Dust storm near Bullfrog"
+    );
+}
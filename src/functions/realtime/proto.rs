@@ -0,0 +1,419 @@
+// GTFS Realtime messages as in gtfs-realtime.proto, written out by hand so that no protoc is
+// needed to build. Only the fields this crate uses are declared. Unknown fields are skipped when
+// decoding protobuf, and ignored in JSON. JSON follows the protobuf JSON mapping, with camelCase
+// keys, enum names and 64 bit integers as strings or numbers.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_with::{serde_as, skip_serializing_none, DisplayFromStr, PickFirst};
+
+// Enum fields are kept as i32 like prost does, so that values from newer spec versions survive
+pub trait NamedEnum {
+    fn name(value: i32) -> Option<&'static str>;
+    fn value(name: &str) -> Option<i32>;
+}
+
+macro_rules! named_enum {
+    ($name:ident { $($variant:ident = $value:literal => $text:literal),* $(,)? }) => {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+        #[repr(i32)]
+        #[allow(clippy::enum_variant_names)]
+        pub enum $name {
+            $($variant = $value),*
+        }
+
+        impl NamedEnum for $name {
+            fn name(value: i32) -> Option<&'static str> {
+                match value {
+                    $($value => Some($text),)*
+                    _ => None,
+                }
+            }
+
+            fn value(name: &str) -> Option<i32> {
+                match name {
+                    $($text => Some($value),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+fn serialize_enum<E: NamedEnum, S: Serializer>(
+    value: &Option<i32>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match value.map(|value| (value, E::name(value))) {
+        Some((_, Some(name))) => serializer.serialize_str(name),
+        Some((value, None)) => serializer.serialize_i32(value),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn deserialize_enum<'de, E: NamedEnum, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<i32>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NameOrValue {
+        Name(String),
+        Value(i32),
+    }
+    match Option::<NameOrValue>::deserialize(deserializer)? {
+        Some(NameOrValue::Name(name)) => E::value(&name)
+            .map(Some)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown enum value {}", name))),
+        Some(NameOrValue::Value(value)) => Ok(Some(value)),
+        None => Ok(None),
+    }
+}
+
+named_enum!(Incrementality {
+    FullDataset = 0 => "FULL_DATASET",
+    Differential = 1 => "DIFFERENTIAL",
+});
+
+named_enum!(TripScheduleRelationship {
+    Scheduled = 0 => "SCHEDULED",
+    Added = 1 => "ADDED",
+    Unscheduled = 2 => "UNSCHEDULED",
+    Canceled = 3 => "CANCELED",
+    Replacement = 5 => "REPLACEMENT",
+    Duplicated = 6 => "DUPLICATED",
+    Deleted = 7 => "DELETED",
+});
+
+named_enum!(StopScheduleRelationship {
+    Scheduled = 0 => "SCHEDULED",
+    Skipped = 1 => "SKIPPED",
+    NoData = 2 => "NO_DATA",
+    Unscheduled = 3 => "UNSCHEDULED",
+});
+
+named_enum!(VehicleStopStatus {
+    IncomingAt = 0 => "INCOMING_AT",
+    StoppedAt = 1 => "STOPPED_AT",
+    InTransitTo = 2 => "IN_TRANSIT_TO",
+});
+
+named_enum!(CongestionLevel {
+    UnknownCongestionLevel = 0 => "UNKNOWN_CONGESTION_LEVEL",
+    RunningSmoothly = 1 => "RUNNING_SMOOTHLY",
+    StopAndGo = 2 => "STOP_AND_GO",
+    Congestion = 3 => "CONGESTION",
+    SevereCongestion = 4 => "SEVERE_CONGESTION",
+});
+
+named_enum!(OccupancyStatus {
+    Empty = 0 => "EMPTY",
+    ManySeatsAvailable = 1 => "MANY_SEATS_AVAILABLE",
+    FewSeatsAvailable = 2 => "FEW_SEATS_AVAILABLE",
+    StandingRoomOnly = 3 => "STANDING_ROOM_ONLY",
+    CrushedStandingRoomOnly = 4 => "CRUSHED_STANDING_ROOM_ONLY",
+    Full = 5 => "FULL",
+    NotAcceptingPassengers = 6 => "NOT_ACCEPTING_PASSENGERS",
+    NoDataAvailable = 7 => "NO_DATA_AVAILABLE",
+    NotBoardable = 8 => "NOT_BOARDABLE",
+});
+
+named_enum!(Cause {
+    UnknownCause = 1 => "UNKNOWN_CAUSE",
+    OtherCause = 2 => "OTHER_CAUSE",
+    TechnicalProblem = 3 => "TECHNICAL_PROBLEM",
+    Strike = 4 => "STRIKE",
+    Demonstration = 5 => "DEMONSTRATION",
+    Accident = 6 => "ACCIDENT",
+    Holiday = 7 => "HOLIDAY",
+    Weather = 8 => "WEATHER",
+    Maintenance = 9 => "MAINTENANCE",
+    Construction = 10 => "CONSTRUCTION",
+    PoliceActivity = 11 => "POLICE_ACTIVITY",
+    MedicalEmergency = 12 => "MEDICAL_EMERGENCY",
+});
+
+named_enum!(Effect {
+    NoService = 1 => "NO_SERVICE",
+    ReducedService = 2 => "REDUCED_SERVICE",
+    SignificantDelays = 3 => "SIGNIFICANT_DELAYS",
+    Detour = 4 => "DETOUR",
+    AdditionalService = 5 => "ADDITIONAL_SERVICE",
+    ModifiedService = 6 => "MODIFIED_SERVICE",
+    OtherEffect = 7 => "OTHER_EFFECT",
+    UnknownEffect = 8 => "UNKNOWN_EFFECT",
+    StopMoved = 9 => "STOP_MOVED",
+    NoEffect = 10 => "NO_EFFECT",
+    AccessibilityIssue = 11 => "ACCESSIBILITY_ISSUE",
+});
+
+named_enum!(SeverityLevel {
+    UnknownSeverity = 1 => "UNKNOWN_SEVERITY",
+    Info = 2 => "INFO",
+    Warning = 3 => "WARNING",
+    Severe = 4 => "SEVERE",
+});
+
+#[derive(Clone, PartialEq, prost::Message, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FeedMessage {
+    #[prost(message, required, tag = "1")]
+    pub header: FeedHeader,
+    #[prost(message, repeated, tag = "2")]
+    pub entity: Vec<FeedEntity>,
+}
+
+#[serde_as]
+#[skip_serializing_none]
+#[derive(Clone, PartialEq, prost::Message, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FeedHeader {
+    #[prost(string, required, tag = "1")]
+    pub gtfs_realtime_version: String,
+    #[prost(enumeration = "Incrementality", optional, tag = "2")]
+    #[serde(
+        serialize_with = "serialize_enum::<Incrementality, _>",
+        deserialize_with = "deserialize_enum::<Incrementality, _>"
+    )]
+    pub incrementality: Option<i32>,
+    #[prost(uint64, optional, tag = "3")]
+    #[serde_as(as = "Option<PickFirst<(_, DisplayFromStr)>>")]
+    pub timestamp: Option<u64>,
+}
+
+#[skip_serializing_none]
+#[derive(Clone, PartialEq, prost::Message, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FeedEntity {
+    #[prost(string, required, tag = "1")]
+    pub id: String,
+    #[prost(bool, optional, tag = "2")]
+    pub is_deleted: Option<bool>,
+    #[prost(message, optional, tag = "3")]
+    pub trip_update: Option<TripUpdate>,
+    #[prost(message, optional, tag = "4")]
+    pub vehicle: Option<VehiclePosition>,
+    #[prost(message, optional, tag = "5")]
+    pub alert: Option<Alert>,
+}
+
+#[serde_as]
+#[skip_serializing_none]
+#[derive(Clone, PartialEq, prost::Message, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TripUpdate {
+    #[prost(message, required, tag = "1")]
+    pub trip: TripDescriptor,
+    #[prost(message, repeated, tag = "2")]
+    pub stop_time_update: Vec<StopTimeUpdate>,
+    #[prost(message, optional, tag = "3")]
+    pub vehicle: Option<VehicleDescriptor>,
+    #[prost(uint64, optional, tag = "4")]
+    #[serde_as(as = "Option<PickFirst<(_, DisplayFromStr)>>")]
+    pub timestamp: Option<u64>,
+    #[prost(int32, optional, tag = "5")]
+    pub delay: Option<i32>,
+}
+
+#[serde_as]
+#[skip_serializing_none]
+#[derive(Clone, PartialEq, prost::Message, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StopTimeEvent {
+    #[prost(int32, optional, tag = "1")]
+    pub delay: Option<i32>,
+    #[prost(int64, optional, tag = "2")]
+    #[serde_as(as = "Option<PickFirst<(_, DisplayFromStr)>>")]
+    pub time: Option<i64>,
+    #[prost(int32, optional, tag = "3")]
+    pub uncertainty: Option<i32>,
+}
+
+#[skip_serializing_none]
+#[derive(Clone, PartialEq, prost::Message, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StopTimeUpdate {
+    #[prost(uint32, optional, tag = "1")]
+    pub stop_sequence: Option<u32>,
+    #[prost(message, optional, tag = "2")]
+    pub arrival: Option<StopTimeEvent>,
+    #[prost(message, optional, tag = "3")]
+    pub departure: Option<StopTimeEvent>,
+    #[prost(string, optional, tag = "4")]
+    pub stop_id: Option<String>,
+    #[prost(enumeration = "StopScheduleRelationship", optional, tag = "5")]
+    #[serde(
+        serialize_with = "serialize_enum::<StopScheduleRelationship, _>",
+        deserialize_with = "deserialize_enum::<StopScheduleRelationship, _>"
+    )]
+    pub schedule_relationship: Option<i32>,
+}
+
+#[serde_as]
+#[skip_serializing_none]
+#[derive(Clone, PartialEq, prost::Message, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct VehiclePosition {
+    #[prost(message, optional, tag = "1")]
+    pub trip: Option<TripDescriptor>,
+    #[prost(message, optional, tag = "2")]
+    pub position: Option<Position>,
+    #[prost(uint32, optional, tag = "3")]
+    pub current_stop_sequence: Option<u32>,
+    #[prost(enumeration = "VehicleStopStatus", optional, tag = "4")]
+    #[serde(
+        serialize_with = "serialize_enum::<VehicleStopStatus, _>",
+        deserialize_with = "deserialize_enum::<VehicleStopStatus, _>"
+    )]
+    pub current_status: Option<i32>,
+    #[prost(uint64, optional, tag = "5")]
+    #[serde_as(as = "Option<PickFirst<(_, DisplayFromStr)>>")]
+    pub timestamp: Option<u64>,
+    #[prost(enumeration = "CongestionLevel", optional, tag = "6")]
+    #[serde(
+        serialize_with = "serialize_enum::<CongestionLevel, _>",
+        deserialize_with = "deserialize_enum::<CongestionLevel, _>"
+    )]
+    pub congestion_level: Option<i32>,
+    #[prost(string, optional, tag = "7")]
+    pub stop_id: Option<String>,
+    #[prost(message, optional, tag = "8")]
+    pub vehicle: Option<VehicleDescriptor>,
+    #[prost(enumeration = "OccupancyStatus", optional, tag = "9")]
+    #[serde(
+        serialize_with = "serialize_enum::<OccupancyStatus, _>",
+        deserialize_with = "deserialize_enum::<OccupancyStatus, _>"
+    )]
+    pub occupancy_status: Option<i32>,
+}
+
+#[skip_serializing_none]
+#[derive(Clone, PartialEq, prost::Message, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Position {
+    #[prost(float, required, tag = "1")]
+    pub latitude: f32,
+    #[prost(float, required, tag = "2")]
+    pub longitude: f32,
+    #[prost(float, optional, tag = "3")]
+    pub bearing: Option<f32>,
+    #[prost(double, optional, tag = "4")]
+    pub odometer: Option<f64>,
+    // Metres per second
+    #[prost(float, optional, tag = "5")]
+    pub speed: Option<f32>,
+}
+
+#[skip_serializing_none]
+#[derive(Clone, PartialEq, prost::Message, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Alert {
+    #[prost(message, repeated, tag = "1")]
+    pub active_period: Vec<TimeRange>,
+    #[prost(message, repeated, tag = "5")]
+    pub informed_entity: Vec<EntitySelector>,
+    #[prost(enumeration = "Cause", optional, tag = "6")]
+    #[serde(
+        serialize_with = "serialize_enum::<Cause, _>",
+        deserialize_with = "deserialize_enum::<Cause, _>"
+    )]
+    pub cause: Option<i32>,
+    #[prost(enumeration = "Effect", optional, tag = "7")]
+    #[serde(
+        serialize_with = "serialize_enum::<Effect, _>",
+        deserialize_with = "deserialize_enum::<Effect, _>"
+    )]
+    pub effect: Option<i32>,
+    #[prost(message, optional, tag = "8")]
+    pub url: Option<TranslatedString>,
+    #[prost(message, optional, tag = "10")]
+    pub header_text: Option<TranslatedString>,
+    #[prost(message, optional, tag = "11")]
+    pub description_text: Option<TranslatedString>,
+    #[prost(enumeration = "SeverityLevel", optional, tag = "14")]
+    #[serde(
+        serialize_with = "serialize_enum::<SeverityLevel, _>",
+        deserialize_with = "deserialize_enum::<SeverityLevel, _>"
+    )]
+    pub severity_level: Option<i32>,
+}
+
+#[serde_as]
+#[skip_serializing_none]
+#[derive(Clone, PartialEq, prost::Message, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TimeRange {
+    #[prost(uint64, optional, tag = "1")]
+    #[serde_as(as = "Option<PickFirst<(_, DisplayFromStr)>>")]
+    pub start: Option<u64>,
+    #[prost(uint64, optional, tag = "2")]
+    #[serde_as(as = "Option<PickFirst<(_, DisplayFromStr)>>")]
+    pub end: Option<u64>,
+}
+
+#[skip_serializing_none]
+#[derive(Clone, PartialEq, prost::Message, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TripDescriptor {
+    #[prost(string, optional, tag = "1")]
+    pub trip_id: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    pub start_time: Option<String>,
+    #[prost(string, optional, tag = "3")]
+    pub start_date: Option<String>,
+    #[prost(enumeration = "TripScheduleRelationship", optional, tag = "4")]
+    #[serde(
+        serialize_with = "serialize_enum::<TripScheduleRelationship, _>",
+        deserialize_with = "deserialize_enum::<TripScheduleRelationship, _>"
+    )]
+    pub schedule_relationship: Option<i32>,
+    #[prost(string, optional, tag = "5")]
+    pub route_id: Option<String>,
+    #[prost(uint32, optional, tag = "6")]
+    pub direction_id: Option<u32>,
+}
+
+#[skip_serializing_none]
+#[derive(Clone, PartialEq, prost::Message, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct VehicleDescriptor {
+    #[prost(string, optional, tag = "1")]
+    pub id: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    pub label: Option<String>,
+    #[prost(string, optional, tag = "3")]
+    pub license_plate: Option<String>,
+}
+
+#[skip_serializing_none]
+#[derive(Clone, PartialEq, prost::Message, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EntitySelector {
+    #[prost(string, optional, tag = "1")]
+    pub agency_id: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    pub route_id: Option<String>,
+    #[prost(int32, optional, tag = "3")]
+    pub route_type: Option<i32>,
+    #[prost(message, optional, tag = "4")]
+    pub trip: Option<TripDescriptor>,
+    #[prost(string, optional, tag = "5")]
+    pub stop_id: Option<String>,
+    #[prost(uint32, optional, tag = "6")]
+    pub direction_id: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TranslatedString {
+    #[prost(message, repeated, tag = "1")]
+    pub translation: Vec<Translation>,
+}
+
+#[skip_serializing_none]
+#[derive(Clone, PartialEq, prost::Message, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Translation {
+    #[prost(string, required, tag = "1")]
+    pub text: String,
+    #[prost(string, optional, tag = "2")]
+    pub language: Option<String>,
+}
@@ -0,0 +1,422 @@
+use std::collections::{HashMap, HashSet};
+#[cfg(test)]
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::objects::feed::Feed;
+use crate::objects::trip::TripInstance;
+
+#[cfg(test)]
+use super::super::gtfs;
+use super::super::gtfs::{CalendarDate, CalendarException, StopTime, Time, Trip};
+use super::proto::{self, FeedMessage, StopScheduleRelationship, TripScheduleRelationship};
+#[cfg(test)]
+use super::{decode_feed_message, read_feed_message};
+use super::{match_calls, parse_start_date, parse_start_time, service_day_start};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CallStatus {
+    // No realtime data, so as in the timetable
+    Scheduled,
+    Predicted,
+    Skipped,
+    Canceled,
+}
+
+// The last prediction for a call made before the vehicle left, in seconds since the start of the
+// service day
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RealizedCall {
+    pub arrival: i64,
+    pub departure: i64,
+    pub skipped: bool,
+}
+
+// A trip instance of the service date with what the realtime data last said about it
+pub struct RealizedTrip {
+    pub instance: TripInstance,
+    pub canceled: bool,
+    // By the instance's stops, None where no update ever predicted the call
+    pub calls: Vec<Option<RealizedCall>>,
+}
+
+// Applies TripUpdate snapshots, in the order given, to the trip instances of the date. Each call
+// keeps the last prediction made before its predicted departure, or the first one made after it
+// when there is none before. Delays carry on to later calls without updates, as in the spec. A
+// trip is canceled when its last update cancels it.
+pub fn realize_trips(feed: &Feed, date: NaiveDate, snapshots: &[FeedMessage]) -> Vec<RealizedTrip> {
+    let mut trips: Vec<RealizedTrip> = feed
+        .trip_instances(&feed.active_services(date))
+        .into_iter()
+        .map(|instance| RealizedTrip {
+            calls: vec![None; instance.stops.len()],
+            instance,
+            canceled: false,
+        })
+        .collect();
+    let mut by_trip: HashMap<String, Vec<usize>> = HashMap::new();
+    for (index, trip) in trips.iter().enumerate() {
+        by_trip
+            .entry(trip.instance.trip.trip_id.to_owned())
+            .or_default()
+            .push(index);
+    }
+    let day_start = service_day_start(date, feed.timezone());
+
+    for message in snapshots {
+        for entity in &message.entity {
+            let Some(update) = entity
+                .trip_update
+                .as_ref()
+                .filter(|_| entity.is_deleted != Some(true))
+            else {
+                continue;
+            };
+            let Some(index) = instance_index(&trips, &by_trip, &update.trip, date) else {
+                continue;
+            };
+            let trip = &mut trips[index];
+            let relationship = update.trip.schedule_relationship.unwrap_or_default();
+            trip.canceled = relationship == TripScheduleRelationship::Canceled as i32;
+            if trip.canceled {
+                continue;
+            }
+            let time = update
+                .timestamp
+                .or(message.header.timestamp)
+                .map_or(i64::MIN, |timestamp| timestamp as i64 - day_start);
+            for (call, prediction) in
+                predict_calls(&trip.instance, &update.stop_time_update, day_start)
+            {
+                let recorded = &mut trip.calls[call];
+                if recorded.is_none() || time <= prediction.departure {
+                    *recorded = Some(prediction);
+                }
+            }
+        }
+    }
+    trips
+}
+
+// The instance an update is about, by trip id, start date and for frequency based trips the
+// start time
+fn instance_index(
+    trips: &[RealizedTrip],
+    by_trip: &HashMap<String, Vec<usize>>,
+    descriptor: &proto::TripDescriptor,
+    date: NaiveDate,
+) -> Option<usize> {
+    let candidates = by_trip.get(descriptor.trip_id.as_ref()?)?;
+    if let Some(start_date) = &descriptor.start_date {
+        if parse_start_date(start_date)? != date {
+            return None;
+        }
+    }
+    match descriptor.start_time.as_deref().and_then(parse_start_time) {
+        Some(start_time) => candidates
+            .iter()
+            .copied()
+            .find(|index| trips[*index].instance.start_time() == start_time),
+        None if candidates.len() == 1 => Some(candidates[0]),
+        None => None,
+    }
+}
+
+// Predicted times of the calls an update covers, by their position in the instance
+pub fn predict_calls(
+    instance: &TripInstance,
+    updates: &[proto::StopTimeUpdate],
+    day_start: i64,
+) -> Vec<(usize, RealizedCall)> {
+    let calls: Vec<(Option<u64>, Option<&str>)> = instance
+        .stops
+        .iter()
+        .map(|stop| (Some(stop.stop_sequence), Some(stop.stop_id.as_str())))
+        .collect();
+    let mut by_call: Vec<Option<&proto::StopTimeUpdate>> = vec![None; calls.len()];
+    for (update, call) in updates.iter().zip(match_calls(&calls, updates)) {
+        if let Some(call) = call {
+            by_call[call] = Some(update);
+        }
+    }
+
+    let event_delay = |event: &Option<proto::StopTimeEvent>, scheduled: i64| {
+        let event = event.as_ref()?;
+        event
+            .time
+            .map(|time| time - day_start - scheduled)
+            .or(event.delay.map(i64::from))
+    };
+    let mut predictions: Vec<(usize, RealizedCall)> = Vec::new();
+    let mut delay: Option<i64> = None;
+    for (call, stop) in instance.stops.iter().enumerate() {
+        let (arrival, departure) = (stop.arrival as i64, stop.departure as i64);
+        let relationship =
+            by_call[call].map(|update| update.schedule_relationship.unwrap_or_default());
+        if relationship == Some(StopScheduleRelationship::Skipped as i32) {
+            predictions.push((
+                call,
+                RealizedCall {
+                    arrival,
+                    departure,
+                    skipped: true,
+                },
+            ));
+            continue;
+        }
+        if relationship == Some(StopScheduleRelationship::NoData as i32) {
+            delay = None;
+            continue;
+        }
+        let (arrival_delay, departure_delay) = match by_call[call] {
+            Some(update) => {
+                let arrival_delay = event_delay(&update.arrival, arrival);
+                let departure_delay = event_delay(&update.departure, departure);
+                (
+                    arrival_delay.or(delay).or(departure_delay),
+                    departure_delay.or(arrival_delay).or(delay),
+                )
+            }
+            None => (delay, delay),
+        };
+        let (Some(arrival_delay), Some(departure_delay)) = (arrival_delay, departure_delay) else {
+            continue;
+        };
+        delay = Some(departure_delay);
+        predictions.push((
+            call,
+            RealizedCall {
+                arrival: arrival + arrival_delay,
+                departure: (departure + departure_delay).max(arrival + arrival_delay),
+                skipped: false,
+            },
+        ));
+    }
+    predictions
+}
+
+#[derive(Debug, Serialize)]
+pub struct RealizedStopTime {
+    pub trip_id: String,
+    pub service_date: String,
+    pub start_time: Time,
+    pub stop_sequence: u64,
+    pub stop_id: String,
+    pub scheduled_arrival: Time,
+    pub scheduled_departure: Time,
+    pub realized_arrival: Option<Time>,
+    pub realized_departure: Option<Time>,
+    // Seconds, negative when early
+    pub arrival_delay: Option<i64>,
+    pub departure_delay: Option<i64>,
+    pub status: CallStatus,
+}
+
+fn time(seconds: i64) -> Time {
+    Time::from_seconds(seconds.max(0) as u64)
+}
+
+pub fn realized_stop_times(trips: &[RealizedTrip], date: NaiveDate) -> Vec<RealizedStopTime> {
+    let mut rows: Vec<RealizedStopTime> = Vec::new();
+    for trip in trips {
+        for (stop, call) in trip.instance.stops.iter().zip(&trip.calls) {
+            let (status, realized) = match call {
+                _ if trip.canceled => (CallStatus::Canceled, None),
+                Some(call) if call.skipped => (CallStatus::Skipped, None),
+                Some(call) => (CallStatus::Predicted, Some(call)),
+                None => (CallStatus::Scheduled, None),
+            };
+            rows.push(RealizedStopTime {
+                trip_id: trip.instance.trip.trip_id.to_owned(),
+                service_date: date.format("%Y%m%d").to_string(),
+                start_time: Time::from_seconds(trip.instance.start_time()),
+                stop_sequence: stop.stop_sequence,
+                stop_id: stop.stop_id.to_owned(),
+                scheduled_arrival: Time::from_seconds(stop.arrival),
+                scheduled_departure: Time::from_seconds(stop.departure),
+                realized_arrival: realized.map(|call| time(call.arrival)),
+                realized_departure: realized.map(|call| time(call.departure)),
+                arrival_delay: realized.map(|call| call.arrival - stop.arrival as i64),
+                departure_delay: realized.map(|call| call.departure - stop.departure as i64),
+                status,
+            });
+        }
+    }
+    rows
+}
+
+// The feed as it ran on the date: one service for the date, canceled trips and skipped stops
+// removed and realized times where there are any. Instances of frequency based trips become trips
+// of their own, named after the trip and their start time.
+pub fn realized_feed(feed: &Feed, date: NaiveDate, trips: &[RealizedTrip]) -> Feed {
+    let service_id = format!("realized_{}", date.format("%Y%m%d"));
+    let frequency_trips: HashSet<&str> = feed
+        .frequencies
+        .iter()
+        .map(|frequency| frequency.trip_id.as_str())
+        .collect();
+    let static_stop_times: HashMap<(&str, Option<u64>), &StopTime> = feed
+        .stop_times
+        .iter()
+        .map(|stop_time| {
+            (
+                (stop_time.trip_id.as_str(), stop_time.stop_sequence),
+                stop_time,
+            )
+        })
+        .collect();
+
+    let mut realized_trips: Vec<Trip> = Vec::new();
+    let mut stop_times: Vec<StopTime> = Vec::new();
+    for trip in trips.iter().filter(|trip| !trip.canceled) {
+        let static_trip = &trip.instance.trip;
+        let trip_id = if frequency_trips.contains(static_trip.trip_id.as_str()) {
+            let start = Time::from_seconds(trip.instance.start_time());
+            format!(
+                "{}_{:02}{:02}{:02}",
+                static_trip.trip_id, start.h, start.m, start.s
+            )
+        } else {
+            static_trip.trip_id.to_owned()
+        };
+
+        let mut trip_stop_times: Vec<StopTime> = Vec::new();
+        let mut previous = 0;
+        for (stop, call) in trip.instance.stops.iter().zip(&trip.calls) {
+            if call.is_some_and(|call| call.skipped) {
+                continue;
+            }
+            let Some(static_stop_time) =
+                static_stop_times.get(&(static_trip.trip_id.as_str(), Some(stop.stop_sequence)))
+            else {
+                continue;
+            };
+            let (arrival, departure) = call
+                .map_or((stop.arrival as i64, stop.departure as i64), |call| {
+                    (call.arrival, call.departure)
+                });
+            let arrival = arrival.max(previous);
+            previous = departure.max(arrival);
+            trip_stop_times.push(StopTime {
+                trip_id: trip_id.to_owned(),
+                arrival_time: Some(time(arrival)),
+                departure_time: Some(time(previous)),
+                ..(*static_stop_time).clone()
+            });
+        }
+        if trip_stop_times.len() < 2 {
+            continue;
+        }
+        stop_times.extend(trip_stop_times);
+        realized_trips.push(Trip {
+            trip_id,
+            service_id: service_id.to_owned(),
+            ..static_trip.clone()
+        });
+    }
+
+    Feed {
+        trips: realized_trips,
+        stop_times,
+        calendars: Vec::new(),
+        calendar_dates: vec![CalendarDate {
+            service_id,
+            date,
+            exception_type: CalendarException::Added,
+        }],
+        frequencies: Vec::new(),
+        ..feed.clone()
+    }
+}
+
+#[test]
+fn test_realize_trips() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let feed = Feed::load(&mut gtfs_file);
+    let date = NaiveDate::from_ymd_opt(2007, 6, 5).unwrap();
+
+    // A quarter past eight, with Bullfrog a minute later than first predicted, the 06:30 shuttle
+    // canceled and the 08:00 city loop skipping North Ave / D Ave N
+    let later = r#"{"header": {"gtfsRealtimeVersion": "2.0", "timestamp": "1181056500"}, "entity": [
+        {"id": "1", "tripUpdate": {"trip": {"tripId": "AB1"}, "stopTimeUpdate": [
+            {"stopSequence": 2, "arrival": {"time": "1181056380"}}]}},
+        {"id": "2", "tripUpdate": {"trip": {"tripId": "STBA", "startTime": "06:30:00", "scheduleRelationship": "CANCELED"}}},
+        {"id": "3", "tripUpdate": {"trip": {"tripId": "CITY1", "startTime": "08:00:00"}, "stopTimeUpdate": [
+            {"stopSequence": 3, "scheduleRelationship": "SKIPPED"},
+            {"stopSequence": 4, "departure": {"delay": 60}}]}}
+    ]}"#;
+    let snapshots = vec![
+        read_feed_message(&PathBuf::from("test_data/sample-rt-1.json")).unwrap(),
+        decode_feed_message(later.as_bytes()).unwrap(),
+    ];
+    let trips = realize_trips(&feed, date, &snapshots);
+    let rows = realized_stop_times(&trips, date);
+    let row = |trip_id: &str, start: &str, sequence: u64| {
+        rows.iter()
+            .find(|row| {
+                row.trip_id == trip_id
+                    && row.start_time.to_string() == start
+                    && row.stop_sequence == sequence
+            })
+            .unwrap()
+    };
+
+    let airport = row("AB1", "08:00:00", 1);
+    assert_eq!(
+        airport.realized_departure.as_ref().unwrap().to_string(),
+        "08:02:00"
+    );
+    assert_eq!(airport.departure_delay, Some(120));
+    let bullfrog = row("AB1", "08:00:00", 2);
+    assert_eq!(
+        bullfrog.realized_arrival.as_ref().unwrap().to_string(),
+        "08:13:00"
+    );
+    assert_eq!(
+        bullfrog.realized_departure.as_ref().unwrap().to_string(),
+        "08:18:00"
+    );
+    assert_eq!(row("STBA", "06:30:00", 1).status, CallStatus::Canceled);
+    assert_eq!(row("STBA", "06:00:00", 1).status, CallStatus::Scheduled);
+    assert_eq!(row("CITY1", "08:00:00", 3).status, CallStatus::Skipped);
+    assert_eq!(row("CITY1", "08:00:00", 5).arrival_delay, Some(60));
+
+    let realized = realized_feed(&feed, date, &trips);
+    assert!(realized
+        .trips
+        .iter()
+        .any(|trip| trip.trip_id == "STBA_060000"));
+    assert!(!realized
+        .trips
+        .iter()
+        .any(|trip| trip.trip_id == "STBA_063000"));
+    let city: Vec<&StopTime> = realized
+        .stop_times
+        .iter()
+        .filter(|stop_time| stop_time.trip_id == "CITY1_080000")
+        .collect();
+    assert_eq!(city.len(), 4);
+    assert_eq!(
+        city[2].departure_time.as_ref().unwrap().to_string(),
+        "08:22:00"
+    );
+
+    // Written and read back, with the fares copied over
+    // One file per test process, so that parallel runs don't overwrite each other's feed
+    let output =
+        std::env::temp_dir().join(format!("gtfs-tooling-realized-{}.zip", std::process::id()));
+    realized.write(&output, &mut gtfs_file).unwrap();
+    let mut written = gtfs::GtfsFile::new(&output).unwrap();
+    assert!(written
+        .list_files()
+        .contains(&String::from("fare_rules.txt")));
+    let reloaded = Feed::load(&mut written);
+    assert_eq!(reloaded.stop_times.len(), realized.stop_times.len());
+    assert!(reloaded.frequencies.is_empty());
+    assert_eq!(reloaded.active_services(date).len(), 1);
+    std::fs::remove_file(output).unwrap();
+}
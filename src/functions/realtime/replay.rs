@@ -0,0 +1,261 @@
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use prost::Message;
+use tiny_http::{Header, Response, Server};
+
+use super::format_timestamp;
+use super::proto::{self, FeedEntity, FeedMessage};
+#[cfg(test)]
+use super::proto::{Alert, FeedHeader, TripUpdate, VehiclePosition};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Endpoint {
+    TripUpdates,
+    VehiclePositions,
+    Alerts,
+}
+
+impl Endpoint {
+    fn from_path(path: &str) -> Option<Endpoint> {
+        match path {
+            "/trip-updates" => Some(Endpoint::TripUpdates),
+            "/vehicle-positions" => Some(Endpoint::VehiclePositions),
+            "/alerts" => Some(Endpoint::Alerts),
+            _ => None,
+        }
+    }
+}
+
+// Everything known at one time, from all snapshot files with that header timestamp
+struct Snapshot {
+    timestamp: u64,
+    trip_updates: Vec<FeedEntity>,
+    vehicle_positions: Vec<FeedEntity>,
+    alerts: Vec<FeedEntity>,
+}
+
+pub struct Replay {
+    snapshots: Vec<Snapshot>,
+}
+
+impl Replay {
+    // Merges the snapshots by header timestamp and splits their entities by kind, so trip updates,
+    // vehicle positions and alerts can come from the same or from separate files. Snapshots
+    // without a timestamp can't be placed and are left out.
+    pub fn new(messages: Vec<FeedMessage>) -> Result<Replay, String> {
+        let mut snapshots: BTreeMap<u64, Snapshot> = BTreeMap::new();
+        for message in messages {
+            let Some(timestamp) = message.header.timestamp else {
+                continue;
+            };
+            let snapshot = snapshots.entry(timestamp).or_insert_with(|| Snapshot {
+                timestamp,
+                trip_updates: Vec::new(),
+                vehicle_positions: Vec::new(),
+                alerts: Vec::new(),
+            });
+            for entity in message.entity {
+                if entity.trip_update.is_some() {
+                    snapshot.trip_updates.push(entity.clone());
+                }
+                if entity.vehicle.is_some() {
+                    snapshot.vehicle_positions.push(entity.clone());
+                }
+                if entity.alert.is_some() {
+                    snapshot.alerts.push(entity);
+                }
+            }
+        }
+        if snapshots.is_empty() {
+            return Err(String::from("No snapshots with a header timestamp"));
+        }
+        Ok(Replay {
+            snapshots: snapshots.into_values().collect(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn first_timestamp(&self) -> u64 {
+        self.snapshots[0].timestamp
+    }
+
+    pub fn last_timestamp(&self) -> u64 {
+        self.snapshots[self.snapshots.len() - 1].timestamp
+    }
+
+    // The latest snapshot at a time, or the first one before the replay starts
+    fn at(&self, time: u64) -> &Snapshot {
+        let index = self
+            .snapshots
+            .partition_point(|snapshot| snapshot.timestamp <= time);
+        &self.snapshots[index.saturating_sub(1)]
+    }
+
+    fn feed_message(&self, endpoint: Endpoint, time: u64) -> FeedMessage {
+        let snapshot = self.at(time);
+        let entity = match endpoint {
+            Endpoint::TripUpdates => &snapshot.trip_updates,
+            Endpoint::VehiclePositions => &snapshot.vehicle_positions,
+            Endpoint::Alerts => &snapshot.alerts,
+        };
+        FeedMessage {
+            header: proto::FeedHeader {
+                gtfs_realtime_version: String::from("2.0"),
+                incrementality: Some(proto::Incrementality::FullDataset as i32),
+                timestamp: Some(snapshot.timestamp),
+            },
+            entity: entity.clone(),
+        }
+    }
+}
+
+// Replay time advancing from the first snapshot at some multiple of real time, holding at the last
+// snapshot or starting over
+#[derive(Debug, Clone, Copy)]
+pub struct ReplayClock {
+    pub speed: f64,
+    pub looping: bool,
+}
+
+impl ReplayClock {
+    fn time(&self, replay: &Replay, elapsed_s: f64) -> u64 {
+        let (first, last) = (replay.first_timestamp(), replay.last_timestamp());
+        let mut offset = (elapsed_s * self.speed) as u64;
+        if self.looping {
+            // Wrapping a second after the last snapshot, so it is served too
+            offset %= last - first + 1;
+        }
+        first.saturating_add(offset).min(last)
+    }
+}
+
+// Status code, content type and body of the response to a request path, with `?format=json` for
+// the JSON form of a feed message
+fn respond(replay: &Replay, url: &str, time: u64) -> (u16, &'static str, Vec<u8>) {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let json = query.split('&').any(|parameter| parameter == "format=json");
+    if path == "/" {
+        let status = format!(
+            "Replaying {} snapshots from {} to {}, now at {}\n/trip-updates\n/vehicle-positions\n/alerts\n",
+            replay.len(),
+            format_timestamp(replay.first_timestamp() as i64),
+            format_timestamp(replay.last_timestamp() as i64),
+            format_timestamp(time as i64),
+        );
+        return (200, "text/plain", status.into_bytes());
+    }
+    let Some(endpoint) = Endpoint::from_path(path) else {
+        return (404, "text/plain", b"Not found\n".to_vec());
+    };
+    let message = replay.feed_message(endpoint, time);
+    if json {
+        (
+            200,
+            "application/json",
+            serde_json::to_vec_pretty(&message).expect("Unable to serialize json"),
+        )
+    } else {
+        (200, "application/x-protobuf", message.encode_to_vec())
+    }
+}
+
+// Serves the replay on a local port until the process is stopped
+pub fn serve(replay: &Replay, port: u16, clock: ReplayClock) -> Result<(), String> {
+    let server = Server::http(("127.0.0.1", port)).map_err(|error| error.to_string())?;
+    println!(
+        "Replaying {} snapshots at {}x on http://{}",
+        replay.len(),
+        clock.speed,
+        server.server_addr()
+    );
+    let started = Instant::now();
+    for request in server.incoming_requests() {
+        let time = clock.time(replay, started.elapsed().as_secs_f64());
+        let (status, content_type, body) = respond(replay, request.url(), time);
+        let header = Header::from_bytes(&b"Content-Type"[..], content_type.as_bytes())
+            .expect("valid header");
+        let response = Response::from_data(body)
+            .with_status_code(status)
+            .with_header(header);
+        if let Err(error) = request.respond(response) {
+            println!("{}", error);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_replay() {
+    let message = |timestamp: u64, entity: Vec<FeedEntity>| FeedMessage {
+        header: FeedHeader {
+            gtfs_realtime_version: String::from("2.0"),
+            incrementality: None,
+            timestamp: Some(timestamp),
+        },
+        entity,
+    };
+    let update = FeedEntity {
+        id: String::from("trip"),
+        trip_update: Some(TripUpdate::default()),
+        ..Default::default()
+    };
+    let vehicle = FeedEntity {
+        id: String::from("vehicle"),
+        vehicle: Some(VehiclePosition::default()),
+        ..Default::default()
+    };
+    let alert = FeedEntity {
+        id: String::from("alert"),
+        alert: Some(Alert::default()),
+        ..Default::default()
+    };
+    // Trip updates and vehicle positions from separate files, the vehicle gone at the second time
+    let replay = Replay::new(vec![
+        message(1030, vec![update.clone()]),
+        message(1000, vec![update, alert]),
+        message(1000, vec![vehicle]),
+        message(1030, Vec::new()),
+    ])
+    .unwrap();
+    assert_eq!(replay.len(), 2);
+
+    let decode = |url: &str, time: u64| {
+        let (status, content_type, body) = respond(&replay, url, time);
+        assert_eq!((status, content_type), (200, "application/x-protobuf"));
+        FeedMessage::decode(body.as_slice()).unwrap()
+    };
+    assert_eq!(decode("/vehicle-positions", 900).entity.len(), 1);
+    assert_eq!(
+        decode("/vehicle-positions", 1029).header.timestamp,
+        Some(1000)
+    );
+    assert!(decode("/vehicle-positions", 1030).entity.is_empty());
+    assert_eq!(decode("/trip-updates", 5000).entity[0].id, "trip");
+    assert_eq!(decode("/alerts", 1000).entity[0].id, "alert");
+    assert_eq!(
+        respond(&replay, "/alerts?format=json", 1000).1,
+        "application/json"
+    );
+    assert_eq!(respond(&replay, "/stops", 1000).0, 404);
+
+    let clock = ReplayClock {
+        speed: 10.0,
+        looping: false,
+    };
+    assert_eq!(clock.time(&replay, 2.0), 1020);
+    assert_eq!(clock.time(&replay, 60.0), 1030);
+    let clock = ReplayClock {
+        speed: 1e30,
+        looping: false,
+    };
+    assert_eq!(clock.time(&replay, 1.0), 1030);
+    let clock = ReplayClock {
+        speed: 10.0,
+        looping: true,
+    };
+    assert_eq!(clock.time(&replay, 4.0), 1009);
+}
@@ -0,0 +1,504 @@
+use std::collections::HashMap;
+use std::path::Path;
+#[cfg(test)]
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use prost::Message;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rand_distr::{Distribution, Normal};
+
+use crate::objects::feed::Feed;
+use crate::objects::trip::TripInstance;
+
+use super::super::geodata;
+#[cfg(test)]
+use super::super::gtfs;
+use super::super::gtfs::{RouteType, Time};
+use super::proto::{
+    self, FeedMessage, StopScheduleRelationship, TripScheduleRelationship, VehicleStopStatus,
+};
+#[cfg(test)]
+use super::read_snapshots;
+use super::service_day_start;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SyntheticSettings {
+    // Seconds since the start of the service day
+    pub start: u64,
+    pub end: u64,
+    pub interval_s: u64,
+    pub cancel_probability: f64,
+    pub detour_probability: f64,
+    pub seed: u64,
+}
+
+// The delay at the first stop and its change from one stop to the next, in seconds
+struct DelayModel {
+    initial: Normal<f64>,
+    per_stop: Normal<f64>,
+}
+
+fn delay_model(route_type: Option<&RouteType>) -> DelayModel {
+    let ((initial_mean, initial_sd), (per_stop_mean, per_stop_sd)) =
+        match route_type.map(RouteType::code) {
+            // Trams
+            Some(0 | 900..=999) => ((30.0, 60.0), (5.0, 20.0)),
+            // Metros run closest to the timetable
+            Some(1 | 400..=499) => ((10.0, 30.0), (2.0, 10.0)),
+            // Rail
+            Some(2 | 100..=199) => ((60.0, 120.0), (5.0, 30.0)),
+            // Ferries
+            Some(4 | 1000..=1299) => ((60.0, 120.0), (0.0, 30.0)),
+            // Buses, and anything else, which mostly runs in traffic
+            _ => ((60.0, 90.0), (10.0, 30.0)),
+        };
+    DelayModel {
+        initial: Normal::new(initial_mean, initial_sd).expect("valid delay model"),
+        per_stop: Normal::new(per_stop_mean, per_stop_sd).expect("valid delay model"),
+    }
+}
+
+// A trip instance as it runs in the simulation
+pub struct SimulatedTrip {
+    pub instance: TripInstance,
+    pub canceled: bool,
+    // Arrival and departure in seconds since the start of the service day, None for stops left
+    // out by a detour
+    pub calls: Vec<Option<(u64, u64)>>,
+    pub vehicle_id: String,
+    // The shape or the line through the stops, with the distance of each stop along it
+    path: Vec<geo_types::Point>,
+    distances: Vec<f64>,
+}
+
+// Runs every trip instance of the date overlapping the settings' time span, with delays drawn
+// from the model of its route type. Early vehicles only wait for their departure at the first
+// stop. Detours leave out one or two stops in the middle of the trip and add a few minutes.
+pub fn simulate_trips(
+    feed: &Feed,
+    date: NaiveDate,
+    settings: &SyntheticSettings,
+) -> Vec<SimulatedTrip> {
+    let route_types: HashMap<&str, Option<&RouteType>> = feed
+        .routes
+        .iter()
+        .map(|route| (route.route_id.as_str(), route.route_type.as_ref()))
+        .collect();
+    let stop_points: HashMap<&str, geo_types::Point> = feed
+        .stops
+        .iter()
+        .filter_map(|stop| Some((stop.stop_id.as_str(), geodata::stop_point(stop)?)))
+        .collect();
+    let shape_lines = geodata::shape_lines(&feed.shapes);
+    let mut rng = StdRng::seed_from_u64(settings.seed);
+
+    let mut trips: Vec<SimulatedTrip> = Vec::new();
+    for instance in feed.trip_instances(&feed.active_services(date)) {
+        if instance.start_time() > settings.end || instance.end_time() < settings.start {
+            continue;
+        }
+        let model = delay_model(
+            route_types
+                .get(instance.trip.route_id.as_str())
+                .copied()
+                .flatten(),
+        );
+        let (path, distances) = trip_path(&instance, &stop_points, &shape_lines);
+        let canceled = rng.gen_bool(settings.cancel_probability);
+        let stops = instance.stops.len();
+        let detour =
+            (!canceled && stops >= 3 && rng.gen_bool(settings.detour_probability)).then(|| {
+                let first = rng.gen_range(1..stops - 1);
+                let last = (first + rng.gen_range(0..2)).min(stops - 2);
+                (first, last, rng.gen_range(120.0..300.0))
+            });
+
+        let mut calls: Vec<Option<(u64, u64)>> = Vec::new();
+        let mut delay: f64 = model.initial.sample(&mut rng).max(-60.0);
+        let mut previous: f64 = 0.0;
+        for (index, stop) in instance.stops.iter().enumerate() {
+            if index > 0 {
+                delay += model.per_stop.sample(&mut rng);
+            }
+            if let Some((first, last, extra)) = detour {
+                if index == first {
+                    delay += extra;
+                }
+                if (first..=last).contains(&index) {
+                    calls.push(None);
+                    continue;
+                }
+            }
+            let arrival = (stop.arrival as f64 + delay).max(previous + 1.0).max(0.0);
+            let mut departure = (stop.departure as f64 + delay).max(arrival);
+            if index == 0 {
+                departure = departure.max(stop.departure as f64);
+            }
+            delay = departure - stop.departure as f64;
+            previous = departure;
+            calls.push(Some((arrival.round() as u64, departure.round() as u64)));
+        }
+
+        let start = Time::from_seconds(instance.start_time());
+        let vehicle_id = instance
+            .trip
+            .block_id
+            .clone()
+            .filter(|block_id| !block_id.is_empty())
+            .unwrap_or_else(|| format!("{}-{}", instance.trip.trip_id, start));
+        trips.push(SimulatedTrip {
+            instance,
+            canceled,
+            calls,
+            vehicle_id,
+            path,
+            distances,
+        });
+    }
+    trips
+}
+
+// The trip's shape with its stops projected onto it in order, or else the line through its stops
+fn trip_path(
+    instance: &TripInstance,
+    stop_points: &HashMap<&str, geo_types::Point>,
+    shape_lines: &HashMap<String, Vec<geo_types::Point>>,
+) -> (Vec<geo_types::Point>, Vec<f64>) {
+    let Some(points) = instance
+        .stops
+        .iter()
+        .map(|stop| stop_points.get(stop.stop_id.as_str()).copied())
+        .collect::<Option<Vec<geo_types::Point>>>()
+    else {
+        return (Vec::new(), Vec::new());
+    };
+
+    if let Some(line) = instance
+        .trip
+        .shape_id
+        .as_ref()
+        .and_then(|shape_id| shape_lines.get(shape_id))
+    {
+        let mut segment = 0;
+        let mut distances: Vec<f64> = Vec::new();
+        for point in &points {
+            let Some((found, along, _)) = geodata::project_onto_line(line, *point, segment) else {
+                break;
+            };
+            segment = found;
+            distances.push(along);
+        }
+        if distances.len() == points.len() {
+            return (line.clone(), distances);
+        }
+    }
+
+    let mut distances: Vec<f64> = vec![0.0];
+    for pair in points.windows(2) {
+        distances
+            .push(distances[distances.len() - 1] + geodata::haversine_distance(pair[0], pair[1]));
+    }
+    (points, distances)
+}
+
+fn trip_descriptor(trip: &SimulatedTrip, date: NaiveDate) -> proto::TripDescriptor {
+    let relationship = if trip.canceled {
+        TripScheduleRelationship::Canceled
+    } else {
+        TripScheduleRelationship::Scheduled
+    };
+    proto::TripDescriptor {
+        trip_id: Some(trip.instance.trip.trip_id.to_owned()),
+        start_time: Some(Time::from_seconds(trip.instance.start_time()).to_string()),
+        start_date: Some(date.format("%Y%m%d").to_string()),
+        schedule_relationship: Some(relationship as i32),
+        route_id: Some(trip.instance.trip.route_id.to_owned()),
+        direction_id: trip
+            .instance
+            .trip
+            .direction_id
+            .map(|direction| direction as u32),
+    }
+}
+
+fn feed_message(timestamp: u64, entity: Vec<proto::FeedEntity>) -> FeedMessage {
+    FeedMessage {
+        header: proto::FeedHeader {
+            gtfs_realtime_version: String::from("2.0"),
+            incrementality: Some(proto::Incrementality::FullDataset as i32),
+            timestamp: Some(timestamp),
+        },
+        entity,
+    }
+}
+
+// What a realtime system would publish at a time, in seconds since the start of the service day:
+// trip updates from half an hour before a trip starts until it ends, predicting the delay it last
+// left a stop with for all stops ahead, and positions of vehicles from five minutes before they
+// leave
+pub fn snapshot(
+    trips: &[SimulatedTrip],
+    date: NaiveDate,
+    day_start: i64,
+    time: u64,
+) -> (FeedMessage, FeedMessage) {
+    let timestamp = (day_start + time as i64) as u64;
+    let mut updates: Vec<proto::FeedEntity> = Vec::new();
+    let mut vehicles: Vec<proto::FeedEntity> = Vec::new();
+    for trip in trips {
+        let stops = &trip.instance.stops;
+        let visited: Vec<(usize, u64, u64)> = trip
+            .calls
+            .iter()
+            .enumerate()
+            .filter_map(|(index, call)| {
+                call.map(|(arrival, departure)| (index, arrival, departure))
+            })
+            .collect();
+        let end = if trip.canceled {
+            trip.instance.end_time()
+        } else {
+            visited.last().map_or(0, |(_, arrival, _)| *arrival)
+        };
+        if time + 1800 < trip.instance.start_time() || time > end {
+            continue;
+        }
+        let id = format!(
+            "{}-{}",
+            trip.instance.trip.trip_id,
+            Time::from_seconds(trip.instance.start_time())
+        );
+        let vehicle = proto::VehicleDescriptor {
+            id: Some(trip.vehicle_id.to_owned()),
+            ..Default::default()
+        };
+
+        let mut stop_time_updates: Vec<proto::StopTimeUpdate> = Vec::new();
+        if !trip.canceled {
+            let known_delay = visited
+                .iter()
+                .rev()
+                .find(|(_, _, departure)| *departure <= time)
+                .or(visited.first())
+                .map_or(0, |(index, _, departure)| {
+                    *departure as i64 - stops[*index].departure as i64
+                });
+            for (index, (stop, call)) in stops.iter().zip(&trip.calls).enumerate() {
+                let event = |predicted: i64, scheduled: u64| proto::StopTimeEvent {
+                    delay: Some((predicted - scheduled as i64) as i32),
+                    time: Some(day_start + predicted),
+                    uncertainty: None,
+                };
+                let mut update = proto::StopTimeUpdate {
+                    stop_sequence: Some(stop.stop_sequence as u32),
+                    stop_id: Some(stop.stop_id.to_owned()),
+                    ..Default::default()
+                };
+                match call {
+                    Some((_, departure)) if *departure <= time => continue,
+                    Some((arrival, _)) => {
+                        let arrival = if *arrival <= time {
+                            *arrival as i64
+                        } else {
+                            stop.arrival as i64 + known_delay
+                        };
+                        let departure = (stop.departure as i64 + known_delay).max(arrival);
+                        if index > 0 {
+                            update.arrival = Some(event(arrival, stop.arrival));
+                        }
+                        update.departure = Some(event(departure, stop.departure));
+                    }
+                    // Detours are announced before the vehicle gets there
+                    None if visited
+                        .iter()
+                        .any(|(visited, _, departure)| *visited > index && *departure <= time) =>
+                    {
+                        continue
+                    }
+                    None => {
+                        update.schedule_relationship =
+                            Some(StopScheduleRelationship::Skipped as i32)
+                    }
+                }
+                stop_time_updates.push(update);
+            }
+        }
+        updates.push(proto::FeedEntity {
+            id: format!("trip-{}", id),
+            trip_update: Some(proto::TripUpdate {
+                trip: trip_descriptor(trip, date),
+                stop_time_update: stop_time_updates,
+                vehicle: Some(vehicle.clone()),
+                timestamp: Some(timestamp),
+                delay: None,
+            }),
+            ..Default::default()
+        });
+
+        if trip.canceled || trip.path.len() < 2 {
+            continue;
+        }
+        let Some(first) = visited.first() else {
+            continue;
+        };
+        if time + 300 < first.2 {
+            continue;
+        }
+        // The last stop reached, and where the vehicle is between it and the next
+        let reached = visited
+            .iter()
+            .rposition(|(_, arrival, _)| *arrival <= time)
+            .unwrap_or(0);
+        let (index, _, departure) = visited[reached];
+        let (status, stop_index, distance, speed) = match visited.get(reached + 1) {
+            Some((next, arrival, _)) if time > departure => {
+                let fraction = (time - departure) as f64 / (*arrival - departure).max(1) as f64;
+                let length = trip.distances[*next] - trip.distances[index];
+                let speed = length / (*arrival - departure).max(1) as f64;
+                (
+                    VehicleStopStatus::InTransitTo,
+                    *next,
+                    trip.distances[index] + length * fraction.min(1.0),
+                    speed,
+                )
+            }
+            _ => (
+                VehicleStopStatus::StoppedAt,
+                index,
+                trip.distances[index],
+                0.0,
+            ),
+        };
+        let Some(point) = geodata::point_along_line(&trip.path, distance) else {
+            continue;
+        };
+        vehicles.push(proto::FeedEntity {
+            id: format!("vehicle-{}", id),
+            vehicle: Some(proto::VehiclePosition {
+                trip: Some(trip_descriptor(trip, date)),
+                position: Some(proto::Position {
+                    latitude: point.y() as f32,
+                    longitude: point.x() as f32,
+                    speed: Some(speed as f32),
+                    ..Default::default()
+                }),
+                current_stop_sequence: Some(stops[stop_index].stop_sequence as u32),
+                current_status: Some(status as i32),
+                timestamp: Some(timestamp),
+                stop_id: Some(stops[stop_index].stop_id.to_owned()),
+                vehicle: Some(vehicle),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+    }
+    (
+        feed_message(timestamp, updates),
+        feed_message(timestamp, vehicles),
+    )
+}
+
+// Writes a snapshot of trip updates and one of vehicle positions for every interval of the time
+// span into the directory, named after their unix time. Returns the number of snapshot times.
+pub fn write_series(
+    feed: &Feed,
+    date: NaiveDate,
+    settings: &SyntheticSettings,
+    directory: &Path,
+) -> Result<usize, String> {
+    std::fs::create_dir_all(directory)
+        .map_err(|error| format!("{}: {}", directory.display(), error))?;
+    let trips = simulate_trips(feed, date, settings);
+    let day_start = service_day_start(date, feed.timezone());
+    let mut count = 0;
+    for time in (settings.start..=settings.end).step_by(settings.interval_s.max(1) as usize) {
+        let (updates, vehicles) = snapshot(&trips, date, day_start, time);
+        let timestamp = day_start + time as i64;
+        for (name, message) in [("trip_updates", updates), ("vehicle_positions", vehicles)] {
+            let path = directory.join(format!("{}_{}.pb", name, timestamp));
+            std::fs::write(&path, message.encode_to_vec())
+                .map_err(|error| format!("{}: {}", path.display(), error))?;
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[test]
+fn test_simulate_trips() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let feed = Feed::load(&mut gtfs_file);
+    let date = NaiveDate::from_ymd_opt(2007, 6, 5).unwrap();
+    let day_start = service_day_start(date, feed.timezone());
+    let mut settings = SyntheticSettings {
+        start: 8 * 3600,
+        end: 8 * 3600 + 20 * 60,
+        interval_s: 600,
+        cancel_probability: 0.0,
+        detour_probability: 0.0,
+        seed: 7,
+    };
+
+    let trips = simulate_trips(&feed, date, &settings);
+    let again = simulate_trips(&feed, date, &settings);
+    assert!(trips.iter().zip(&again).all(|(a, b)| a.calls == b.calls));
+    let (updates, vehicles) = snapshot(&trips, date, day_start, 8 * 3600 + 5 * 60);
+    let update = updates
+        .entity
+        .iter()
+        .find_map(|entity| {
+            entity
+                .trip_update
+                .as_ref()
+                .filter(|update| update.trip.trip_id.as_deref() == Some("AB1"))
+        })
+        .unwrap();
+    let bullfrog = update.stop_time_update.last().unwrap();
+    assert_eq!(bullfrog.stop_id.as_deref(), Some("BULLFROG"));
+    assert!(bullfrog.arrival.as_ref().unwrap().time.unwrap() > day_start + 8 * 3600);
+    // Somewhere between the airport and Bullfrog
+    let position = vehicles
+        .entity
+        .iter()
+        .find_map(|entity| {
+            entity
+                .vehicle
+                .as_ref()
+                .filter(|vehicle| vehicle.trip.as_ref().unwrap().trip_id.as_deref() == Some("AB1"))
+        })
+        .and_then(|vehicle| vehicle.position.clone())
+        .unwrap();
+    assert!(position.latitude >= 36.868 && position.latitude <= 36.882);
+
+    settings.cancel_probability = 1.0;
+    let trips = simulate_trips(&feed, date, &settings);
+    let (updates, vehicles) = snapshot(&trips, date, day_start, 8 * 3600);
+    assert!(updates.entity.iter().all(|entity| entity
+        .trip_update
+        .as_ref()
+        .unwrap()
+        .trip
+        .schedule_relationship
+        == Some(TripScheduleRelationship::Canceled as i32)));
+    assert!(vehicles.entity.is_empty());
+
+    settings.cancel_probability = 0.0;
+    settings.detour_probability = 1.0;
+    let trips = simulate_trips(&feed, date, &settings);
+    let city = trips
+        .iter()
+        .find(|trip| trip.instance.trip.trip_id == "CITY1")
+        .unwrap();
+    assert!(city.calls.iter().any(Option::is_none));
+    assert!(city.calls.first().unwrap().is_some() && city.calls.last().unwrap().is_some());
+
+    // One directory per test process, so that parallel runs don't mix their snapshots
+    let directory =
+        std::env::temp_dir().join(format!("gtfs-tooling-synthetic-{}", std::process::id()));
+    assert_eq!(write_series(&feed, date, &settings, &directory).unwrap(), 3);
+    assert_eq!(read_snapshots(&directory).unwrap().len(), 6);
+    std::fs::remove_dir_all(directory).unwrap();
+}
@@ -0,0 +1,243 @@
+use std::collections::{BTreeMap, HashMap};
+#[cfg(test)]
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::objects::feed::Feed;
+
+use super::super::geodata;
+#[cfg(test)]
+use super::super::gtfs;
+use super::super::round;
+#[cfg(test)]
+use super::super::to_geojson_string;
+use super::proto::{FeedMessage, VehiclePosition, VehicleStopStatus};
+#[cfg(test)]
+use super::read_feed_message;
+use super::{enum_name, format_timestamp, StaticIndex};
+
+// A reported vehicle position, with the speed since the vehicle's previous position on the same
+// trip and how far along the trip's shape it is
+#[derive(Debug, Serialize)]
+pub struct PositionPoint {
+    #[serde(serialize_with = "geojson::ser::serialize_geometry")]
+    pub geometry: geo_types::Point,
+    pub timestamp: u64,
+    pub time: String,
+    pub vehicle_id: String,
+    pub trip_id: Option<String>,
+    pub start_date: Option<String>,
+    pub route_id: Option<String>,
+    pub stop_id: Option<String>,
+    pub current_status: Option<String>,
+    pub reported_speed_mps: Option<f32>,
+    pub speed_mps: Option<f64>,
+    // Snapped onto the shape, or the line through the stops for trips without one
+    pub shape_distance_m: Option<f64>,
+    pub shape_offset_m: Option<f64>,
+    pub progress_pct: Option<f64>,
+}
+
+// The positions of one vehicle on one trip, with a timestamp for every vertex
+#[derive(Debug, Serialize)]
+pub struct Trajectory {
+    #[serde(serialize_with = "geojson::ser::serialize_geometry")]
+    pub geometry: geo_types::LineString,
+    pub vehicle_id: String,
+    pub trip_id: Option<String>,
+    pub start_date: Option<String>,
+    pub route_id: Option<String>,
+    pub first_timestamp: u64,
+    pub last_timestamp: u64,
+    pub timestamps: Vec<u64>,
+    pub distance_m: f64,
+    pub mean_speed_mps: Option<f64>,
+    pub max_speed_mps: Option<f64>,
+}
+
+type VehicleTrip = (String, Option<String>, Option<String>);
+
+// Every vehicle position of the snapshots in order of time. Positions repeated in later snapshots
+// with the same timestamp are only kept once. Vehicles are identified by their descriptor, or by
+// the entity id where there is none.
+pub fn position_points(feed: &Feed, messages: &[FeedMessage]) -> Vec<PositionPoint> {
+    let index = StaticIndex::new(feed);
+    let shape_lines = geodata::shape_lines(&feed.shapes);
+
+    let mut tracks: BTreeMap<VehicleTrip, Vec<(u64, &VehiclePosition)>> = BTreeMap::new();
+    for message in messages {
+        for entity in &message.entity {
+            let Some(vehicle) = &entity.vehicle else {
+                continue;
+            };
+            let Some(timestamp) = vehicle.timestamp.or(message.header.timestamp) else {
+                continue;
+            };
+            if vehicle.position.is_none() {
+                continue;
+            }
+            let vehicle_id = vehicle
+                .vehicle
+                .as_ref()
+                .and_then(|descriptor| descriptor.id.clone().or(descriptor.label.clone()))
+                .unwrap_or(entity.id.to_owned());
+            let trip = vehicle.trip.as_ref();
+            let key = (
+                vehicle_id,
+                trip.and_then(|trip| trip.trip_id.clone()),
+                trip.and_then(|trip| trip.start_date.clone()),
+            );
+            tracks.entry(key).or_default().push((timestamp, vehicle));
+        }
+    }
+
+    let mut lines: HashMap<&str, (Vec<geo_types::Point>, f64)> = HashMap::new();
+    let mut points: Vec<PositionPoint> = Vec::new();
+    for ((vehicle_id, trip_id, start_date), mut track) in tracks {
+        track.sort_by_key(|(timestamp, _)| *timestamp);
+        track.dedup_by_key(|(timestamp, _)| *timestamp);
+
+        let trip = trip_id
+            .as_deref()
+            .and_then(|trip_id| index.trips.get(trip_id));
+        let line = trip.map(|trip| {
+            &*lines.entry(trip.trip_id.as_str()).or_insert_with(|| {
+                let line = index.trip_line(trip, &shape_lines);
+                let length = geodata::line_length(&line);
+                (line, length)
+            })
+        });
+        let mut previous: Option<(u64, geo_types::Point)> = None;
+        let mut segment = 0;
+        for (timestamp, vehicle) in track {
+            let Some(position) = &vehicle.position else {
+                continue;
+            };
+            let point = geo_types::Point::new(position.longitude as f64, position.latitude as f64);
+            let speed =
+                previous
+                    .filter(|(previous, _)| timestamp > *previous)
+                    .map(|(previous, last)| {
+                        round(
+                            geodata::haversine_distance(last, point)
+                                / (timestamp - previous) as f64,
+                            2,
+                        )
+                    });
+            previous = Some((timestamp, point));
+
+            let projection = line
+                .filter(|(line, _)| line.len() >= 2)
+                .and_then(|(line, length)| {
+                    Some((geodata::project_onto_line(line, point, segment)?, *length))
+                });
+            if let Some(((found, _, _), _)) = projection {
+                segment = found;
+            }
+            let route_id = vehicle
+                .trip
+                .as_ref()
+                .and_then(|trip| trip.route_id.clone())
+                .or(trip.map(|trip| trip.route_id.to_owned()));
+            points.push(PositionPoint {
+                geometry: point,
+                timestamp,
+                time: format_timestamp(timestamp as i64),
+                vehicle_id: vehicle_id.to_owned(),
+                trip_id: trip_id.clone(),
+                start_date: start_date.clone(),
+                route_id,
+                stop_id: vehicle.stop_id.clone(),
+                current_status: vehicle.current_status.map(enum_name::<VehicleStopStatus>),
+                reported_speed_mps: position.speed,
+                speed_mps: speed,
+                shape_distance_m: projection.map(|((_, along, _), _)| round(along, 2)),
+                shape_offset_m: projection.map(|((_, _, offset), _)| round(offset, 2)),
+                progress_pct: projection
+                    .filter(|(_, length)| *length > 0.0)
+                    .map(|((_, along, _), length)| round(100.0 * along / length, 2)),
+            });
+        }
+    }
+    points.sort_by_key(|point| point.timestamp);
+    points
+}
+
+// A line per vehicle and trip through its positions, for those with at least two
+pub fn trajectories(points: &[PositionPoint]) -> Vec<Trajectory> {
+    let mut tracks: BTreeMap<VehicleTrip, Vec<&PositionPoint>> = BTreeMap::new();
+    for point in points {
+        let key = (
+            point.vehicle_id.to_owned(),
+            point.trip_id.clone(),
+            point.start_date.clone(),
+        );
+        tracks.entry(key).or_default().push(point);
+    }
+
+    tracks
+        .into_iter()
+        .filter(|(_, track)| track.len() >= 2)
+        .map(|((vehicle_id, trip_id, start_date), track)| {
+            let vertices: Vec<geo_types::Point> =
+                track.iter().map(|point| point.geometry).collect();
+            let distance = geodata::line_length(&vertices);
+            let (first, last) = (track[0].timestamp, track[track.len() - 1].timestamp);
+            Trajectory {
+                geometry: geo_types::LineString::from(vertices),
+                vehicle_id,
+                trip_id,
+                start_date,
+                route_id: track.iter().find_map(|point| point.route_id.clone()),
+                first_timestamp: first,
+                last_timestamp: last,
+                timestamps: track.iter().map(|point| point.timestamp).collect(),
+                distance_m: round(distance, 2),
+                mean_speed_mps: (last > first).then(|| round(distance / (last - first) as f64, 2)),
+                max_speed_mps: track
+                    .iter()
+                    .filter_map(|point| point.speed_mps)
+                    .reduce(f64::max),
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_trajectories() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let feed = Feed::load(&mut gtfs_file);
+
+    // The same snapshot again, and the bus a minute on towards Bullfrog
+    let first = read_feed_message(&PathBuf::from("test_data/sample-rt-1.json")).unwrap();
+    let mut later = first.clone();
+    later.header.timestamp = Some(1181055960);
+    for entity in &mut later.entity {
+        if let Some(vehicle) = &mut entity.vehicle {
+            vehicle.timestamp = Some(1181055940);
+            vehicle.position.as_mut().unwrap().latitude = 36.878;
+            vehicle.position.as_mut().unwrap().longitude = -116.81;
+        }
+    }
+    let points = position_points(&feed, &[first.clone(), first, later]);
+    assert_eq!(points.len(), 2);
+    assert_eq!(points[0].vehicle_id, "bus-7");
+    assert_eq!(points[0].route_id.as_deref(), Some("AB"));
+    assert_eq!(points[0].current_status.as_deref(), Some("IN_TRANSIT_TO"));
+    assert_eq!(points[0].speed_mps, None);
+    // About 860 m in a minute
+    let speed = points[1].speed_mps.unwrap();
+    assert!(speed > 13.0 && speed < 16.0, "{}", speed);
+    assert!(points[1].progress_pct.unwrap() > points[0].progress_pct.unwrap());
+    assert!(points[0].shape_offset_m.unwrap() < 200.0);
+
+    let trajectories = trajectories(&points);
+    assert_eq!(trajectories.len(), 1);
+    assert_eq!(trajectories[0].timestamps, vec![1181055880, 1181055940]);
+    assert_eq!(trajectories[0].mean_speed_mps, Some(speed));
+    assert!(to_geojson_string(&trajectories)
+        .unwrap()
+        .contains("\"LineString\""));
+}
@@ -0,0 +1,481 @@
+use std::collections::{HashMap, HashSet};
+#[cfg(test)]
+use std::path::PathBuf;
+
+use chrono::{DateTime, Days, NaiveDate};
+
+#[cfg(test)]
+use crate::objects::feed::Feed;
+
+use super::super::geodata;
+#[cfg(test)]
+use super::super::gtfs;
+use super::super::gtfs::Trip;
+use super::super::validation::{Finding, Severity};
+use super::proto::{self, FeedMessage, StopScheduleRelationship, TripScheduleRelationship};
+#[cfg(test)]
+use super::read_feed_message;
+use super::{parse_start_date, parse_start_time, StaticIndex};
+
+// Rules checked, with their severity:
+// unknown_trip_id, unknown_route_id, unknown_stop_id: error, ids missing from the static feed
+// invalid_start_date, invalid_start_time: error, not YYYYMMDD or HH:MM:SS
+// trip_not_active: error, the trip's service doesn't run on the start date, or without one on
+//   the day of the header timestamp or the one before
+// stop_time_updates_out_of_order: error, stop sequences or scheduled calls not increasing
+// predicted_times_decreasing: error, a predicted time before the one of an earlier event
+// implausible_delay: warning, later or earlier than the settings allow
+// stale_timestamp: warning, older than the header, or the header older than now, by too much
+// future_timestamp: error, an entity timestamp after the header's
+// vehicle_far_from_shape: warning, further from the trip's shape, or the line through its
+//   stops without one, than the settings allow
+#[derive(Debug, Clone, Copy)]
+pub struct RealtimeSettings {
+    pub max_delay_s: i64,
+    pub max_early_s: i64,
+    pub max_age_s: u64,
+    pub max_shape_distance_m: f64,
+    // Unix time to check the header timestamp against, when validating a live feed
+    pub now: Option<u64>,
+}
+
+impl Default for RealtimeSettings {
+    fn default() -> RealtimeSettings {
+        RealtimeSettings {
+            max_delay_s: 3 * 3600,
+            max_early_s: 30 * 60,
+            max_age_s: 300,
+            max_shape_distance_m: 200.0,
+            now: None,
+        }
+    }
+}
+
+struct Validator<'a, 'b> {
+    index: &'b StaticIndex<'a>,
+    settings: RealtimeSettings,
+    header_timestamp: Option<u64>,
+    shape_lines: HashMap<String, Vec<geo_types::Point>>,
+    active_services: HashMap<NaiveDate, HashSet<String>>,
+    findings: Vec<Finding>,
+}
+
+// A trip of the static feed on the service date the realtime data is about
+struct TripInstance<'a> {
+    trip: &'a Trip,
+    date: NaiveDate,
+    start_time: Option<u64>,
+}
+
+pub fn validate_feed_message(
+    message: &FeedMessage,
+    index: &StaticIndex,
+    settings: RealtimeSettings,
+) -> Vec<Finding> {
+    let mut validator = Validator {
+        index,
+        settings,
+        header_timestamp: message.header.timestamp,
+        shape_lines: geodata::shape_lines(&index.feed.shapes),
+        active_services: HashMap::new(),
+        findings: Vec::new(),
+    };
+
+    if let (Some(now), Some(header)) = (settings.now, message.header.timestamp) {
+        if now > header.saturating_add(settings.max_age_s) {
+            validator.report(
+                "stale_timestamp",
+                Severity::Warning,
+                "header",
+                format!("feed is {} s old", now - header),
+            );
+        }
+    }
+
+    for entity in &message.entity {
+        if entity.is_deleted == Some(true) {
+            continue;
+        }
+        if let Some(update) = &entity.trip_update {
+            validator.trip_update(&entity.id, update);
+        }
+        if let Some(vehicle) = &entity.vehicle {
+            validator.vehicle(&entity.id, vehicle);
+        }
+        if let Some(alert) = &entity.alert {
+            for selector in &alert.informed_entity {
+                if let Some(route_id) = &selector.route_id {
+                    validator.route_id(&entity.id, route_id);
+                }
+                if let Some(stop_id) = &selector.stop_id {
+                    validator.stop_id(&entity.id, stop_id);
+                }
+                if let Some(trip) = &selector.trip {
+                    validator.trip(&entity.id, trip);
+                }
+            }
+        }
+    }
+    validator.findings
+}
+
+impl<'a> Validator<'a, '_> {
+    fn report(&mut self, code: &'static str, severity: Severity, entity_id: &str, message: String) {
+        self.findings
+            .push(Finding::new(code, severity, entity_id, message));
+    }
+
+    fn route_id(&mut self, entity_id: &str, route_id: &str) {
+        if !self.index.routes.contains_key(route_id) {
+            self.report(
+                "unknown_route_id",
+                Severity::Error,
+                entity_id,
+                format!("route {} not in the feed", route_id),
+            );
+        }
+    }
+
+    fn stop_id(&mut self, entity_id: &str, stop_id: &str) {
+        if !self.index.stops.contains_key(stop_id) {
+            self.report(
+                "unknown_stop_id",
+                Severity::Error,
+                entity_id,
+                format!("stop {} not in the feed", stop_id),
+            );
+        }
+    }
+
+    fn timestamp(&mut self, entity_id: &str, timestamp: Option<u64>) {
+        let (Some(timestamp), Some(header)) = (timestamp, self.header_timestamp) else {
+            return;
+        };
+        if timestamp > header {
+            self.report(
+                "future_timestamp",
+                Severity::Error,
+                entity_id,
+                format!("timestamp {} s after the header's", timestamp - header),
+            );
+        } else if header - timestamp > self.settings.max_age_s {
+            self.report(
+                "stale_timestamp",
+                Severity::Warning,
+                entity_id,
+                format!("timestamp {} s before the header's", header - timestamp),
+            );
+        }
+    }
+
+    fn is_active(&mut self, trip: &Trip, date: NaiveDate) -> bool {
+        let index = self.index;
+        self.active_services
+            .entry(date)
+            .or_insert_with(|| index.feed.active_services(date))
+            .contains(&trip.service_id)
+    }
+
+    // Checks the ids of a trip descriptor and when its trip runs, returning the static trip
+    fn trip(
+        &mut self,
+        entity_id: &str,
+        descriptor: &proto::TripDescriptor,
+    ) -> Option<TripInstance<'a>> {
+        if let Some(route_id) = &descriptor.route_id {
+            self.route_id(entity_id, route_id);
+        }
+        let trip_id = descriptor.trip_id.as_deref()?;
+        let relationship = descriptor.schedule_relationship.unwrap_or_default();
+        let Some(trip) = self.index.trips.get(trip_id).copied() else {
+            // Added trips are the only ones that may be missing from the static feed
+            let added = [
+                TripScheduleRelationship::Added,
+                TripScheduleRelationship::Unscheduled,
+            ];
+            if !added.iter().any(|added| *added as i32 == relationship) {
+                self.report(
+                    "unknown_trip_id",
+                    Severity::Error,
+                    entity_id,
+                    format!("trip {} not in the feed", trip_id),
+                );
+            }
+            return None;
+        };
+
+        let start_time = match &descriptor.start_time {
+            Some(start_time) => match parse_start_time(start_time) {
+                Some(seconds) => Some(seconds),
+                None => {
+                    self.report(
+                        "invalid_start_time",
+                        Severity::Error,
+                        entity_id,
+                        format!("start time {}", start_time),
+                    );
+                    return None;
+                }
+            },
+            None => None,
+        };
+        let date = match &descriptor.start_date {
+            Some(start_date) => {
+                let Some(date) = parse_start_date(start_date) else {
+                    self.report(
+                        "invalid_start_date",
+                        Severity::Error,
+                        entity_id,
+                        format!("start date {}", start_date),
+                    );
+                    return None;
+                };
+                if !self.is_active(trip, date) {
+                    self.report(
+                        "trip_not_active",
+                        Severity::Error,
+                        entity_id,
+                        format!("trip {} doesn't run on {}", trip_id, date),
+                    );
+                    return None;
+                }
+                date
+            }
+            None => {
+                // Trips after midnight belong to the day before
+                let today = DateTime::from_timestamp(self.header_timestamp? as i64, 0)?
+                    .with_timezone(&self.index.timezone)
+                    .date_naive();
+                let candidates = [Some(today), today.checked_sub_days(Days::new(1))];
+                match candidates
+                    .into_iter()
+                    .flatten()
+                    .find(|date| self.is_active(trip, *date))
+                {
+                    Some(date) => date,
+                    None => {
+                        self.report(
+                            "trip_not_active",
+                            Severity::Error,
+                            entity_id,
+                            format!(
+                                "trip {} doesn't run on {} or the day before",
+                                trip_id, today
+                            ),
+                        );
+                        return None;
+                    }
+                }
+            }
+        };
+        Some(TripInstance {
+            trip,
+            date,
+            start_time,
+        })
+    }
+
+    fn delay(&mut self, entity_id: &str, place: &str, delay: i64) {
+        if delay > self.settings.max_delay_s || delay < -self.settings.max_early_s {
+            self.report(
+                "implausible_delay",
+                Severity::Warning,
+                entity_id,
+                format!("delay of {} s {}", delay, place),
+            );
+        }
+    }
+
+    fn trip_update(&mut self, entity_id: &str, update: &proto::TripUpdate) {
+        let instance = self.trip(entity_id, &update.trip);
+        self.timestamp(entity_id, update.timestamp);
+        if let Some(delay) = update.delay {
+            self.delay(entity_id, "for the trip", delay as i64);
+        }
+
+        let matches = match &instance {
+            Some(instance) => self
+                .index
+                .match_stop_times(&instance.trip.trip_id, &update.stop_time_update),
+            None => vec![None; update.stop_time_update.len()],
+        };
+        let mut last_sequence: Option<u32> = None;
+        let mut last_match: Option<usize> = None;
+        let mut last_time: Option<i64> = None;
+        for (stop_time_update, matched) in update.stop_time_update.iter().zip(matches) {
+            let place = match (stop_time_update.stop_sequence, &stop_time_update.stop_id) {
+                (Some(sequence), _) => format!("at stop sequence {}", sequence),
+                (None, Some(stop_id)) => format!("at stop {}", stop_id),
+                (None, None) => String::from("at an unidentified stop"),
+            };
+            if let Some(stop_id) = &stop_time_update.stop_id {
+                self.stop_id(entity_id, stop_id);
+            }
+
+            let out_of_order = match (stop_time_update.stop_sequence, last_sequence) {
+                (Some(sequence), Some(last)) => sequence <= last,
+                _ => matched
+                    .zip(last_match)
+                    .is_some_and(|(matched, last)| matched <= last),
+            };
+            if out_of_order {
+                self.report(
+                    "stop_time_updates_out_of_order",
+                    Severity::Error,
+                    entity_id,
+                    format!("update {} comes after a later stop", place),
+                );
+            }
+            last_sequence = stop_time_update.stop_sequence.or(last_sequence);
+            last_match = matched.or(last_match);
+
+            let relationship = stop_time_update.schedule_relationship.unwrap_or_default();
+            if relationship == StopScheduleRelationship::Skipped as i32
+                || relationship == StopScheduleRelationship::NoData as i32
+            {
+                continue;
+            }
+            let scheduled = instance.as_ref().zip(matched).map(|(instance, matched)| {
+                let stop_time = self.index.stop_times[instance.trip.trip_id.as_str()][matched];
+                (instance, &stop_time.arrival_time, &stop_time.departure_time)
+            });
+            let events = [
+                (
+                    &stop_time_update.arrival,
+                    scheduled.map(|(instance, arrival, _)| (instance, arrival)),
+                ),
+                (
+                    &stop_time_update.departure,
+                    scheduled.map(|(instance, _, departure)| (instance, departure)),
+                ),
+            ];
+            for (event, scheduled) in events {
+                let Some(event) = event else {
+                    continue;
+                };
+                if let Some(time) = event.time {
+                    if last_time.is_some_and(|last| time < last) {
+                        self.report(
+                            "predicted_times_decreasing",
+                            Severity::Error,
+                            entity_id,
+                            format!("predicted time {} is before an earlier event's", place),
+                        );
+                    }
+                    last_time = Some(time);
+                }
+                let scheduled = scheduled.and_then(|(instance, time)| {
+                    let seconds = time.as_ref()?.seconds();
+                    Some(self.index.scheduled_time(
+                        &instance.trip.trip_id,
+                        instance.date,
+                        instance.start_time,
+                        seconds,
+                    ))
+                });
+                let delay = event.delay.map(i64::from).or(event
+                    .time
+                    .zip(scheduled)
+                    .map(|(time, scheduled)| time - scheduled));
+                if let Some(delay) = delay {
+                    self.delay(entity_id, &place, delay);
+                }
+            }
+        }
+    }
+
+    fn vehicle(&mut self, entity_id: &str, vehicle: &proto::VehiclePosition) {
+        let instance = vehicle
+            .trip
+            .as_ref()
+            .and_then(|trip| self.trip(entity_id, trip));
+        if let Some(stop_id) = &vehicle.stop_id {
+            self.stop_id(entity_id, stop_id);
+        }
+        self.timestamp(entity_id, vehicle.timestamp);
+
+        let (Some(instance), Some(position)) = (instance, &vehicle.position) else {
+            return;
+        };
+        let point = geo_types::Point::new(position.longitude as f64, position.latitude as f64);
+        let line = self.index.trip_line(instance.trip, &self.shape_lines);
+        if line.len() < 2 {
+            return;
+        }
+        if let Some((_, _, offset)) = geodata::project_onto_line(&line, point, 0) {
+            if offset > self.settings.max_shape_distance_m {
+                self.report(
+                    "vehicle_far_from_shape",
+                    Severity::Warning,
+                    entity_id,
+                    format!(
+                        "position {:.0} m from trip {}",
+                        offset, instance.trip.trip_id
+                    ),
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_validate_feed_message() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let feed = Feed::load(&mut gtfs_file);
+    let index = StaticIndex::new(&feed);
+    let mut message = read_feed_message(&PathBuf::from("test_data/sample-rt-1.json")).unwrap();
+    let codes = |findings: &[Finding]| {
+        let mut codes: Vec<(String, &str)> = findings
+            .iter()
+            .map(|finding| (finding.entity_id.to_owned(), finding.code))
+            .collect();
+        codes.sort();
+        codes
+    };
+
+    let findings = validate_feed_message(&message, &index, RealtimeSettings::default());
+    assert_eq!(
+        codes(&findings),
+        vec![
+            (String::from("trip-GHOST"), "unknown_stop_id"),
+            (String::from("trip-GHOST"), "unknown_trip_id"),
+        ]
+    );
+
+    // Bullfrog before the airport, four hours late, and the bus an hour old and far off
+    let update = message.entity[0].trip_update.as_mut().unwrap();
+    update.stop_time_update.reverse();
+    update.stop_time_update[0].arrival.as_mut().unwrap().time = Some(1181056320 + 4 * 3600);
+    update.stop_time_update[1].departure.as_mut().unwrap().time = Some(1181055720);
+    let vehicle = message.entity[2].vehicle.as_mut().unwrap();
+    vehicle.timestamp = Some(1181052000);
+    vehicle.position.as_mut().unwrap().latitude = 36.9;
+    message.entity[1].trip_update.as_mut().unwrap().trip = proto::TripDescriptor {
+        trip_id: Some(String::from("AB2")),
+        start_date: Some(String::from("20070604")),
+        ..Default::default()
+    };
+    let findings = validate_feed_message(&message, &index, RealtimeSettings::default());
+    assert_eq!(
+        codes(&findings),
+        vec![
+            (String::from("trip-AB1"), "implausible_delay"),
+            (String::from("trip-AB1"), "predicted_times_decreasing"),
+            (String::from("trip-AB1"), "stop_time_updates_out_of_order"),
+            (String::from("trip-GHOST"), "trip_not_active"),
+            (String::from("trip-GHOST"), "unknown_stop_id"),
+            (String::from("vehicle-bus-7"), "stale_timestamp"),
+            (String::from("vehicle-bus-7"), "vehicle_far_from_shape"),
+        ]
+    );
+
+    // A bogus header timestamp can't be stale
+    message.header.timestamp = Some(u64::MAX);
+    let settings = RealtimeSettings {
+        now: Some(1181056000),
+        ..RealtimeSettings::default()
+    };
+    let findings = validate_feed_message(&message, &index, settings);
+    assert!(findings.iter().all(|finding| finding.entity_id != "header"));
+}
@@ -0,0 +1,841 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+#[cfg(test)]
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::objects::feed::Feed;
+use crate::objects::pattern;
+use crate::objects::spatial::SpatialIndex;
+
+use super::geodata;
+#[cfg(test)]
+use super::gtfs;
+use super::gtfs::{Time, TransferType};
+
+// Walking between stops that aren't linked in transfers.txt
+#[derive(Debug, Clone, Copy)]
+pub struct WalkSettings {
+    pub max_distance_m: f64,
+    pub speed_m_s: f64,
+}
+
+impl Default for WalkSettings {
+    fn default() -> WalkSettings {
+        WalkSettings {
+            max_distance_m: 500.0,
+            speed_m_s: 1.3,
+        }
+    }
+}
+
+impl WalkSettings {
+    pub fn seconds(&self, distance_m: f64) -> u64 {
+        (distance_m / self.speed_m_s).ceil() as u64
+    }
+}
+
+// Departures every `step` seconds from `start` up to but not including `end`
+#[derive(Debug, Clone, Copy)]
+pub struct Window {
+    pub start: u64,
+    pub end: u64,
+    pub step: u64,
+}
+
+impl Window {
+    pub fn departures(&self) -> impl Iterator<Item = u64> {
+        (self.start..self.end).step_by(self.step.max(1) as usize)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Place {
+    // A stop, or a station standing for all of its stops
+    Stop(String),
+    Coordinate(geo_types::Point),
+}
+
+// The times of one trip instance at every stop of its route
+struct TripTimes {
+    trip_id: String,
+    arrivals: Vec<u64>,
+    departures: Vec<u64>,
+}
+
+// Trips sharing a stop sequence, ordered so that no trip overtakes another, as RAPTOR expects
+struct RaptorRoute {
+    route_id: String,
+    stops: Vec<usize>,
+    trips: Vec<TripTimes>,
+}
+
+// The trips of one service date and the footpaths between stops, indexed for RAPTOR
+pub struct Timetable {
+    pub stop_ids: Vec<String>,
+    stop_index: HashMap<String, usize>,
+    pub stop_names: Vec<Option<String>>,
+    pub stop_points: Vec<Option<geo_types::Point>>,
+    parents: Vec<Option<usize>>,
+    routes: Vec<RaptorRoute>,
+    // Routes calling at every stop, with the stop's position in the route
+    stop_routes: Vec<Vec<(usize, usize)>>,
+    footpaths: Vec<Vec<(usize, u64)>>,
+    // Minimum time to change between trips at the same stop
+    change_times: Vec<u64>,
+    route_names: HashMap<String, String>,
+    headsigns: HashMap<String, String>,
+    pub walk: WalkSettings,
+}
+
+impl Timetable {
+    pub fn new(feed: &Feed, date: NaiveDate, walk: WalkSettings) -> Timetable {
+        let stop_ids: Vec<String> = feed
+            .stops
+            .iter()
+            .map(|stop| stop.stop_id.to_owned())
+            .collect();
+        let stop_index: HashMap<String, usize> = stop_ids
+            .iter()
+            .enumerate()
+            .map(|(index, stop_id)| (stop_id.to_owned(), index))
+            .collect();
+        let stop_points: Vec<Option<geo_types::Point>> =
+            feed.stops.iter().map(geodata::stop_point).collect();
+        let parents = feed
+            .stops
+            .iter()
+            .map(|stop| {
+                stop.parent_station
+                    .as_ref()
+                    .and_then(|parent| stop_index.get(parent))
+                    .copied()
+            })
+            .collect();
+
+        // Ordered so routes get the same indices on every run
+        let mut sequences: BTreeMap<(String, Vec<usize>), Vec<TripTimes>> = BTreeMap::new();
+        for instance in feed.trip_instances(&feed.active_services(date)) {
+            let stops: Option<Vec<usize>> = instance
+                .stops
+                .iter()
+                .map(|stop| stop_index.get(&stop.stop_id).copied())
+                .collect();
+            let Some(stops) = stops.filter(|stops| stops.len() > 1) else {
+                continue;
+            };
+            sequences
+                .entry((instance.trip.route_id.to_owned(), stops))
+                .or_default()
+                .push(TripTimes {
+                    trip_id: instance.trip.trip_id.to_owned(),
+                    arrivals: instance.stops.iter().map(|stop| stop.arrival).collect(),
+                    departures: instance.stops.iter().map(|stop| stop.departure).collect(),
+                });
+        }
+
+        let mut routes: Vec<RaptorRoute> = Vec::new();
+        for ((route_id, stops), mut trips) in sequences {
+            trips.sort_by_key(|trip| (trip.departures[0], trip.arrivals[trip.arrivals.len() - 1]));
+            // A trip overtaking the last one of a group starts a new group
+            let mut groups: Vec<Vec<TripTimes>> = Vec::new();
+            for trip in trips {
+                let group = groups.iter_mut().find(|group| {
+                    group.last().map_or(true, |last| {
+                        (0..stops.len()).all(|i| {
+                            last.arrivals[i] <= trip.arrivals[i]
+                                && last.departures[i] <= trip.departures[i]
+                        })
+                    })
+                });
+                match group {
+                    Some(group) => group.push(trip),
+                    None => groups.push(vec![trip]),
+                }
+            }
+            for trips in groups {
+                routes.push(RaptorRoute {
+                    route_id: route_id.to_owned(),
+                    stops: stops.clone(),
+                    trips,
+                });
+            }
+        }
+
+        let mut stop_routes: Vec<Vec<(usize, usize)>> = vec![Vec::new(); stop_ids.len()];
+        for (route_index, route) in routes.iter().enumerate() {
+            for (position, stop) in route.stops.iter().enumerate() {
+                stop_routes[*stop].push((route_index, position));
+            }
+        }
+
+        let (footpaths, change_times) = footpaths(feed, &stop_index, &stop_points, walk);
+
+        Timetable {
+            stop_names: feed
+                .stops
+                .iter()
+                .map(|stop| stop.stop_name.clone())
+                .collect(),
+            route_names: feed
+                .routes
+                .iter()
+                .map(|route| {
+                    (
+                        route.route_id.to_owned(),
+                        pattern::route_name(route).to_owned(),
+                    )
+                })
+                .collect(),
+            headsigns: feed
+                .trips
+                .iter()
+                .filter_map(|trip| Some((trip.trip_id.to_owned(), trip.trip_headsign.clone()?)))
+                .collect(),
+            stop_ids,
+            stop_index,
+            stop_points,
+            parents,
+            routes,
+            stop_routes,
+            footpaths,
+            change_times,
+            walk,
+        }
+    }
+
+    // Stops a place stands for and the seconds it takes to walk between the place and each of
+    // them. None if the place is an unknown stop.
+    pub fn access(&self, place: &Place) -> Option<Vec<(usize, u64)>> {
+        match place {
+            Place::Stop(stop_id) => {
+                let stop = *self.stop_index.get(stop_id)?;
+                let mut stops = vec![(stop, 0)];
+                for (child, parent) in self.parents.iter().enumerate() {
+                    if *parent == Some(stop) {
+                        stops.push((child, 0));
+                    }
+                }
+                Some(stops)
+            }
+            Place::Coordinate(point) => Some(
+                self.stop_points
+                    .iter()
+                    .enumerate()
+                    .filter(|(stop, _)| self.is_served(*stop))
+                    .filter_map(|(stop, stop_point)| {
+                        let distance = geodata::haversine_distance(*point, (*stop_point)?);
+                        (distance <= self.walk.max_distance_m)
+                            .then(|| (stop, self.walk.seconds(distance)))
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn is_served(&self, stop: usize) -> bool {
+        !self.stop_routes[stop].is_empty()
+    }
+
+    // RAPTOR from the origin stops, round k holding the earliest arrivals with up to k trips.
+    // Arrivals at the target, where given, bound the search.
+    pub fn search(
+        &self,
+        origin: &[(usize, u64)],
+        departure: u64,
+        max_rounds: usize,
+        target: &[(usize, u64)],
+    ) -> Search {
+        let count = self.stop_ids.len();
+        let mut best: Vec<u64> = vec![u64::MAX; count];
+        let mut round: Vec<Option<Label>> = vec![None; count];
+        let mut marked: BTreeSet<usize> = BTreeSet::new();
+        for (stop, access) in origin {
+            let time = departure + access;
+            if time < best[*stop] {
+                best[*stop] = time;
+                round[*stop] = Some(Label {
+                    time,
+                    by_transit: false,
+                    via: Via::Origin { access: *access },
+                });
+                marked.insert(*stop);
+            }
+        }
+        let target_bound = |best: &[u64]| {
+            target
+                .iter()
+                .map(|(stop, egress)| best[*stop].saturating_add(*egress))
+                .min()
+                .unwrap_or(u64::MAX)
+        };
+        let bound = target_bound(&best);
+        self.relax_footpaths(&mut round, &mut best, &mut marked, bound);
+        let mut rounds = vec![round];
+
+        for _ in 0..max_rounds {
+            if marked.is_empty() {
+                break;
+            }
+            let previous = &rounds[rounds.len() - 1];
+            let mut round: Vec<Option<Label>> = previous
+                .iter()
+                .map(|label| {
+                    label.map(|label| Label {
+                        via: Via::Carried,
+                        ..label
+                    })
+                })
+                .collect();
+
+            // Every route is scanned once, from the first marked stop along it
+            let mut queue: HashMap<usize, usize> = HashMap::new();
+            for stop in &marked {
+                for (route, position) in &self.stop_routes[*stop] {
+                    let entry = queue.entry(*route).or_insert(*position);
+                    *entry = (*entry).min(*position);
+                }
+            }
+            let mut queue: Vec<(usize, usize)> = queue.into_iter().collect();
+            queue.sort();
+            marked.clear();
+
+            let mut bound = target_bound(&best);
+            for (route_index, start) in queue {
+                let route = &self.routes[route_index];
+                let mut boarded: Option<(usize, usize)> = None;
+                for position in start..route.stops.len() {
+                    let stop = route.stops[position];
+                    if let Some((trip, board)) = boarded {
+                        let arrival = route.trips[trip].arrivals[position];
+                        if arrival < best[stop].min(bound) {
+                            best[stop] = arrival;
+                            round[stop] = Some(Label {
+                                time: arrival,
+                                by_transit: true,
+                                via: Via::Trip {
+                                    route: route_index,
+                                    trip,
+                                    board,
+                                    alight: position,
+                                },
+                            });
+                            marked.insert(stop);
+                            bound = target_bound(&best);
+                        }
+                    }
+                    if let Some(label) = previous[stop] {
+                        let ready = label.time
+                            + if label.by_transit {
+                                self.change_times[stop]
+                            } else {
+                                0
+                            };
+                        let first = route
+                            .trips
+                            .partition_point(|trip| trip.departures[position] < ready);
+                        if first < route.trips.len()
+                            && boarded.map_or(true, |(trip, _)| first < trip)
+                        {
+                            boarded = Some((first, position));
+                        }
+                    }
+                }
+            }
+
+            self.relax_footpaths(&mut round, &mut best, &mut marked, bound);
+            rounds.push(round);
+        }
+
+        Search { rounds, best }
+    }
+
+    // Seconds from each departure of the window to every stop, None where a stop isn't reached
+    pub fn window_travel_times(
+        &self,
+        origin: &[(usize, u64)],
+        window: Window,
+        max_rounds: usize,
+    ) -> Vec<Vec<Option<u64>>> {
+        window
+            .departures()
+            .map(|departure| {
+                let search = self.search(origin, departure, max_rounds, &[]);
+                search
+                    .best
+                    .iter()
+                    .map(|arrival| (*arrival != u64::MAX).then(|| arrival - departure))
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Walks on from the stops reached in this round. Only stops reached by trip or from the
+    // origin are walked from, so walks aren't chained.
+    fn relax_footpaths(
+        &self,
+        round: &mut [Option<Label>],
+        best: &mut [u64],
+        marked: &mut BTreeSet<usize>,
+        bound: u64,
+    ) {
+        let reached: Vec<(usize, u64)> = marked
+            .iter()
+            .filter_map(|stop| Some((*stop, round[*stop]?.time)))
+            .collect();
+        for (stop, time) in reached {
+            for (other, duration) in &self.footpaths[stop] {
+                let arrival = time + duration;
+                if arrival < best[*other].min(bound) {
+                    best[*other] = arrival;
+                    round[*other] = Some(Label {
+                        time: arrival,
+                        by_transit: false,
+                        via: Via::Walk {
+                            from: stop,
+                            duration: *duration,
+                        },
+                    });
+                    marked.insert(*other);
+                }
+            }
+        }
+    }
+
+    // Journeys to the destination that are Pareto optimal in arrival time and number of trips
+    pub fn journeys(
+        &self,
+        search: &Search,
+        destination: &[(usize, u64)],
+        departure: u64,
+    ) -> Vec<Journey> {
+        let mut journeys: Vec<Journey> = Vec::new();
+        let mut earliest = u64::MAX;
+        for (round_index, round) in search.rounds.iter().enumerate() {
+            let arrival = destination
+                .iter()
+                .filter_map(|(stop, egress)| Some((round[*stop]?.time + egress, *stop, *egress)))
+                .min();
+            if let Some((arrival, stop, egress)) = arrival {
+                if arrival < earliest {
+                    earliest = arrival;
+                    journeys.push(self.reconstruct(search, round_index, stop, egress, departure));
+                }
+            }
+        }
+        journeys
+    }
+
+    fn reconstruct(
+        &self,
+        search: &Search,
+        mut round: usize,
+        mut stop: usize,
+        egress: u64,
+        departure: u64,
+    ) -> Journey {
+        let mut legs: Vec<Leg> = Vec::new();
+        let Some(arrival) = search.rounds[round][stop].map(|label| label.time) else {
+            return Journey::from_legs(legs, departure);
+        };
+        if egress > 0 {
+            legs.push(self.walk_leg(Some(stop), None, arrival, arrival + egress));
+        }
+
+        while let Some(label) = search.rounds[round][stop] {
+            match label.via {
+                Via::Origin { access } => {
+                    if access > 0 {
+                        legs.push(self.walk_leg(None, Some(stop), label.time - access, label.time));
+                    }
+                    break;
+                }
+                Via::Carried => round -= 1,
+                Via::Walk { from, duration } => {
+                    legs.push(self.walk_leg(
+                        Some(from),
+                        Some(stop),
+                        label.time - duration,
+                        label.time,
+                    ));
+                    stop = from;
+                }
+                Via::Trip {
+                    route,
+                    trip,
+                    board,
+                    alight,
+                } => {
+                    let raptor_route = &self.routes[route];
+                    let trip_times = &raptor_route.trips[trip];
+                    let route_id = &raptor_route.route_id;
+                    legs.push(Leg {
+                        mode: LegMode::Transit,
+                        from_stop_id: Some(self.stop_ids[raptor_route.stops[board]].to_owned()),
+                        from_name: self.stop_names[raptor_route.stops[board]].clone(),
+                        to_stop_id: Some(self.stop_ids[stop].to_owned()),
+                        to_name: self.stop_names[stop].clone(),
+                        departure: Time::from_seconds(trip_times.departures[board]).to_string(),
+                        arrival: Time::from_seconds(trip_times.arrivals[alight]).to_string(),
+                        route_id: Some(route_id.to_owned()),
+                        route_name: self.route_names.get(route_id).cloned(),
+                        trip_id: Some(trip_times.trip_id.to_owned()),
+                        headsign: self.headsigns.get(&trip_times.trip_id).cloned(),
+                        start: trip_times.departures[board],
+                        end: trip_times.arrivals[alight],
+                    });
+                    stop = raptor_route.stops[board];
+                    round -= 1;
+                }
+            }
+        }
+        legs.reverse();
+
+        // Walks before the first trip leave just in time to catch it
+        if let Some(first_trip) = legs.iter().position(|leg| leg.mode == LegMode::Transit) {
+            let mut time = legs[first_trip].start;
+            for leg in legs[..first_trip].iter_mut().rev() {
+                let duration = leg.end - leg.start;
+                leg.end = time;
+                leg.start = time - duration;
+                leg.departure = Time::from_seconds(leg.start).to_string();
+                leg.arrival = Time::from_seconds(leg.end).to_string();
+                time = leg.start;
+            }
+        }
+        Journey::from_legs(legs, departure)
+    }
+
+    fn walk_leg(&self, from: Option<usize>, to: Option<usize>, start: u64, end: u64) -> Leg {
+        Leg {
+            mode: LegMode::Walk,
+            from_stop_id: from.map(|stop| self.stop_ids[stop].to_owned()),
+            from_name: from.and_then(|stop| self.stop_names[stop].clone()),
+            to_stop_id: to.map(|stop| self.stop_ids[stop].to_owned()),
+            to_name: to.and_then(|stop| self.stop_names[stop].clone()),
+            departure: Time::from_seconds(start).to_string(),
+            arrival: Time::from_seconds(end).to_string(),
+            route_id: None,
+            route_name: None,
+            trip_id: None,
+            headsign: None,
+            start,
+            end,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Via {
+    Origin {
+        access: u64,
+    },
+    // Reached in an earlier round
+    Carried,
+    Trip {
+        route: usize,
+        trip: usize,
+        board: usize,
+        alight: usize,
+    },
+    Walk {
+        from: usize,
+        duration: u64,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Label {
+    time: u64,
+    // Arrived on a trip, so changing to another needs the stop's change time
+    by_transit: bool,
+    via: Via,
+}
+
+pub struct Search {
+    rounds: Vec<Vec<Option<Label>>>,
+    // Earliest arrival at every stop over all rounds
+    best: Vec<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LegMode {
+    Transit,
+    Walk,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Leg {
+    pub mode: LegMode,
+    // Walks to and from a coordinate have no stop at that end
+    pub from_stop_id: Option<String>,
+    pub from_name: Option<String>,
+    pub to_stop_id: Option<String>,
+    pub to_name: Option<String>,
+    pub departure: String,
+    pub arrival: String,
+    pub route_id: Option<String>,
+    pub route_name: Option<String>,
+    pub trip_id: Option<String>,
+    pub headsign: Option<String>,
+    #[serde(skip)]
+    start: u64,
+    #[serde(skip)]
+    end: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Journey {
+    pub departure: String,
+    pub arrival: String,
+    pub duration_min: f64,
+    pub transfers: usize,
+    pub legs: Vec<Leg>,
+}
+
+impl Journey {
+    fn from_legs(legs: Vec<Leg>, departure: u64) -> Journey {
+        let start = legs.first().map_or(departure, |leg| leg.start);
+        let end = legs.last().map_or(departure, |leg| leg.end);
+        let trips = legs
+            .iter()
+            .filter(|leg| leg.mode == LegMode::Transit)
+            .count();
+        Journey {
+            departure: Time::from_seconds(start).to_string(),
+            arrival: Time::from_seconds(end).to_string(),
+            duration_min: (end - start) as f64 / 60.0,
+            transfers: trips.saturating_sub(1),
+            legs,
+        }
+    }
+}
+
+// Journeys between two places, from the earliest arriving with the fewest transfers on to ones
+// arriving earlier with more. None if either place is an unknown stop.
+pub fn plan(
+    timetable: &Timetable,
+    from: &Place,
+    to: &Place,
+    departure: u64,
+    max_transfers: usize,
+) -> Option<Vec<Journey>> {
+    let origin = timetable.access(from)?;
+    let destination = timetable.access(to)?;
+    let search = timetable.search(&origin, departure, max_transfers + 1, &destination);
+    Some(timetable.journeys(&search, &destination, departure))
+}
+
+// Nearest rank percentile of travel times over the departures of a window. Departures that don't
+// reach the stop count as slower than any that do.
+pub fn percentile(times: &[Option<u64>], percentile: f64) -> Option<u64> {
+    let mut sorted: Vec<u64> = times.iter().map(|time| time.unwrap_or(u64::MAX)).collect();
+    sorted.sort_unstable();
+    nearest_rank(&sorted, percentile).filter(|time| *time != u64::MAX)
+}
+
+// Nearest rank percentile of sorted values, None when there are none
+pub fn nearest_rank<T: Copy>(sorted: &[T], percentile: f64) -> Option<T> {
+    let rank =
+        ((percentile / 100.0 * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len().max(1));
+    sorted.get(rank - 1).copied()
+}
+
+pub fn print_journeys(journeys: &[Journey]) {
+    if journeys.is_empty() {
+        println!("No journey found");
+    }
+    for (index, journey) in journeys.iter().enumerate() {
+        println!(
+            "Journey {}: {} - {}, {} min, {} transfers",
+            index + 1,
+            journey.departure,
+            journey.arrival,
+            journey.duration_min,
+            journey.transfers
+        );
+        for leg in &journey.legs {
+            let from = leg
+                .from_name
+                .as_deref()
+                .or(leg.from_stop_id.as_deref())
+                .unwrap_or("origin");
+            let to = leg
+                .to_name
+                .as_deref()
+                .or(leg.to_stop_id.as_deref())
+                .unwrap_or("destination");
+            match leg.mode {
+                LegMode::Transit => println!(
+                    "  {} - {} {} {}: {} to {}",
+                    leg.departure,
+                    leg.arrival,
+                    leg.route_name.as_deref().unwrap_or_default(),
+                    leg.headsign.as_deref().unwrap_or_default(),
+                    from,
+                    to
+                ),
+                LegMode::Walk => println!(
+                    "  {} - {} walk: {} to {}",
+                    leg.departure, leg.arrival, from, to
+                ),
+            }
+        }
+    }
+}
+
+// Footpaths from transfers.txt, where they override walking estimates, and walks between stops
+// within the walking distance. Transfers from a stop to itself set its change time instead.
+fn footpaths(
+    feed: &Feed,
+    stop_index: &HashMap<String, usize>,
+    stop_points: &[Option<geo_types::Point>],
+    walk: WalkSettings,
+) -> (Vec<Vec<(usize, u64)>>, Vec<u64>) {
+    let count = stop_points.len();
+    let mut change_times: Vec<u64> = vec![0; count];
+    let mut explicit: HashMap<(usize, usize), Option<u64>> = HashMap::new();
+    for transfer in &feed.transfers {
+        // Route and trip specific rules need per trip labels and aren't supported
+        if transfer.from_route_id.is_some()
+            || transfer.to_route_id.is_some()
+            || transfer.from_trip_id.is_some()
+            || transfer.to_trip_id.is_some()
+        {
+            continue;
+        }
+        let (Some(from), Some(to)) = (
+            transfer
+                .from_stop_id
+                .as_ref()
+                .and_then(|stop_id| stop_index.get(stop_id)),
+            transfer
+                .to_stop_id
+                .as_ref()
+                .and_then(|stop_id| stop_index.get(stop_id)),
+        ) else {
+            continue;
+        };
+        let duration = match transfer.transfer_type {
+            TransferType::NotPossible => None,
+            _ => Some(transfer.min_transfer_time.unwrap_or_else(|| {
+                match (stop_points[*from], stop_points[*to]) {
+                    (Some(a), Some(b)) => walk.seconds(geodata::haversine_distance(a, b)),
+                    _ => 0,
+                }
+            })),
+        };
+        if from == to {
+            change_times[*from] = change_times[*from].max(duration.unwrap_or(0));
+        } else {
+            explicit.insert((*from, *to), duration);
+        }
+    }
+
+    let mut footpaths: Vec<HashMap<usize, u64>> = vec![HashMap::new(); count];
+    let index = SpatialIndex::new(&feed.stops, &[]);
+    for (from, to, distance) in index.stop_pairs_within(walk.max_distance_m) {
+        for (a, b) in [(from, to), (to, from)] {
+            if !explicit.contains_key(&(a, b)) {
+                footpaths[a].insert(b, walk.seconds(distance));
+            }
+        }
+    }
+    for ((from, to), duration) in explicit {
+        if let Some(duration) = duration {
+            footpaths[from].insert(to, duration);
+        }
+    }
+
+    let footpaths = footpaths
+        .into_iter()
+        .map(|paths| {
+            let mut paths: Vec<(usize, u64)> = paths.into_iter().collect();
+            paths.sort();
+            paths
+        })
+        .collect();
+    (footpaths, change_times)
+}
+
+#[test]
+fn test_plan() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let feed = Feed::load(&mut gtfs_file);
+    let timetable = Timetable::new(
+        &feed,
+        NaiveDate::from_ymd_opt(2007, 6, 5).unwrap(),
+        WalkSettings::default(),
+    );
+
+    // The shuttle to the airport connects to the only morning trip to Bullfrog at 08:00
+    let from = Place::Stop(String::from("STAGECOACH"));
+    let to = Place::Stop(String::from("BULLFROG"));
+    let journeys = plan(&timetable, &from, &to, 6 * 3600, 3).unwrap();
+    assert_eq!(journeys.len(), 1);
+    let journey = &journeys[0];
+    assert_eq!(journey.arrival, "08:10:00");
+    assert_eq!(journey.transfers, 1);
+    let routes: Vec<&str> = journey
+        .legs
+        .iter()
+        .filter_map(|leg| leg.route_id.as_deref())
+        .collect();
+    assert_eq!(routes, vec!["STBA", "AB"]);
+
+    // Too late for the morning trip, and only one trip allowed
+    assert!(plan(&timetable, &from, &to, 9 * 3600, 3)
+        .unwrap()
+        .is_empty());
+    assert!(plan(&timetable, &from, &to, 6 * 3600, 0)
+        .unwrap()
+        .is_empty());
+    assert!(plan(&timetable, &Place::Stop(String::from("unknown")), &to, 0, 3).is_none());
+
+    // Starting a little way from the hotel adds a walk, timed to catch the first shuttle
+    let near_hotel = Place::Coordinate(geo_types::Point::new(-116.7525, 36.9160));
+    let journeys = plan(&timetable, &near_hotel, &to, 5 * 3600 + 45 * 60, 3).unwrap();
+    let legs = &journeys[0].legs;
+    assert_eq!(legs[0].mode, LegMode::Walk);
+    assert_eq!(legs[0].to_stop_id.as_deref(), Some("STAGECOACH"));
+    assert_eq!(legs[0].arrival, "06:00:00");
+    assert_eq!(legs[1].departure, "06:00:00");
+}
+
+#[test]
+fn test_window_travel_times() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let feed = Feed::load(&mut gtfs_file);
+    let timetable = Timetable::new(
+        &feed,
+        NaiveDate::from_ymd_opt(2007, 6, 5).unwrap(),
+        WalkSettings::default(),
+    );
+
+    // Shuttles leave every half hour, so waiting grows by a minute with every later departure
+    let origin = timetable
+        .access(&Place::Stop(String::from("STAGECOACH")))
+        .unwrap();
+    let window = Window {
+        start: 6 * 3600,
+        end: 6 * 3600 + 30 * 60,
+        step: 60,
+    };
+    let times = timetable.window_travel_times(&origin, window, 1);
+    assert_eq!(times.len(), 30);
+    let airport = timetable
+        .stop_ids
+        .iter()
+        .position(|stop_id| stop_id == "BEATTY_AIRPORT")
+        .unwrap();
+    let airport: Vec<Option<u64>> = times.iter().map(|times| times[airport]).collect();
+    assert_eq!(airport[0], Some(20 * 60));
+    assert_eq!(airport[1], Some(49 * 60));
+    assert_eq!(percentile(&airport, 0.0), Some(20 * 60));
+    assert_eq!(percentile(&airport, 50.0), Some(34 * 60));
+    assert_eq!(percentile(&[None, Some(60)], 100.0), None);
+}
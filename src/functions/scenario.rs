@@ -0,0 +1,557 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::Path;
+#[cfg(test)]
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Deserializer};
+
+use crate::objects::feed::Feed;
+
+#[cfg(test)]
+use super::gtfs;
+use super::gtfs::{Calendar, CalendarDate, CalendarException, Frequency, StopTime, Time, Trip};
+use super::realtime::proto::{self, Cause, Effect, FeedMessage, NamedEnum};
+use super::realtime::service_day_start;
+
+// Closures of stops and of the road between two stops for a range of dates, read from YAML or JSON
+#[derive(Debug, Deserialize)]
+pub struct Scenario {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub closures: Vec<Closure>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Closure {
+    pub id: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(deserialize_with = "deserialize_date")]
+    pub from: NaiveDate,
+    #[serde(deserialize_with = "deserialize_date")]
+    pub to: NaiveDate,
+    // Closed stops, or stations with all their stops
+    #[serde(default)]
+    pub stops: Vec<String>,
+    #[serde(default)]
+    pub segments: Vec<Segment>,
+    // GTFS-RT cause and effect names of the alert, like CONSTRUCTION and DETOUR
+    #[serde(default)]
+    pub cause: Option<String>,
+    #[serde(default)]
+    pub effect: Option<String>,
+}
+
+// The road between two stops of a trip, in either direction, for trips of one or all routes.
+// Stops in between can't be served and trips are split in two around it.
+#[derive(Debug, Deserialize)]
+pub struct Segment {
+    #[serde(default)]
+    pub route_id: Option<String>,
+    pub from_stop: String,
+    pub to_stop: String,
+}
+
+// Dates as 20240131, which YAML reads as a number, or 2024-01-31
+fn deserialize_date<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveDate, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Date {
+        Number(u64),
+        Text(String),
+    }
+    let text = match Date::deserialize(deserializer)? {
+        Date::Number(number) => number.to_string(),
+        Date::Text(text) => text,
+    };
+    NaiveDate::parse_from_str(&text, "%Y%m%d")
+        .or_else(|_| NaiveDate::parse_from_str(&text, "%Y-%m-%d"))
+        .map_err(|_| serde::de::Error::custom(format!("invalid date {}", text)))
+}
+
+pub fn read_scenario(path: &Path) -> Result<Scenario, String> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+    // JSON is read as YAML too
+    serde_yaml::from_str(&contents).map_err(|error| format!("{}: {}", path.display(), error))
+}
+
+// Trips replaced on the dates of a range
+#[derive(Debug, Default)]
+pub struct ReplacedTrips {
+    pub dates: usize,
+    pub edited: usize,
+    pub split: usize,
+    pub canceled: usize,
+    pub routes: HashSet<String>,
+}
+
+fn shift(time: &Time, seconds: i64) -> Time {
+    Time::from_seconds((time.seconds() as i64 + seconds).max(0) as u64)
+}
+
+// The id of a replaced trip's part, numbered where the trip was split
+pub fn part_trip_id(trip_id: &str, tag: &str, number: usize, count: usize) -> String {
+    match count {
+        1 => format!("{}-{}", trip_id, tag),
+        _ => format!("{}-{}-{}", trip_id, tag, number + 1),
+    }
+}
+
+// Replaces trips on the active dates of a range by the parts an edit returns for their stop times,
+// or drops them where it returns none. Trips the edit leaves alone return None. Services of
+// replaced trips are split into one without the range, for the original trips, and one with only
+// the range's dates, for the parts, which get new trip ids tagged like the services.
+pub fn replace_trips(
+    feed: &Feed,
+    from: NaiveDate,
+    to: NaiveDate,
+    tag: &str,
+    mut edit: impl FnMut(&Trip, &[StopTime]) -> Option<Vec<Vec<StopTime>>>,
+) -> (Feed, ReplacedTrips) {
+    let mut replaced = ReplacedTrips::default();
+    let mut dates: HashMap<String, Vec<NaiveDate>> = HashMap::new();
+    for date in from.iter_days().take_while(|date| *date <= to) {
+        for service_id in feed.active_services(date) {
+            dates.entry(service_id).or_default().push(date);
+        }
+    }
+
+    let mut stop_times: HashMap<&str, Vec<&StopTime>> = HashMap::new();
+    for stop_time in &feed.stop_times {
+        stop_times
+            .entry(stop_time.trip_id.as_str())
+            .or_default()
+            .push(stop_time);
+    }
+    let mut frequencies: HashMap<&str, Vec<&Frequency>> = HashMap::new();
+    for frequency in &feed.frequencies {
+        frequencies
+            .entry(frequency.trip_id.as_str())
+            .or_default()
+            .push(frequency);
+    }
+
+    let mut edited = feed.clone();
+    edited.trips.clear();
+    let mut services: BTreeSet<&str> = BTreeSet::new();
+    for trip in &feed.trips {
+        if !dates.contains_key(trip.service_id.as_str()) {
+            edited.trips.push(trip.clone());
+            continue;
+        }
+        let mut trip_stop_times: Vec<StopTime> = stop_times
+            .get(trip.trip_id.as_str())
+            .map(|stop_times| {
+                stop_times
+                    .iter()
+                    .map(|stop_time| (*stop_time).clone())
+                    .collect()
+            })
+            .unwrap_or_default();
+        trip_stop_times.sort_by_key(|stop_time| stop_time.stop_sequence);
+        let Some(parts) = edit(trip, &trip_stop_times) else {
+            edited.trips.push(trip.clone());
+            continue;
+        };
+
+        services.insert(trip.service_id.as_str());
+        replaced.routes.insert(trip.route_id.to_owned());
+        match parts.len() {
+            0 => replaced.canceled += 1,
+            1 => replaced.edited += 1,
+            _ => replaced.split += 1,
+        }
+        edited.trips.push(Trip {
+            service_id: format!("{}-except-{}", trip.service_id, tag),
+            ..trip.clone()
+        });
+        let first_departure = |stop_times: &[StopTime]| {
+            stop_times
+                .first()
+                .and_then(|stop_time| {
+                    stop_time
+                        .departure_time
+                        .as_ref()
+                        .or(stop_time.arrival_time.as_ref())
+                })
+                .map_or(0, |time| time.seconds() as i64)
+        };
+        let start = first_departure(&trip_stop_times);
+        let count = parts.len();
+        for (number, part) in parts.into_iter().enumerate() {
+            let trip_id = part_trip_id(&trip.trip_id, tag, number, count);
+            // Frequencies count from the first departure, which is later for later parts
+            let offset = first_departure(&part) - start;
+            for frequency in frequencies
+                .get(trip.trip_id.as_str())
+                .map(Vec::as_slice)
+                .unwrap_or_default()
+            {
+                edited.frequencies.push(Frequency {
+                    trip_id: trip_id.to_owned(),
+                    start_time: shift(&frequency.start_time, offset),
+                    end_time: shift(&frequency.end_time, offset),
+                    ..(*frequency).clone()
+                });
+            }
+            edited
+                .stop_times
+                .extend(part.into_iter().map(|stop_time| StopTime {
+                    trip_id: trip_id.to_owned(),
+                    ..stop_time
+                }));
+            edited.trips.push(Trip {
+                trip_id,
+                service_id: format!("{}-{}", trip.service_id, tag),
+                ..trip.clone()
+            });
+        }
+    }
+
+    let replaced_dates: BTreeSet<NaiveDate> = services
+        .iter()
+        .flat_map(|service_id| dates[*service_id].iter().copied())
+        .collect();
+    replaced.dates = replaced_dates.len();
+    for service_id in services {
+        let except = format!("{}-except-{}", service_id, tag);
+        for calendar in feed
+            .calendars
+            .iter()
+            .filter(|calendar| calendar.service_id == service_id)
+        {
+            edited.calendars.push(Calendar {
+                service_id: except.to_owned(),
+                ..calendar.clone()
+            });
+        }
+        for calendar_date in feed
+            .calendar_dates
+            .iter()
+            .filter(|calendar_date| calendar_date.service_id == service_id)
+        {
+            edited.calendar_dates.push(CalendarDate {
+                service_id: except.to_owned(),
+                ..calendar_date.clone()
+            });
+        }
+        for date in &dates[service_id] {
+            edited.calendar_dates.push(CalendarDate {
+                service_id: except.to_owned(),
+                date: *date,
+                exception_type: CalendarException::Removed,
+            });
+            edited.calendar_dates.push(CalendarDate {
+                service_id: format!("{}-{}", service_id, tag),
+                date: *date,
+                exception_type: CalendarException::Added,
+            });
+        }
+    }
+    (edited, replaced)
+}
+
+// Leaves out the calls at closed stops, and splits the trip where it would use a closed segment,
+// keeping parts with at least two calls
+fn close(
+    closure: &Closure,
+    closed_stops: &HashSet<&str>,
+    trip: &Trip,
+    stop_times: &[StopTime],
+) -> Option<Vec<Vec<StopTime>>> {
+    let stop_ids: Vec<&str> = stop_times
+        .iter()
+        .map(|stop_time| stop_time.stop_id.as_deref().unwrap_or_default())
+        .collect();
+    let mut removed: Vec<bool> = stop_ids
+        .iter()
+        .map(|stop_id| closed_stops.contains(stop_id))
+        .collect();
+    let mut splits: HashSet<usize> = HashSet::new();
+    for segment in &closure.segments {
+        if segment
+            .route_id
+            .as_ref()
+            .is_some_and(|route_id| *route_id != trip.route_id)
+        {
+            continue;
+        }
+        let from = stop_ids
+            .iter()
+            .position(|stop_id| *stop_id == segment.from_stop);
+        let to = stop_ids
+            .iter()
+            .position(|stop_id| *stop_id == segment.to_stop);
+        let (Some(from), Some(to)) = (from, to) else {
+            continue;
+        };
+        let (first, last) = (from.min(to), from.max(to));
+        removed[first + 1..last]
+            .iter_mut()
+            .for_each(|removed| *removed = true);
+        splits.insert(last);
+    }
+    if !removed.contains(&true) && splits.is_empty() {
+        return None;
+    }
+    Some(cut_trip(stop_times, &removed, &splits))
+}
+
+// The parts of a trip without its removed calls, starting new parts at the splits, that still
+// have at least two calls
+pub fn cut_trip(
+    stop_times: &[StopTime],
+    removed: &[bool],
+    splits: &HashSet<usize>,
+) -> Vec<Vec<StopTime>> {
+    let mut parts: Vec<Vec<StopTime>> = vec![Vec::new()];
+    for (index, stop_time) in stop_times.iter().enumerate() {
+        if splits.contains(&index) {
+            parts.push(Vec::new());
+        }
+        if !removed[index] {
+            parts.last_mut().unwrap().push(stop_time.clone());
+        }
+    }
+    parts.retain(|part| part.len() >= 2);
+    parts
+}
+
+fn translated(text: &str) -> proto::TranslatedString {
+    proto::TranslatedString {
+        translation: vec![proto::Translation {
+            text: text.to_owned(),
+            language: None,
+        }],
+    }
+}
+
+pub struct ClosureReport {
+    pub id: String,
+    pub trips: ReplacedTrips,
+}
+
+pub struct AppliedScenario {
+    pub feed: Feed,
+    pub reports: Vec<ClosureReport>,
+    pub alerts: FeedMessage,
+}
+
+// Applies the closures one after another, and describes each as an alert for its stops and the
+// routes of the trips it changes, active over its dates
+pub fn apply_scenario(feed: &Feed, scenario: &Scenario) -> Result<AppliedScenario, String> {
+    let stop_ids: HashSet<&str> = feed
+        .stops
+        .iter()
+        .map(|stop| stop.stop_id.as_str())
+        .collect();
+    let timezone = feed.timezone();
+    let mut applied = AppliedScenario {
+        feed: feed.clone(),
+        reports: Vec::new(),
+        alerts: FeedMessage {
+            header: proto::FeedHeader {
+                gtfs_realtime_version: String::from("2.0"),
+                incrementality: Some(proto::Incrementality::FullDataset as i32),
+                timestamp: None,
+            },
+            entity: Vec::new(),
+        },
+    };
+    for closure in &scenario.closures {
+        if closure.to < closure.from {
+            return Err(format!("Closure {} ends before it starts", closure.id));
+        }
+        let segment_stops = closure
+            .segments
+            .iter()
+            .flat_map(|segment| [&segment.from_stop, &segment.to_stop]);
+        if let Some(stop_id) = closure
+            .stops
+            .iter()
+            .chain(segment_stops)
+            .find(|stop_id| !stop_ids.contains(stop_id.as_str()))
+        {
+            return Err(format!("Closure {}: unknown stop {}", closure.id, stop_id));
+        }
+        if let Some(segment) = closure
+            .segments
+            .iter()
+            .find(|segment| segment.from_stop == segment.to_stop)
+        {
+            return Err(format!(
+                "Closure {}: segment from and to {}",
+                closure.id, segment.from_stop
+            ));
+        }
+        let cause = match &closure.cause {
+            Some(cause) => Some(
+                Cause::value(cause)
+                    .ok_or(format!("Closure {}: unknown cause {}", closure.id, cause))?,
+            ),
+            None => None,
+        };
+        let effect = match &closure.effect {
+            Some(effect) => Effect::value(effect)
+                .ok_or(format!("Closure {}: unknown effect {}", closure.id, effect))?,
+            None => Effect::ModifiedService as i32,
+        };
+
+        let closed_stops: HashSet<&str> = feed
+            .stops
+            .iter()
+            .filter(|stop| {
+                closure.stops.contains(&stop.stop_id)
+                    || stop
+                        .parent_station
+                        .as_ref()
+                        .is_some_and(|parent| closure.stops.contains(parent))
+            })
+            .map(|stop| stop.stop_id.as_str())
+            .collect();
+        let (edited, trips) = replace_trips(
+            &applied.feed,
+            closure.from,
+            closure.to,
+            &closure.id,
+            |trip, stop_times| close(closure, &closed_stops, trip, stop_times),
+        );
+        applied.feed = edited;
+
+        let mut informed_entity: Vec<proto::EntitySelector> = closure
+            .stops
+            .iter()
+            .map(|stop_id| proto::EntitySelector {
+                stop_id: Some(stop_id.to_owned()),
+                ..Default::default()
+            })
+            .collect();
+        let routes: BTreeSet<&String> = trips.routes.iter().collect();
+        informed_entity.extend(routes.into_iter().map(|route_id| proto::EntitySelector {
+            route_id: Some(route_id.to_owned()),
+            ..Default::default()
+        }));
+        let end = closure.to.succ_opt().unwrap_or(closure.to);
+        applied.alerts.entity.push(proto::FeedEntity {
+            id: closure.id.to_owned(),
+            alert: Some(proto::Alert {
+                active_period: vec![proto::TimeRange {
+                    start: Some(service_day_start(closure.from, timezone) as u64),
+                    end: Some(service_day_start(end, timezone) as u64),
+                }],
+                informed_entity,
+                cause,
+                effect: Some(effect),
+                header_text: Some(translated(
+                    closure.description.as_deref().unwrap_or(&closure.id),
+                )),
+                description_text: scenario.name.as_deref().map(translated),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        applied.reports.push(ClosureReport {
+            id: closure.id.to_owned(),
+            trips,
+        });
+    }
+    Ok(applied)
+}
+
+#[test]
+fn test_apply_scenario() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let feed = Feed::load(&mut gtfs_file);
+
+    let mut scenario: Scenario = serde_yaml::from_str(
+        "name: Roadworks
+closures:
+  - id: dadan
+    description: Road between North Ave and Doing Ave closed
+    from: 20070605
+    to: 2007-06-06
+    segments:
+      - route_id: CITY
+        from_stop: NANAA
+        to_stop: DADAN
+    cause: CONSTRUCTION
+    effect: DETOUR
+  - id: airport
+    from: 20070605
+    to: 20070605
+    stops: [BEATTY_AIRPORT]
+",
+    )
+    .unwrap();
+    let applied = apply_scenario(&feed, &scenario).unwrap();
+    let dadan = &applied.reports[0].trips;
+    assert_eq!(
+        (dadan.dates, dadan.split, dadan.edited, dadan.canceled),
+        (2, 2, 0, 0)
+    );
+    // Both AB trips and the shuttle have only one other stop, and weekend AAMV trips don't run
+    let airport = &applied.reports[1].trips;
+    assert_eq!((airport.dates, airport.edited, airport.canceled), (1, 0, 3));
+
+    let trip_ids = |date: NaiveDate| -> HashSet<String> {
+        let services = applied.feed.active_services(date);
+        applied
+            .feed
+            .trips
+            .iter()
+            .filter(|trip| services.contains(&trip.service_id))
+            .map(|trip| trip.trip_id.to_owned())
+            .collect()
+    };
+    let closed = trip_ids(NaiveDate::from_ymd_opt(2007, 6, 5).unwrap());
+    assert!(
+        closed.contains("CITY1-dadan-1")
+            && closed.contains("CITY1-dadan-2")
+            && !closed.contains("CITY1")
+    );
+    assert!(!closed.contains("AB1") && closed.contains("BFC1"));
+    let open = trip_ids(NaiveDate::from_ymd_opt(2007, 6, 7).unwrap());
+    assert!(open.contains("CITY1") && open.contains("AB1") && !open.contains("CITY1-dadan-1"));
+
+    // The second part starts at DADAN, 21 minutes after the trip did
+    let second: Vec<&str> = applied
+        .feed
+        .stop_times
+        .iter()
+        .filter(|stop_time| stop_time.trip_id == "CITY1-dadan-2")
+        .filter_map(|stop_time| stop_time.stop_id.as_deref())
+        .collect();
+    assert_eq!(second, vec!["DADAN", "EMSI"]);
+    let frequency = applied
+        .feed
+        .frequencies
+        .iter()
+        .find(|frequency| frequency.trip_id == "CITY1-dadan-2")
+        .unwrap();
+    assert_eq!(frequency.start_time, Time { h: 6, m: 21, s: 0 });
+
+    let alert = applied.alerts.entity[0].alert.as_ref().unwrap();
+    assert_eq!(alert.effect, Some(Effect::Detour as i32));
+    assert_eq!(alert.informed_entity[0].route_id.as_deref(), Some("CITY"));
+    assert_eq!(
+        alert.active_period[0].end.unwrap() - alert.active_period[0].start.unwrap(),
+        2 * 86400
+    );
+    assert_eq!(
+        applied.alerts.entity[1]
+            .alert
+            .as_ref()
+            .unwrap()
+            .informed_entity[0]
+            .stop_id
+            .as_deref(),
+        Some("BEATTY_AIRPORT")
+    );
+
+    // A segment from a stop to itself is a typo rather than a closure
+    scenario.closures[0].segments[0].to_stop = String::from("NANAA");
+    assert!(apply_scenario(&feed, &scenario).is_err());
+}
@@ -0,0 +1,290 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+#[cfg(test)]
+use std::path::PathBuf;
+
+use chrono::{Datelike, NaiveDate, Weekday};
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::objects::feed::Feed;
+use crate::objects::pattern;
+use crate::objects::trip;
+
+use super::calendar;
+use super::geodata::{self, line_length};
+#[cfg(test)]
+use super::gtfs;
+use super::gtfs::Route;
+use super::round;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum StatsFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum DayType {
+    Weekday,
+    Saturday,
+    Sunday,
+}
+
+impl DayType {
+    fn matches(&self, weekday: Weekday) -> bool {
+        match self {
+            DayType::Weekday => !matches!(weekday, Weekday::Sat | Weekday::Sun),
+            DayType::Saturday => weekday == Weekday::Sat,
+            DayType::Sunday => weekday == Weekday::Sun,
+        }
+    }
+}
+
+pub enum ServiceDay {
+    Date(NaiveDate),
+    // Averaged over every date of this type within the feed's calendar
+    Typical(DayType),
+}
+
+impl ServiceDay {
+    fn label(&self) -> String {
+        match self {
+            ServiceDay::Date(date) => date.format("%Y-%m-%d").to_string(),
+            ServiceDay::Typical(day_type) => format!("{:?}", day_type).to_lowercase(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum KpiLevel {
+    Total,
+    Agency,
+    RouteType,
+    Route,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ServiceKpi {
+    pub day: String,
+    pub dates: usize,
+    pub level: KpiLevel,
+    pub id: String,
+    pub name: Option<String>,
+    pub trips: f64,
+    pub vehicle_km: f64,
+    pub vehicle_hours: f64,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Totals {
+    trips: f64,
+    km: f64,
+    hours: f64,
+}
+
+impl Totals {
+    fn add(&mut self, other: &Totals) {
+        self.trips += other.trips;
+        self.km += other.km;
+        self.hours += other.hours;
+    }
+}
+
+pub fn service_kpis(feed: &Feed, days: &[ServiceDay]) -> Vec<ServiceKpi> {
+    let trip_km = trip_distances(feed);
+    let routes: HashMap<&str, &Route> = feed
+        .routes
+        .iter()
+        .map(|route| (route.route_id.as_str(), route))
+        .collect();
+
+    // Every trip instance of the feed, regardless of date, summed per group and service id
+    let all_services: HashSet<String> = feed
+        .trips
+        .iter()
+        .map(|trip| trip.service_id.to_owned())
+        .collect();
+    let mut group_totals: BTreeMap<(KpiLevel, String), HashMap<String, Totals>> = BTreeMap::new();
+    let mut names: HashMap<(KpiLevel, String), String> = HashMap::new();
+    for instance in feed.trip_instances(&all_services) {
+        let Some(route) = routes.get(instance.trip.route_id.as_str()) else {
+            continue;
+        };
+        let totals = Totals {
+            trips: 1.0,
+            km: trip_km.get(&instance.trip.trip_id).copied().unwrap_or(0.0) / 1000.0,
+            hours: instance.end_time().saturating_sub(instance.start_time()) as f64 / 3600.0,
+        };
+
+        let agency_id = feed.route_agency(route);
+        let mut groups = vec![
+            (KpiLevel::Total, String::from("all"), None),
+            (
+                KpiLevel::Agency,
+                agency_id.to_owned(),
+                agency_name(feed, &agency_id),
+            ),
+            (
+                KpiLevel::Route,
+                route.route_id.to_owned(),
+                Some(pattern::route_name(route).to_owned()),
+            ),
+        ];
+        if let Some(route_type) = &route.route_type {
+            groups.push((
+                KpiLevel::RouteType,
+                route_type.code().to_string(),
+                Some(route_type.name()),
+            ));
+        }
+
+        for (level, id, name) in groups {
+            if let Some(name) = name {
+                names.insert((level, id.to_owned()), name);
+            }
+            group_totals
+                .entry((level, id))
+                .or_default()
+                .entry(instance.trip.service_id.to_owned())
+                .or_default()
+                .add(&totals);
+        }
+    }
+
+    let date_range = calendar::service_date_range(&feed.calendars, &feed.calendar_dates);
+    let mut kpis: Vec<ServiceKpi> = Vec::new();
+    for day in days {
+        let dates: Vec<NaiveDate> = match day {
+            ServiceDay::Date(date) => vec![*date],
+            ServiceDay::Typical(day_type) => match date_range {
+                Some((start, end)) => start
+                    .iter_days()
+                    .take_while(|date| *date <= end)
+                    .filter(|date| day_type.matches(date.weekday()))
+                    .collect(),
+                None => vec![],
+            },
+        };
+        if dates.is_empty() {
+            continue;
+        }
+
+        let mut day_totals: BTreeMap<&(KpiLevel, String), Totals> = BTreeMap::new();
+        for date in &dates {
+            let services = feed.active_services(*date);
+            for (group, service_totals) in &group_totals {
+                let totals = day_totals.entry(group).or_default();
+                for service_id in &services {
+                    if let Some(service) = service_totals.get(service_id) {
+                        totals.add(service);
+                    }
+                }
+            }
+        }
+
+        let count = dates.len() as f64;
+        for (group, totals) in day_totals {
+            kpis.push(ServiceKpi {
+                day: day.label(),
+                dates: dates.len(),
+                level: group.0,
+                id: group.1.to_owned(),
+                name: names.get(group).cloned(),
+                trips: round(totals.trips / count, 2),
+                vehicle_km: round(totals.km / count, 2),
+                vehicle_hours: round(totals.hours / count, 2),
+            });
+        }
+    }
+
+    kpis
+}
+
+// Length of every trip in metres, along its shape or else between consecutive stops
+fn trip_distances(feed: &Feed) -> HashMap<String, f64> {
+    let shape_lengths: HashMap<String, f64> = geodata::shape_lines(&feed.shapes)
+        .into_iter()
+        .map(|(shape_id, points)| (shape_id, line_length(&points)))
+        .collect();
+    let stop_points: HashMap<&str, geo_types::Point> = feed
+        .stops
+        .iter()
+        .filter_map(|stop| Some((stop.stop_id.as_str(), geodata::stop_point(stop)?)))
+        .collect();
+    let timetable = trip::stop_times_by_trip(&feed.stop_times);
+
+    let mut distances: HashMap<String, f64> = HashMap::new();
+    for trip in &feed.trips {
+        let shape_length = trip
+            .shape_id
+            .as_ref()
+            .and_then(|shape_id| shape_lengths.get(shape_id))
+            .filter(|length| **length > 0.0);
+        let distance = match shape_length {
+            Some(length) => *length,
+            None => {
+                let points: Vec<geo_types::Point> = timetable
+                    .get(&trip.trip_id)
+                    .map(|stop_times| {
+                        stop_times
+                            .iter()
+                            .filter_map(|stop_time| stop_points.get(stop_time.stop_id.as_deref()?))
+                            .copied()
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                line_length(&points)
+            }
+        };
+        distances.insert(trip.trip_id.to_owned(), distance);
+    }
+
+    distances
+}
+
+fn agency_name(feed: &Feed, agency_id: &str) -> Option<String> {
+    feed.agencies
+        .iter()
+        .find(|agency| {
+            agency.agency_id.as_deref() == Some(agency_id) || agency.agency_name == agency_id
+        })
+        .map(|agency| agency.agency_name.to_owned())
+}
+
+#[test]
+fn test_service_kpis() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let feed = Feed::load(&mut gtfs_file);
+
+    let saturday = NaiveDate::from_ymd_opt(2007, 6, 2).unwrap();
+    let days = [
+        ServiceDay::Date(saturday),
+        ServiceDay::Typical(DayType::Weekday),
+    ];
+    let kpis = service_kpis(&feed, &days);
+
+    let find = |day: &str, level: KpiLevel, id: &str| {
+        kpis.iter()
+            .find(|kpi| kpi.day == day && kpi.level == level && kpi.id == id)
+            .unwrap()
+    };
+
+    // The amargosa valley route only runs on weekends, four trips of one hour
+    let amargosa = find("2007-06-02", KpiLevel::Route, "AAMV");
+    assert_eq!(amargosa.trips, 4.0);
+    assert_eq!(amargosa.vehicle_hours, 4.0);
+    assert!(amargosa.vehicle_km > 4.0 * 40.0 && amargosa.vehicle_km < 4.0 * 45.0);
+    assert_eq!(
+        find("2007-06-02", KpiLevel::Agency, "DTA").name.as_deref(),
+        Some("Demo Transit Authority")
+    );
+    assert_eq!(
+        find("2007-06-02", KpiLevel::RouteType, "3").name.as_deref(),
+        Some("Bus")
+    );
+
+    let weekday_total = find("weekday", KpiLevel::Total, "all");
+    assert!(weekday_total.trips < find("2007-06-02", KpiLevel::Total, "all").trips);
+}
@@ -0,0 +1,336 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+#[cfg(test)]
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use super::calendar;
+use super::gtfs::{
+    self, Agency, Calendar, CalendarDate, FeedInfo, GtfsObject, LocationType, Route, Stop,
+    StopTime, Trip,
+};
+
+const WARNING_LIMIT: usize = 10;
+
+#[derive(Debug, Serialize)]
+pub struct FeedSummary {
+    pub tables: BTreeMap<String, usize>,
+    pub service_start: Option<String>,
+    pub service_end: Option<String>,
+    pub feed_publisher: Option<String>,
+    pub feed_version: Option<String>,
+    pub feed_start: Option<String>,
+    pub feed_end: Option<String>,
+    pub agencies: Vec<String>,
+    pub routes_by_type: BTreeMap<String, usize>,
+    pub stops_by_location_type: BTreeMap<String, usize>,
+    // min lon, min lat, max lon, max lat
+    pub bounding_box: Option<[f64; 4]>,
+    pub features: BTreeMap<String, bool>,
+    pub warnings: Vec<Warning>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Warning {
+    pub count: usize,
+    pub message: String,
+}
+
+// Optional spec features, and the files that indicate their presence
+const FEATURES: [(&str, &[&str]); 10] = [
+    ("shapes", &["shapes.txt"]),
+    ("frequencies", &["frequencies.txt"]),
+    ("transfers", &["transfers.txt"]),
+    ("pathways", &["pathways.txt", "levels.txt"]),
+    ("fares_v1", &["fare_attributes.txt", "fare_rules.txt"]),
+    (
+        "fares_v2",
+        &[
+            "fare_products.txt",
+            "fare_leg_rules.txt",
+            "fare_transfer_rules.txt",
+        ],
+    ),
+    (
+        "flex",
+        &[
+            "locations.geojson",
+            "location_groups.txt",
+            "booking_rules.txt",
+        ],
+    ),
+    ("translations", &["translations.txt"]),
+    ("feed_info", &["feed_info.txt"]),
+    ("attributions", &["attributions.txt"]),
+];
+
+pub fn summarize(gtfs_file: &mut gtfs::GtfsFile) -> FeedSummary {
+    let mut warnings: Vec<Warning> = Vec::new();
+
+    let files = gtfs_file.list_files();
+    let mut tables: BTreeMap<String, usize> = BTreeMap::new();
+    for file in &files {
+        if file.ends_with(".txt") {
+            tables.insert(file.to_owned(), gtfs_file.count_rows(file));
+        }
+    }
+    let mut features: BTreeMap<String, bool> = BTreeMap::new();
+    for (feature, feature_files) in FEATURES {
+        let present = feature_files
+            .iter()
+            .any(|file| files.iter().any(|name| name == file) && tables.get(*file) != Some(&0));
+        features.insert(feature.to_owned(), present);
+    }
+
+    let agencies: Vec<Agency> = read_reporting_errors(gtfs_file, &mut warnings);
+    let stops: Vec<Stop> = read_reporting_errors(gtfs_file, &mut warnings);
+    let routes: Vec<Route> = read_reporting_errors(gtfs_file, &mut warnings);
+    let trips: Vec<Trip> = read_reporting_errors(gtfs_file, &mut warnings);
+    let stop_times: Vec<StopTime> = read_reporting_errors(gtfs_file, &mut warnings);
+    let calendars: Vec<Calendar> = read_reporting_errors(gtfs_file, &mut warnings);
+    let calendar_dates: Vec<CalendarDate> = read_reporting_errors(gtfs_file, &mut warnings);
+    let feed_info: Vec<FeedInfo> = read_reporting_errors(gtfs_file, &mut warnings);
+
+    let service_range = calendar::service_date_range(&calendars, &calendar_dates);
+    let feed_info = feed_info.first();
+
+    let mut routes_by_type: BTreeMap<String, usize> = BTreeMap::new();
+    for route in &routes {
+        let route_type = match &route.route_type {
+            Some(route_type) => format!("{} ({})", route_type.name(), route_type.code()),
+            None => String::from("Unknown"),
+        };
+        *routes_by_type.entry(route_type).or_default() += 1;
+    }
+
+    let mut stops_by_location_type: BTreeMap<String, usize> = BTreeMap::new();
+    for stop in &stops {
+        let location_type = stop.location_type.clone().unwrap_or(LocationType::Stop);
+        *stops_by_location_type
+            .entry(format!("{:?}", location_type))
+            .or_default() += 1;
+    }
+
+    warnings.append(&mut reference_warnings(
+        &stops,
+        &routes,
+        &trips,
+        &stop_times,
+    ));
+    if feed_info.is_none() {
+        warnings.push(Warning {
+            count: 1,
+            message: String::from("feed_info.txt is missing or empty"),
+        });
+    }
+    warnings.sort_by_key(|warning| std::cmp::Reverse(warning.count));
+
+    FeedSummary {
+        tables,
+        service_start: service_range.map(|(start, _)| start.to_string()),
+        service_end: service_range.map(|(_, end)| end.to_string()),
+        feed_publisher: feed_info.map(|info| info.feed_publisher_name.to_owned()),
+        feed_version: feed_info.and_then(|info| info.feed_version.clone()),
+        feed_start: feed_info
+            .and_then(|info| info.feed_start_date)
+            .map(|date| date.to_string()),
+        feed_end: feed_info
+            .and_then(|info| info.feed_end_date)
+            .map(|date| date.to_string()),
+        agencies: agencies
+            .into_iter()
+            .map(|agency| agency.agency_name)
+            .collect(),
+        routes_by_type,
+        stops_by_location_type,
+        bounding_box: bounding_box(&stops),
+        features,
+        warnings,
+    }
+}
+
+pub fn print_summary(summary: &FeedSummary) {
+    println!("Tables:");
+    for (table, rows) in &summary.tables {
+        println!("  {}: {} rows", table, rows);
+    }
+    println!(
+        "Service: {} to {}",
+        summary.service_start.as_deref().unwrap_or("?"),
+        summary.service_end.as_deref().unwrap_or("?")
+    );
+    if let Some(publisher) = &summary.feed_publisher {
+        println!(
+            "Feed info: {} version {}, valid {} to {}",
+            publisher,
+            summary.feed_version.as_deref().unwrap_or("?"),
+            summary.feed_start.as_deref().unwrap_or("?"),
+            summary.feed_end.as_deref().unwrap_or("?")
+        );
+    }
+    println!("Agencies: {}", summary.agencies.join(", "));
+    println!("Routes by type:");
+    for (route_type, count) in &summary.routes_by_type {
+        println!("  {}: {}", route_type, count);
+    }
+    println!("Stops by location type:");
+    for (location_type, count) in &summary.stops_by_location_type {
+        println!("  {}: {}", location_type, count);
+    }
+    if let Some([min_lon, min_lat, max_lon, max_lat]) = summary.bounding_box {
+        println!(
+            "Bounding box: {}, {} to {}, {}",
+            min_lon, min_lat, max_lon, max_lat
+        );
+    }
+    let features: Vec<String> = summary
+        .features
+        .iter()
+        .map(|(feature, present)| format!("{}: {}", feature, if *present { "yes" } else { "no" }))
+        .collect();
+    println!("Features: {}", features.join(", "));
+    println!("Warnings:");
+    for warning in summary.warnings.iter().take(WARNING_LIMIT) {
+        println!("  {} ({})", warning.message, warning.count);
+    }
+    if summary.warnings.len() > WARNING_LIMIT {
+        println!("  ... and {} more", summary.warnings.len() - WARNING_LIMIT);
+    }
+}
+
+fn read_reporting_errors<T>(gtfs_file: &mut gtfs::GtfsFile, warnings: &mut Vec<Warning>) -> Vec<T>
+where
+    T: for<'a> GtfsObject + for<'de> serde::Deserialize<'de>,
+{
+    let (records, errors) = gtfs_file.read_vec_lossy::<T>();
+    if let Some(first) = errors.first() {
+        warnings.push(Warning {
+            count: errors.len(),
+            message: format!("unreadable rows in {}, first: {}", T::FILE, first),
+        });
+    }
+    records
+}
+
+fn reference_warnings(
+    stops: &[Stop],
+    routes: &[Route],
+    trips: &[Trip],
+    stop_times: &[StopTime],
+) -> Vec<Warning> {
+    let route_ids: HashSet<&str> = routes.iter().map(|route| route.route_id.as_str()).collect();
+    let stop_ids: HashSet<&str> = stops.iter().map(|stop| stop.stop_id.as_str()).collect();
+    let mut trips_per_route: HashMap<&str, usize> = HashMap::new();
+    for trip in trips {
+        *trips_per_route.entry(trip.route_id.as_str()).or_default() += 1;
+    }
+    let timed_trips: HashSet<&str> = stop_times
+        .iter()
+        .map(|stop_time| stop_time.trip_id.as_str())
+        .collect();
+    let served_stops: HashSet<&str> = stop_times
+        .iter()
+        .filter_map(|stop_time| stop_time.stop_id.as_deref())
+        .collect();
+
+    let boarding_stops = stops
+        .iter()
+        .filter(|stop| matches!(stop.location_type, None | Some(LocationType::Stop)));
+    let counts = [
+        (
+            stops
+                .iter()
+                .filter(|stop| {
+                    matches!(
+                        stop.location_type,
+                        None | Some(LocationType::Stop)
+                            | Some(LocationType::Station)
+                            | Some(LocationType::EntranceExit)
+                    ) && (stop.stop_lat.is_none() || stop.stop_lon.is_none())
+                })
+                .count(),
+            "stops without coordinates",
+        ),
+        (
+            boarding_stops
+                .filter(|stop| !served_stops.contains(stop.stop_id.as_str()))
+                .count(),
+            "stops not served by any trip",
+        ),
+        (
+            routes
+                .iter()
+                .filter(|route| !trips_per_route.contains_key(route.route_id.as_str()))
+                .count(),
+            "routes without trips",
+        ),
+        (
+            trips
+                .iter()
+                .filter(|trip| !route_ids.contains(trip.route_id.as_str()))
+                .count(),
+            "trips referencing an unknown route",
+        ),
+        (
+            trips
+                .iter()
+                .filter(|trip| !timed_trips.contains(trip.trip_id.as_str()))
+                .count(),
+            "trips without stop times",
+        ),
+        (
+            served_stops
+                .iter()
+                .filter(|stop_id| !stop_ids.contains(*stop_id))
+                .count(),
+            "stop ids in stop_times.txt missing from stops.txt",
+        ),
+    ];
+
+    counts
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, message)| Warning {
+            count,
+            message: message.to_owned(),
+        })
+        .collect()
+}
+
+fn bounding_box(stops: &[Stop]) -> Option<[f64; 4]> {
+    let mut bounds: Option<[f64; 4]> = None;
+    for stop in stops {
+        let (Some(lon), Some(lat)) = (stop.stop_lon, stop.stop_lat) else {
+            continue;
+        };
+        bounds = Some(match bounds {
+            None => [lon, lat, lon, lat],
+            Some([min_lon, min_lat, max_lon, max_lat]) => [
+                min_lon.min(lon),
+                min_lat.min(lat),
+                max_lon.max(lon),
+                max_lat.max(lat),
+            ],
+        });
+    }
+    bounds
+}
+
+#[test]
+fn test_summarize_broken_sample() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let summary = summarize(&mut gtfs_file);
+
+    assert_eq!(summary.tables.get("stop_times.txt"), Some(&28));
+    assert_eq!(summary.service_start.as_deref(), Some("2007-01-01"));
+    assert_eq!(summary.service_end.as_deref(), Some("2010-12-31"));
+    assert_eq!(summary.agencies, vec!["Demo Transit Authority"]);
+    assert_eq!(summary.routes_by_type.get("Bus (3)"), Some(&5));
+    assert_eq!(summary.stops_by_location_type.get("Stop"), Some(&9));
+    assert_eq!(summary.features.get("frequencies"), Some(&true));
+    assert_eq!(summary.features.get("shapes"), Some(&false));
+    assert!(summary.warnings.iter().any(|warning| warning
+        .message
+        .starts_with("unreadable rows in stop_times.txt")));
+}
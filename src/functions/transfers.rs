@@ -0,0 +1,282 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+#[cfg(test)]
+use std::path::PathBuf;
+
+#[cfg(test)]
+use crate::objects::feed::Feed;
+use crate::objects::spatial::SpatialIndex;
+
+use super::geodata;
+#[cfg(test)]
+use super::gtfs;
+use super::gtfs::{LocationType, Pathway, Stop, Transfer, TransferType};
+use super::routing::WalkSettings;
+
+pub struct TransferRules {
+    // The feed's rules followed by the generated ones
+    pub transfers: Vec<Transfer>,
+    pub generated: usize,
+}
+
+// Walking transfers between stops within the walking distance, or along the pathways of stations
+// that have them. Pairs of stops the feed already has a stop to stop rule for keep that rule.
+pub fn generate_transfers(
+    stops: &[Stop],
+    pathways: &[Pathway],
+    existing: &[Transfer],
+    walk: WalkSettings,
+) -> TransferRules {
+    // Pathways describe every way through a station, so its stops are only linked along them
+    let walked = pathway_times(stops, pathways, walk);
+    let stations = station_ids(stops);
+    let pathway_stations: HashSet<&str> = walked.keys().map(|(from, _)| stations[*from]).collect();
+
+    let mut times: BTreeMap<(usize, usize), u64> = walked;
+    let index = SpatialIndex::new(stops, &[]);
+    for (a, b, distance) in index.stop_pairs_within(walk.max_distance_m) {
+        if !is_boarding_stop(&stops[a]) || !is_boarding_stop(&stops[b]) {
+            continue;
+        }
+        if stations[a] == stations[b] && pathway_stations.contains(stations[a]) {
+            continue;
+        }
+        times.insert((a, b), walk.seconds(distance));
+        times.insert((b, a), walk.seconds(distance));
+    }
+
+    let ruled: HashSet<(&str, &str)> = existing
+        .iter()
+        .filter(|transfer| {
+            transfer.from_route_id.is_none()
+                && transfer.to_route_id.is_none()
+                && transfer.from_trip_id.is_none()
+                && transfer.to_trip_id.is_none()
+        })
+        .filter_map(|transfer| {
+            Some((
+                transfer.from_stop_id.as_deref()?,
+                transfer.to_stop_id.as_deref()?,
+            ))
+        })
+        .collect();
+
+    let mut transfers = existing.to_vec();
+    let mut generated = 0;
+    for ((from, to), seconds) in times {
+        let (from, to) = (&stops[from].stop_id, &stops[to].stop_id);
+        if ruled.contains(&(from.as_str(), to.as_str())) {
+            continue;
+        }
+        transfers.push(Transfer {
+            from_stop_id: Some(from.to_owned()),
+            to_stop_id: Some(to.to_owned()),
+            from_route_id: None,
+            to_route_id: None,
+            from_trip_id: None,
+            to_trip_id: None,
+            transfer_type: TransferType::MinTime,
+            min_transfer_time: Some(seconds),
+        });
+        generated += 1;
+    }
+    TransferRules {
+        transfers,
+        generated,
+    }
+}
+
+// Transfers are only between stops and platforms that trips call at
+fn is_boarding_stop(stop: &Stop) -> bool {
+    matches!(stop.location_type, None | Some(LocationType::Stop))
+}
+
+// The outermost parent of every stop, so that boarding areas belong to their platform's station
+fn station_ids(stops: &[Stop]) -> Vec<&str> {
+    let parents: HashMap<&str, &str> = stops
+        .iter()
+        .filter_map(|stop| {
+            Some((
+                stop.stop_id.as_str(),
+                stop.parent_station
+                    .as_deref()
+                    .filter(|parent| !parent.is_empty())?,
+            ))
+        })
+        .collect();
+    stops
+        .iter()
+        .map(|stop| {
+            let mut station = stop.stop_id.as_str();
+            for _ in 0..3 {
+                match parents.get(station) {
+                    Some(parent) => station = parent,
+                    None => break,
+                }
+            }
+            station
+        })
+        .collect()
+}
+
+// Shortest walks along pathways between the boarding stops of each station. Pathways without a
+// traversal time take their length, or the distance between their ends, at walking speed.
+fn pathway_times(
+    stops: &[Stop],
+    pathways: &[Pathway],
+    walk: WalkSettings,
+) -> BTreeMap<(usize, usize), u64> {
+    let index: HashMap<&str, usize> = stops
+        .iter()
+        .enumerate()
+        .map(|(index, stop)| (stop.stop_id.as_str(), index))
+        .collect();
+    let mut edges: Vec<Vec<(usize, u64)>> = vec![Vec::new(); stops.len()];
+    for pathway in pathways {
+        let (Some(from), Some(to)) = (
+            index.get(pathway.from_stop_id.as_str()),
+            index.get(pathway.to_stop_id.as_str()),
+        ) else {
+            continue;
+        };
+        let seconds = pathway
+            .traversal_time
+            .or_else(|| pathway.length.map(|length| walk.seconds(length)))
+            .or_else(|| {
+                let distance = geodata::haversine_distance(
+                    geodata::stop_point(&stops[*from])?,
+                    geodata::stop_point(&stops[*to])?,
+                );
+                Some(walk.seconds(distance))
+            });
+        let Some(seconds) = seconds else {
+            continue;
+        };
+        edges[*from].push((*to, seconds));
+        if pathway.is_bidirectional {
+            edges[*to].push((*from, seconds));
+        }
+    }
+
+    let mut times: BTreeMap<(usize, usize), u64> = BTreeMap::new();
+    for (source, stop) in stops.iter().enumerate() {
+        if !is_boarding_stop(stop) || edges[source].is_empty() {
+            continue;
+        }
+        let mut distances: HashMap<usize, u64> = HashMap::from([(source, 0)]);
+        let mut queue = BinaryHeap::from([Reverse((0, source))]);
+        while let Some(Reverse((time, node))) = queue.pop() {
+            if distances.get(&node).is_some_and(|known| time > *known) {
+                continue;
+            }
+            if node != source && is_boarding_stop(&stops[node]) {
+                times.insert((source, node), time);
+            }
+            for (next, seconds) in &edges[node] {
+                let arrival = time + seconds;
+                if distances.get(next).map_or(true, |known| arrival < *known) {
+                    distances.insert(*next, arrival);
+                    queue.push(Reverse((arrival, *next)));
+                }
+            }
+        }
+    }
+    times
+}
+
+#[test]
+fn test_generate_transfers() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let feed = Feed::load(&mut gtfs_file);
+    let walk = WalkSettings {
+        max_distance_m: 600.0,
+        speed_m_s: 1.0,
+    };
+
+    // Only the two stops on North Avenue are close enough, and a rule for one direction is kept
+    let existing = vec![Transfer {
+        from_stop_id: Some(String::from("NANAA")),
+        to_stop_id: Some(String::from("NADAV")),
+        from_route_id: None,
+        to_route_id: None,
+        from_trip_id: None,
+        to_trip_id: None,
+        transfer_type: TransferType::NotPossible,
+        min_transfer_time: None,
+    }];
+    let rules = generate_transfers(&feed.stops, &[], &existing, walk);
+    assert_eq!(rules.generated, 1);
+    assert_eq!(rules.transfers.len(), 2);
+    assert_eq!(rules.transfers[0].transfer_type, TransferType::NotPossible);
+    let generated = &rules.transfers[1];
+    assert_eq!(generated.from_stop_id.as_deref(), Some("NADAV"));
+    assert_eq!(generated.transfer_type, TransferType::MinTime);
+    assert!(generated
+        .min_transfer_time
+        .is_some_and(|time| time > 500 && time < 700));
+}
+
+#[test]
+fn test_pathway_transfers() {
+    let stop = |stop_id: &str, location_type: LocationType, parent: Option<&str>, lon: f64| Stop {
+        stop_id: stop_id.to_owned(),
+        stop_code: None,
+        stop_name: None,
+        tts_stop_name: None,
+        stop_desc: None,
+        stop_lat: Some(48.0),
+        stop_lon: Some(lon),
+        zone_id: None,
+        stop_url: None,
+        location_type: Some(location_type),
+        parent_station: parent.map(str::to_owned),
+        stop_timezone: None,
+        wheelchair_boarding: None,
+        level_id: None,
+        platform_code: None,
+    };
+    let pathway = |from: &str, to: &str, traversal_time: Option<u64>| Pathway {
+        pathway_id: format!("{}-{}", from, to),
+        from_stop_id: from.to_owned(),
+        to_stop_id: to.to_owned(),
+        pathway_mode: gtfs::PathwayMode::Walkway,
+        is_bidirectional: true,
+        length: None,
+        traversal_time,
+        stair_count: None,
+        max_slope: None,
+        min_width: None,
+        signposted_as: None,
+        reversed_signposted_as: None,
+    };
+    // Two platforms side by side, but only linked through a node in the concourse
+    let stops = vec![
+        stop("station", LocationType::Station, None, 8.0),
+        stop("platform_1", LocationType::Stop, Some("station"), 8.0),
+        stop("platform_2", LocationType::Stop, Some("station"), 8.0001),
+        stop("concourse", LocationType::GenericNode, Some("station"), 8.0),
+        stop("bus", LocationType::Stop, None, 8.001),
+    ];
+    let pathways = vec![
+        pathway("platform_1", "concourse", Some(60)),
+        pathway("concourse", "platform_2", Some(90)),
+    ];
+
+    let rules = generate_transfers(&stops, &pathways, &[], WalkSettings::default());
+    let time = |from: &str, to: &str| {
+        rules
+            .transfers
+            .iter()
+            .find(|transfer| {
+                transfer.from_stop_id.as_deref() == Some(from)
+                    && transfer.to_stop_id.as_deref() == Some(to)
+            })
+            .and_then(|transfer| transfer.min_transfer_time)
+    };
+    assert_eq!(time("platform_1", "platform_2"), Some(150));
+    assert_eq!(time("platform_2", "platform_1"), Some(150));
+    assert!(time("platform_1", "bus").is_some());
+    assert_eq!(time("platform_1", "concourse"), None);
+    assert_eq!(rules.generated, 6);
+}
@@ -0,0 +1,68 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    // The data contradicts the spec or the feed it refers to
+    Error,
+    // The data is valid but likely wrong
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+// One breach of a validation rule, by the rule's code and the id of the record it was found in
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Finding {
+    pub code: &'static str,
+    pub severity: Severity,
+    pub entity_id: String,
+    pub message: String,
+}
+
+impl Finding {
+    pub fn new(
+        code: &'static str,
+        severity: Severity,
+        entity_id: &str,
+        message: String,
+    ) -> Finding {
+        Finding {
+            code,
+            severity,
+            entity_id: entity_id.to_owned(),
+            message,
+        }
+    }
+}
+
+// Every finding on its own line, then the number of findings per rule with errors first
+pub fn print_findings(findings: &[Finding]) {
+    for finding in findings {
+        println!(
+            "{} {} {}: {}",
+            finding.severity, finding.code, finding.entity_id, finding.message
+        );
+    }
+    let mut counts: BTreeMap<(Severity, &str), usize> = BTreeMap::new();
+    for finding in findings {
+        *counts.entry((finding.severity, finding.code)).or_default() += 1;
+    }
+    if !counts.is_empty() {
+        println!();
+    }
+    for ((severity, code), count) in counts {
+        println!("{:>6} {} {}", count, severity, code);
+    }
+    println!("{} findings", findings.len());
+}
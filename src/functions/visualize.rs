@@ -1,91 +1,147 @@
-use std::collections::HashMap;
+pub mod gantt;
+pub mod line_diagram;
+pub mod marey;
+pub mod schematic;
+pub mod svg;
 
+use std::collections::{BTreeMap, HashMap};
+#[cfg(test)]
+use std::path::PathBuf;
+
+use clap::ValueEnum;
 use petgraph;
 
-use crate::objects::{self, route::stopid_stops_hash};
+use crate::objects::{self, network, route::stopid_stops_hash};
 
-use super::gtfs::{self, StopTime};
+use super::gtfs::{self, Route, Stop};
 
-pub fn route_to_dot(route_id: String, gtfs_file: &mut gtfs::GtfsFile) -> String {
-    
-    let route = objects::route::extract_route_info(gtfs_file, &route_id).unwrap();
-    println!("Extracting Graph for line {:?}", route.route_description.route_short_name);
-    // let parents = stopid_stops_hash(&route.parent_stops(gtfs_file));
-    let stops = stopid_stops_hash(&route.associated_stops);
-    
-    let mut trips_map: HashMap<String, Vec<StopTime>> = HashMap::new();
-    for stop_time in &route.associated_stop_times {
-        let stop_pos = stop_time.stop_sequence.unwrap() as usize;
-        match trips_map.get_mut(&stop_time.trip_id) {
-            Some(stop_time_vec) => {
-                stop_time_vec.insert(stop_pos, stop_time.to_owned());
-            },
-            None => {
-                let mut stop_time_vec: Vec<StopTime> = Vec::new();
-                let trip_id = stop_time.trip_id.to_owned();
-                stop_time_vec.insert(stop_pos, stop_time.to_owned());
-                trips_map.insert(trip_id, stop_time_vec);
-            }
-        }
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum RouteDiagramFormat {
+    Svg,
+    Dot,
+}
+
+// The stations of a route and the links between them, with stops collapsed to their parent
+// station. Stations are keyed by id, so that stations sharing a name stay apart.
+pub struct RouteGraph {
+    pub route: Route,
+    pub station_names: BTreeMap<String, String>,
+    // Undirected links, keyed by the ordered pair of station ids, with the number of trips
+    pub links: BTreeMap<(String, String), u64>,
+    // Station sequences of the route's stop patterns, the longest first then by trip count
+    pub sequences: Vec<Vec<String>>,
+}
+
+pub fn route_graph(gtfs_file: &mut gtfs::GtfsFile, route_id: &str) -> Option<RouteGraph> {
+    let route = objects::route::extract_route_info(gtfs_file, &route_id.to_owned())?;
+    let stops = stopid_stops_hash(&route.all_stops(gtfs_file));
+    let stops_by_id: HashMap<&str, &Stop> = stops
+        .iter()
+        .map(|(stop_id, stop)| (stop_id.as_str(), stop))
+        .collect();
+
+    let mut patterns = route.stop_patterns();
+    patterns.sort_by_key(|pattern| std::cmp::Reverse(pattern.trip_count));
+    if let Some(longest) = patterns
+        .iter()
+        .enumerate()
+        .max_by_key(|(index, pattern)| (pattern.stop_ids.len(), std::cmp::Reverse(*index)))
+        .map(|(index, _)| index)
+    {
+        patterns[..=longest].rotate_right(1);
     }
 
-    let mut edges: HashMap<(String, String), u64> = HashMap::new();
-    for stop_time_vec in trips_map.into_values() {
-        let mut previous_stop_id: Option<String> = None;
-        for stop_time in stop_time_vec {
-            let stop = stops.get(&stop_time.clone().stop_id.unwrap()).unwrap();
-            match previous_stop_id {
-                None => {
-                    if let Some(parent) = &stop.parent_station {
-                        previous_stop_id = Some(parent.to_owned());
-                    } else {
-                        previous_stop_id = stop_time.stop_id
-                    }
-                },
-                Some(ref prev_stop_id) => {
-                    let stop_id: String;
-                    if let Some(parent) = &stop.parent_station {
-                        stop_id = parent.to_owned();
-                    } else {
-                        stop_id = stop_time.clone().stop_id.unwrap();
-                    }
-                    let edge = (prev_stop_id.to_owned(), stop_id.clone());
-                    match edges.get(&edge) {
-                        None => {edges.insert(edge, 1);},
-                        Some(count) => {edges.insert(edge, count + 1);}
-                    }
-                    previous_stop_id = Some(stop_id);                                  
-                }
-            }
-        }
-    }                                         
-    
-    let mut graph: petgraph::prelude::GraphMap<&str, &u64, petgraph::Undirected> = petgraph::graphmap::UnGraphMap::new();
-    
-    let parent_stations = route.all_stops(gtfs_file);
-    let parents = stopid_stops_hash(&parent_stations);
-    let mut fancy_edges: HashMap<(String, String), u64> = HashMap::new();
-    for (edge, count) in edges.iter() {
-        let name_a;
-        let name_b;
-        if let Some(name) = &parents.get(&edge.0).unwrap().stop_name {
-            name_a=name.to_owned();
-        } else {
-            name_a= edge.0.clone();
+    let mut station_names: BTreeMap<String, String> = BTreeMap::new();
+    let mut links: BTreeMap<(String, String), u64> = BTreeMap::new();
+    let mut sequences: Vec<Vec<String>> = Vec::new();
+    for pattern in &patterns {
+        let mut sequence: Vec<String> = pattern
+            .stop_ids
+            .iter()
+            .map(|stop_id| network::station_id(&stops_by_id, stop_id))
+            .collect();
+        sequence.dedup();
+        for pair in sequence.windows(2) {
+            let link = if pair[0] < pair[1] {
+                (pair[0].to_owned(), pair[1].to_owned())
+            } else {
+                (pair[1].to_owned(), pair[0].to_owned())
+            };
+            *links.entry(link).or_default() += pattern.trip_count as u64;
         }
-        if let Some(name) = &parents.get(&edge.1).unwrap().stop_name {
-            name_b=name.to_owned();
-        } else {
-            name_b= edge.1.clone();
+        for station_id in &sequence {
+            let name = stops
+                .get(station_id)
+                .and_then(|stop| stop.stop_name.clone())
+                .unwrap_or(station_id.to_owned());
+            station_names.insert(station_id.to_owned(), name);
         }
-        fancy_edges.insert((name_a,name_b), *count);
+        sequences.push(sequence);
     }
 
+    Some(RouteGraph {
+        route: route.route_description,
+        station_names,
+        links,
+        sequences,
+    })
+}
 
-    for (edge, count) in fancy_edges.iter() {
-        graph.add_edge(edge.0.as_ref(), edge.1.as_ref(), count);
+// Graphviz dot of a route graph, with station ids as nodes, labelled by name
+pub fn route_to_dot(route_graph: &RouteGraph) -> String {
+    let mut graph: petgraph::prelude::GraphMap<&str, u64, petgraph::Undirected> =
+        petgraph::graphmap::UnGraphMap::new();
+    for station_id in route_graph.station_names.keys() {
+        graph.add_node(station_id);
     }
+    for ((from, to), count) in &route_graph.links {
+        graph.add_edge(from, to, *count);
+    }
+
+    let max_count = route_graph.links.values().copied().max().unwrap_or(1) as f64;
+    let link_attributes = |_, (_, _, count): (&str, &str, &u64)| {
+        format!(
+            "label = \"{}\" penwidth = {:.1}",
+            count,
+            1.0 + 4.0 * *count as f64 / max_count
+        )
+    };
+    let station_attributes = |_, (station_id, _): (&str, &&str)| {
+        let name = route_graph
+            .station_names
+            .get(station_id)
+            .map_or(station_id, |name| name);
+        format!(
+            "label = \"{}\" id = \"{}\"",
+            name.replace('"', "\\\""),
+            station_id.replace('"', "\\\"")
+        )
+    };
+    let dot = petgraph::dot::Dot::with_attr_getters(
+        &graph,
+        &[
+            petgraph::dot::Config::EdgeNoLabel,
+            petgraph::dot::Config::NodeNoLabel,
+        ],
+        &link_attributes,
+        &station_attributes,
+    );
+    format!("{}", dot)
+}
 
-    format!("{:?}", petgraph::dot::Dot::new(&graph))
+#[test]
+fn test_route_to_dot() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let route_graph = route_graph(&mut gtfs_file, "AB").unwrap();
 
+    assert_eq!(
+        route_graph
+            .links
+            .get(&(String::from("BEATTY_AIRPORT"), String::from("BULLFROG"))),
+        Some(&2)
+    );
+    let dot = route_to_dot(&route_graph);
+    assert!(dot.contains("label = \"Bullfrog (Demo)\" id = \"BULLFROG\""));
+    assert!(dot.contains("label = \"2\""));
 }
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+#[cfg(test)]
+use std::path::PathBuf;
+
+#[cfg(test)]
+use chrono::NaiveDate;
+
+#[cfg(test)]
+use crate::functions::gtfs;
+use crate::functions::gtfs::{Route, Time};
+#[cfg(test)]
+use crate::objects::block;
+use crate::objects::block::Block;
+use crate::objects::feed::Feed;
+use crate::objects::pattern;
+
+use super::svg::{self, SvgDocument};
+
+const LABEL_WIDTH: f64 = 120.0;
+const HOUR_WIDTH: f64 = 80.0;
+const ROW_HEIGHT: f64 = 24.0;
+const AXIS_HEIGHT: f64 = 30.0;
+
+// Gantt style chart with one row per block, trips as bars and layovers as lines between them
+pub fn blocks_to_svg(blocks: &[Block], feed: &Feed) -> String {
+    let routes: HashMap<&str, (usize, &Route)> = feed
+        .routes
+        .iter()
+        .enumerate()
+        .map(|(index, route)| (route.route_id.as_str(), (index, route)))
+        .collect();
+
+    let trips = blocks.iter().flat_map(|block| block.trips.iter());
+    let first_hour = trips
+        .clone()
+        .map(|trip| trip.start / 3600)
+        .min()
+        .unwrap_or(0);
+    let last_hour = trips
+        .map(|trip| trip.end.div_ceil(3600))
+        .max()
+        .unwrap_or(first_hour + 1);
+    let x = |seconds: u64| LABEL_WIDTH + (seconds as f64 / 3600.0 - first_hour as f64) * HOUR_WIDTH;
+
+    let width = LABEL_WIDTH + (last_hour - first_hour) as f64 * HOUR_WIDTH + 20.0;
+    let height = AXIS_HEIGHT + blocks.len() as f64 * ROW_HEIGHT + 10.0;
+    let mut document = SvgDocument::new(width, height);
+
+    for hour in first_hour..=last_hour {
+        let position = x(hour * 3600);
+        document.line(
+            (position, AXIS_HEIGHT - 5.0),
+            (position, height),
+            "stroke:#ddd",
+        );
+        document.text(
+            (position, AXIS_HEIGHT - 10.0),
+            &svg::hour_label(hour * 3600),
+            "font-size:11px;text-anchor:middle",
+        );
+    }
+
+    for (row, block) in blocks.iter().enumerate() {
+        let top = AXIS_HEIGHT + row as f64 * ROW_HEIGHT;
+        let middle = top + ROW_HEIGHT / 2.0;
+        document.text(
+            (LABEL_WIDTH - 8.0, middle + 4.0),
+            &block.block_id,
+            "font-size:12px;text-anchor:end",
+        );
+
+        for (index, trip) in block.trips.iter().enumerate() {
+            if let Some(previous) = index.checked_sub(1).map(|previous| &block.trips[previous]) {
+                if trip.start > previous.end {
+                    // Dashed when the vehicle has to run empty to another stop
+                    let style = if previous.last_stop_id == trip.first_stop_id {
+                        "stroke:#999;stroke-width:2"
+                    } else {
+                        "stroke:#999;stroke-width:2;stroke-dasharray:4 3"
+                    };
+                    document.line((x(previous.end), middle), (x(trip.start), middle), style);
+                }
+            }
+
+            let (colour, route_name) = match routes.get(trip.route_id.as_str()) {
+                Some((route_index, route)) => (
+                    svg::route_colour(Some(route), *route_index),
+                    pattern::route_name(route).to_owned(),
+                ),
+                None => (svg::route_colour(None, 0), trip.route_id.to_owned()),
+            };
+            let overlaps = index > 0 && trip.start < block.trips[index - 1].end;
+            let style = if overlaps {
+                format!("fill:{};stroke:#e00;stroke-width:2", colour)
+            } else {
+                format!("fill:{}", colour)
+            };
+            let title = format!(
+                "{} ({}) {} - {}",
+                trip.trip_id,
+                route_name,
+                Time::from_seconds(trip.start),
+                Time::from_seconds(trip.end)
+            );
+            document.rect(
+                x(trip.start),
+                top + 4.0,
+                (x(trip.end) - x(trip.start)).max(1.0),
+                ROW_HEIGHT - 8.0,
+                &style,
+                Some(&title),
+            );
+        }
+    }
+
+    document.render()
+}
+
+#[test]
+fn test_blocks_to_svg() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let feed = Feed::load(&mut gtfs_file);
+    let date = NaiveDate::from_ymd_opt(2007, 6, 5).unwrap();
+    let (blocks, _) = block::blocks_on_date(&feed, date);
+
+    let output = blocks_to_svg(&blocks, &feed);
+    assert!(output.starts_with("<svg"));
+    assert_eq!(output.matches("<rect").count(), 4);
+    assert!(output.contains("<title>AB1 (10) 08:00:00 - 08:10:00</title>"));
+}
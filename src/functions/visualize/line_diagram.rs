@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+#[cfg(test)]
+use std::path::PathBuf;
+
+#[cfg(test)]
+use crate::functions::gtfs;
+use crate::objects::pattern;
+
+#[cfg(test)]
+use super::route_graph;
+use super::svg::{self, SvgDocument};
+use super::RouteGraph;
+
+const MARGIN: f64 = 60.0;
+const HEADER_HEIGHT: f64 = 40.0;
+const COLUMN_WIDTH: f64 = 100.0;
+const ROW_HEIGHT: f64 = 70.0;
+const MAX_LINK_WIDTH: f64 = 10.0;
+
+// Line diagram of a route, stations are laid out along the longest pattern and other patterns
+// branch off where they leave it. Links get thicker with the number of trips using them.
+pub fn route_to_svg(route_graph: &RouteGraph) -> String {
+    let positions = layout(&route_graph.sequences);
+    let min_column = positions
+        .values()
+        .map(|(column, _)| *column)
+        .fold(f64::INFINITY, f64::min)
+        .min(0.0);
+    let max_column = positions
+        .values()
+        .map(|(column, _)| *column)
+        .fold(0.0, f64::max);
+    let min_row = positions.values().map(|(_, row)| *row).min().unwrap_or(0);
+    let max_row = positions.values().map(|(_, row)| *row).max().unwrap_or(0);
+    let point = |station_id: &str| {
+        positions.get(station_id).map(|(column, row)| {
+            (
+                MARGIN + (column - min_column) * COLUMN_WIDTH,
+                HEADER_HEIGHT + MARGIN / 2.0 + (row - min_row) as f64 * ROW_HEIGHT,
+            )
+        })
+    };
+
+    let width = 2.0 * MARGIN + (max_column - min_column) * COLUMN_WIDTH;
+    let height = HEADER_HEIGHT + MARGIN + (max_row - min_row) as f64 * ROW_HEIGHT;
+    let mut document = SvgDocument::new(width, height);
+    document.text(
+        (MARGIN / 2.0, 24.0),
+        pattern::route_name(&route_graph.route),
+        "font-size:16px;font-weight:bold",
+    );
+
+    let colour = svg::route_colour(Some(&route_graph.route), 0);
+    let max_count = route_graph.links.values().copied().max().unwrap_or(1) as f64;
+    for ((from, to), count) in &route_graph.links {
+        let (Some(from), Some(to)) = (point(from), point(to)) else {
+            continue;
+        };
+        let link_width = 2.0 + (MAX_LINK_WIDTH - 2.0) * *count as f64 / max_count;
+        document.line(
+            from,
+            to,
+            &format!(
+                "stroke:{};stroke-width:{:.1};stroke-linecap:round",
+                colour, link_width
+            ),
+        );
+    }
+
+    for (station_id, name) in &route_graph.station_names {
+        let Some(center) = point(station_id) else {
+            continue;
+        };
+        document.circle(
+            center,
+            5.0,
+            "fill:#fff;stroke:#333;stroke-width:2",
+            &format!("station-{}", station_id),
+            Some(name),
+        );
+        document.text(
+            (center.0, center.1 + 20.0),
+            name,
+            "font-size:10px;text-anchor:middle",
+        );
+    }
+
+    document.render()
+}
+
+// Column and row of every station. The first sequence is the trunk on row 0, runs of stations
+// of later sequences that aren't placed yet become branches on rows alternating below and above.
+fn layout(sequences: &[Vec<String>]) -> HashMap<String, (f64, i32)> {
+    let mut positions: HashMap<String, (f64, i32)> = HashMap::new();
+    let mut branch_rows = (1..).flat_map(|row| [row, -row]);
+    for (index, sequence) in sequences.iter().enumerate() {
+        let mut start = 0;
+        while start < sequence.len() {
+            if positions.contains_key(&sequence[start]) {
+                start += 1;
+                continue;
+            }
+            let mut end = start;
+            while end < sequence.len() && !positions.contains_key(&sequence[end]) {
+                end += 1;
+            }
+
+            let column_of = |position: usize| {
+                sequence
+                    .get(position)
+                    .and_then(|id| positions.get(id))
+                    .map(|(column, _)| *column)
+            };
+            let length = end - start;
+            let before = start.checked_sub(1).and_then(column_of);
+            let after = column_of(end);
+            let columns: Vec<f64> = match (before, after) {
+                (Some(before), Some(after)) if before != after => (1..=length)
+                    .map(|step| before + (after - before) * step as f64 / (length + 1) as f64)
+                    .collect(),
+                (Some(before), _) => {
+                    // Keep going in the direction the sequence ran along the placed stations
+                    let direction = match start.checked_sub(2).and_then(column_of) {
+                        Some(earlier) if earlier > before => -1.0,
+                        _ => 1.0,
+                    };
+                    (1..=length)
+                        .map(|step| before + direction * step as f64)
+                        .collect()
+                }
+                (None, Some(after)) => {
+                    let direction = match column_of(end + 1) {
+                        Some(later) if later < after => -1.0,
+                        _ => 1.0,
+                    };
+                    (0..length)
+                        .map(|step| after - direction * (length - step) as f64)
+                        .collect()
+                }
+                (None, None) => (0..length).map(|step| step as f64).collect(),
+            };
+
+            let row = if index == 0 {
+                0
+            } else {
+                branch_rows.next().unwrap_or(0)
+            };
+            for (station_id, column) in sequence[start..end].iter().zip(columns) {
+                positions
+                    .entry(station_id.to_owned())
+                    .or_insert((column, row));
+            }
+            start = end;
+        }
+    }
+    positions
+}
+
+#[test]
+fn test_layout() {
+    let sequence = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<String>>();
+    let sequences = vec![
+        sequence(&["A", "B", "C", "D"]),
+        sequence(&["D", "C", "B", "A"]),
+        sequence(&["A", "B", "E", "F"]),
+        sequence(&["B", "G", "D"]),
+    ];
+    let positions = layout(&sequences);
+
+    assert_eq!(positions["A"], (0.0, 0));
+    assert_eq!(positions["D"], (3.0, 0));
+    // The branch to F leaves the trunk after B
+    assert_eq!(positions["E"], (2.0, 1));
+    assert_eq!(positions["F"], (3.0, 1));
+    // The bypass of C sits between B and D
+    assert_eq!(positions["G"], (2.0, -1));
+}
+
+#[test]
+fn test_route_to_svg() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let route_graph = route_graph(&mut gtfs_file, "CITY").unwrap();
+
+    let output = route_to_svg(&route_graph);
+    assert_eq!(output.matches("<circle").count(), 5);
+    assert_eq!(output.matches("<line").count(), 4);
+    assert!(output.contains(r#"id="station-STAGECOACH""#));
+}
@@ -0,0 +1,345 @@
+use std::collections::{HashMap, HashSet};
+#[cfg(test)]
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+
+use crate::functions::geodata;
+#[cfg(test)]
+use crate::functions::gtfs;
+use crate::functions::gtfs::{Direction, Time};
+use crate::objects::feed::Feed;
+use crate::objects::pattern::StopPattern;
+
+use super::svg::{self, SvgDocument};
+
+const LABEL_WIDTH: f64 = 160.0;
+const HOUR_WIDTH: f64 = 120.0;
+const HEADER_HEIGHT: f64 = 50.0;
+const MIN_PLOT_HEIGHT: f64 = 400.0;
+const STOP_SPACING: f64 = 24.0;
+
+// Time-distance diagram of all trips of a route or pattern on a service date. Stops are placed
+// on the y axis by their distance along the longest pattern, with the stops of branches the other
+// patterns run to put onto the axis as well. Trips are lines coloured by direction.
+pub fn marey_diagram(feed: &Feed, id: &str, date: NaiveDate) -> Option<String> {
+    let patterns = feed.stop_patterns();
+    let selected: Vec<&StopPattern> = if feed.routes.iter().any(|route| route.route_id == id) {
+        patterns
+            .iter()
+            .filter(|pattern| pattern.route_id == id)
+            .collect()
+    } else {
+        patterns
+            .iter()
+            .filter(|pattern| pattern.pattern_id == id)
+            .collect()
+    };
+    let axis = selected.iter().max_by_key(|pattern| {
+        (
+            pattern.stop_ids.len(),
+            pattern.trip_count,
+            pattern.direction_id != Some(Direction::Inbound),
+        )
+    })?;
+
+    let rows = axis_stops(feed, axis, &selected);
+    let total = rows
+        .last()
+        .map(|(_, position)| *position)
+        .filter(|total| *total > 0.0)
+        .unwrap_or(1.0);
+    let mut stop_rows: HashMap<&str, usize> = HashMap::new();
+    for (index, (stop_id, _)) in rows.iter().enumerate() {
+        stop_rows.entry(stop_id.as_str()).or_insert(index);
+    }
+
+    let trip_ids: HashSet<&str> = selected
+        .iter()
+        .flat_map(|pattern| pattern.trip_ids.iter().map(|trip_id| trip_id.as_str()))
+        .collect();
+    let instances: Vec<_> = feed
+        .trip_instances(&feed.active_services(date))
+        .into_iter()
+        .filter(|instance| trip_ids.contains(instance.trip.trip_id.as_str()))
+        .collect();
+
+    let first_hour = instances
+        .iter()
+        .map(|instance| instance.start_time() / 3600)
+        .min()
+        .unwrap_or(0);
+    let last_hour = instances
+        .iter()
+        .map(|instance| instance.end_time().div_ceil(3600))
+        .max()
+        .unwrap_or(first_hour + 1)
+        .max(first_hour + 1);
+    let plot_height = MIN_PLOT_HEIGHT.max(rows.len() as f64 * STOP_SPACING);
+    let x = |seconds: u64| LABEL_WIDTH + (seconds as f64 / 3600.0 - first_hour as f64) * HOUR_WIDTH;
+    let y = |row: usize| HEADER_HEIGHT + rows[row].1 / total * plot_height;
+
+    let width = LABEL_WIDTH + (last_hour - first_hour) as f64 * HOUR_WIDTH + 20.0;
+    let height = HEADER_HEIGHT + plot_height + 20.0;
+    let mut document = SvgDocument::new(width, height);
+
+    let title = format!("{} on {}", axis.label, date.format("%Y-%m-%d"));
+    document.text(
+        (LABEL_WIDTH, 18.0),
+        &title,
+        "font-size:14px;font-weight:bold",
+    );
+    for hour in first_hour..=last_hour {
+        let position = x(hour * 3600);
+        document.line(
+            (position, HEADER_HEIGHT - 5.0),
+            (position, HEADER_HEIGHT + plot_height),
+            "stroke:#ddd",
+        );
+        document.text(
+            (position, HEADER_HEIGHT - 10.0),
+            &svg::hour_label(hour * 3600),
+            "font-size:11px;text-anchor:middle",
+        );
+    }
+    let stop_names: HashMap<&str, &str> = feed
+        .stops
+        .iter()
+        .filter_map(|stop| Some((stop.stop_id.as_str(), stop.stop_name.as_deref()?)))
+        .collect();
+    for (row, (stop_id, _)) in rows.iter().enumerate() {
+        let name = stop_names.get(stop_id.as_str()).copied().unwrap_or(stop_id);
+        document.line((LABEL_WIDTH, y(row)), (width - 20.0, y(row)), "stroke:#eee");
+        document.text(
+            (LABEL_WIDTH - 8.0, y(row) + 4.0),
+            name,
+            "font-size:11px;text-anchor:end",
+        );
+    }
+
+    for instance in &instances {
+        let mut points: Vec<(f64, f64)> = Vec::new();
+        for stop in &instance.stops {
+            let Some(row) = stop_rows.get(stop.stop_id.as_str()) else {
+                continue;
+            };
+            points.push((x(stop.arrival), y(*row)));
+            if stop.departure > stop.arrival {
+                points.push((x(stop.departure), y(*row)));
+            }
+        }
+        if points.len() < 2 {
+            continue;
+        }
+
+        let colour = match instance.trip.direction_id {
+            Some(Direction::Outbound) => "#1f77b4",
+            Some(Direction::Inbound) => "#d62728",
+            None => "#555555",
+        };
+        let title = format!(
+            "{} {} - {}",
+            instance.trip.trip_id,
+            Time::from_seconds(instance.start_time()),
+            Time::from_seconds(instance.end_time())
+        );
+        document.polyline(
+            &points,
+            &format!("stroke:{};stroke-width:1.5", colour),
+            Some(&title),
+        );
+    }
+
+    Some(document.render())
+}
+
+// Stops of the y axis with their distance from the first one, in order of distance. Stops of the
+// other patterns that the axis pattern doesn't call at are placed at their distance along their own
+// pattern from the last stop they share with the axis before them, or the first one after them.
+// Patterns without any stop on the axis are placed after its end.
+fn axis_stops(feed: &Feed, axis: &StopPattern, selected: &[&StopPattern]) -> Vec<(String, f64)> {
+    let mut stops: Vec<(String, f64)> = axis
+        .stop_ids
+        .iter()
+        .cloned()
+        .zip(stop_positions(feed, axis))
+        .collect();
+    let mut known: HashMap<String, f64> = HashMap::new();
+    for (stop_id, position) in &stops {
+        known.entry(stop_id.to_owned()).or_insert(*position);
+    }
+
+    for pattern in selected {
+        if pattern.pattern_id == axis.pattern_id {
+            continue;
+        }
+        let shared: Vec<usize> = (0..pattern.stop_ids.len())
+            .filter(|index| known.contains_key(&pattern.stop_ids[*index]))
+            .collect();
+        if shared.len() == pattern.stop_ids.len() {
+            continue;
+        }
+        let own = stop_positions(feed, pattern);
+        let end = known.values().copied().fold(0.0, f64::max);
+        // Patterns running against the axis, such as the other direction, count backwards
+        let sign = match (shared.first(), shared.last()) {
+            (Some(first), Some(last))
+                if known[&pattern.stop_ids[*last]] < known[&pattern.stop_ids[*first]] =>
+            {
+                -1.0
+            }
+            _ => 1.0,
+        };
+        for (index, stop_id) in pattern.stop_ids.iter().enumerate() {
+            if known.contains_key(stop_id) {
+                continue;
+            }
+            let anchor = shared
+                .iter()
+                .rev()
+                .find(|shared| **shared < index)
+                .or(shared.first());
+            let position = match anchor {
+                Some(anchor) => {
+                    known[&pattern.stop_ids[*anchor]] + sign * (own[index] - own[*anchor])
+                }
+                None => end + own[index],
+            };
+            known.insert(stop_id.to_owned(), position);
+            stops.push((stop_id.to_owned(), position));
+        }
+    }
+
+    let start = stops
+        .iter()
+        .map(|(_, position)| *position)
+        .fold(0.0, f64::min);
+    for (_, position) in stops.iter_mut() {
+        *position -= start;
+    }
+    stops.sort_by(|a, b| a.1.total_cmp(&b.1));
+    stops
+}
+
+// Distance of every stop of the pattern from its first stop. Uses shape_dist_traveled where the
+// feed has it, else the stops projected onto the pattern's shape, else straight lines between stops.
+fn stop_positions(feed: &Feed, pattern: &StopPattern) -> Vec<f64> {
+    if let Some(trip_id) = pattern.trip_ids.first() {
+        let mut stop_times: Vec<_> = feed
+            .stop_times
+            .iter()
+            .filter(|stop_time| &stop_time.trip_id == trip_id)
+            .collect();
+        stop_times.sort_by_key(|stop_time| stop_time.stop_sequence);
+        let distances: Option<Vec<f64>> = stop_times
+            .iter()
+            .map(|stop_time| stop_time.shape_dist_travelled)
+            .collect();
+        if let Some(distances) = distances {
+            if distances.len() == pattern.stop_ids.len()
+                && distances.windows(2).all(|pair| pair[0] <= pair[1])
+            {
+                let start = distances.first().copied().unwrap_or(0.0);
+                return distances.iter().map(|distance| distance - start).collect();
+            }
+        }
+    }
+
+    let stop_points: HashMap<&str, geo_types::Point> = feed
+        .stops
+        .iter()
+        .filter_map(|stop| Some((stop.stop_id.as_str(), geodata::stop_point(stop)?)))
+        .collect();
+    let points: Vec<Option<geo_types::Point>> = pattern
+        .stop_ids
+        .iter()
+        .map(|stop_id| stop_points.get(stop_id.as_str()).copied())
+        .collect();
+
+    let shape = pattern
+        .shape_id
+        .as_ref()
+        .and_then(|shape_id| geodata::shape_lines(&feed.shapes).remove(shape_id))
+        .filter(|line| line.len() > 1);
+    if let Some(line) = shape {
+        let mut segment = 0;
+        let mut positions: Vec<f64> = Vec::new();
+        for point in &points {
+            let previous = positions.last().copied().unwrap_or(0.0);
+            match point.and_then(|point| geodata::project_onto_line(&line, point, segment)) {
+                Some((index, along, _)) => {
+                    segment = index;
+                    positions.push(along.max(previous));
+                }
+                None => positions.push(previous),
+            }
+        }
+        let start = positions.first().copied().unwrap_or(0.0);
+        return positions.iter().map(|position| position - start).collect();
+    }
+
+    let mut positions: Vec<f64> = vec![0.0];
+    for pair in points.windows(2) {
+        let step = match (pair[0], pair[1]) {
+            (Some(from), Some(to)) => geodata::haversine_distance(from, to),
+            _ => 0.0,
+        };
+        positions.push(positions.last().copied().unwrap_or(0.0) + step);
+    }
+    positions.truncate(points.len());
+    positions
+}
+
+#[test]
+fn test_marey_diagram() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let feed = Feed::load(&mut gtfs_file);
+    let date = NaiveDate::from_ymd_opt(2007, 6, 5).unwrap();
+
+    // Airport - Bullfrog runs once in each direction
+    let output = marey_diagram(&feed, "AB", date).unwrap();
+    assert_eq!(output.matches("<polyline").count(), 2);
+    assert!(output.contains("stroke:#1f77b4"));
+    assert!(output.contains("stroke:#d62728"));
+    assert!(output.contains(">Bullfrog (Demo)</text>"));
+
+    assert!(
+        marey_diagram(&feed, "AB:1", date)
+            .unwrap()
+            .matches("<polyline")
+            .count()
+            == 1
+    );
+    assert!(marey_diagram(&feed, "unknown", date).is_none());
+}
+
+#[test]
+fn test_marey_branch_stops() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let mut feed = Feed::load(&mut gtfs_file);
+    let date = NaiveDate::from_ymd_opt(2007, 6, 5).unwrap();
+
+    // The way back calls at the airport instead of North Ave / D Ave
+    for stop_time in feed.stop_times.iter_mut() {
+        if stop_time.trip_id == "CITY2" && stop_time.stop_id.as_deref() == Some("NADAV") {
+            stop_time.stop_id = Some("BEATTY_AIRPORT".to_owned());
+        }
+    }
+    let output = marey_diagram(&feed, "CITY", date).unwrap();
+    assert!(output.contains(">Nye County Airport (Demo)</text>"));
+    assert!(output.contains(">North Ave / D Ave N (Demo)</text>"));
+    let way_back = output
+        .split("<polyline")
+        .find(|polyline| polyline.contains("CITY2"))
+        .unwrap();
+    let points = way_back
+        .split("points=\"")
+        .nth(1)
+        .unwrap()
+        .split('"')
+        .next()
+        .unwrap();
+    assert_eq!(points.split(' ').count(), 10);
+}
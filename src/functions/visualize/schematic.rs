@@ -0,0 +1,666 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::f64::consts::{FRAC_PI_4, PI, TAU};
+#[cfg(test)]
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::functions::geodata;
+#[cfg(test)]
+use crate::functions::gtfs;
+use crate::functions::gtfs::Route;
+#[cfg(test)]
+use crate::objects::feed::Feed;
+use crate::objects::network::StationNetwork;
+#[cfg(test)]
+use crate::objects::network::{self, Link, Scope, Station};
+
+use super::svg::{self, SvgDocument};
+
+const ITERATIONS: usize = 400;
+// How strongly stations are pulled back to their geographic position, at first
+const GEOGRAPHY_WEIGHT: f64 = 0.02;
+// Closest two stations may get, in grid units
+const MIN_SEPARATION: f64 = 0.6;
+// Links within this many degrees of their direction count as octilinear
+const TOLERANCE_DEG: f64 = 2.0;
+// Times the step of a station breaking the topology is halved before it is undone
+const BACKTRACKS: usize = 6;
+const METRES_PER_DEGREE: f64 = 111_195.0;
+const UNIT_PX: f64 = 60.0;
+const MARGIN_PX: f64 = 80.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum SchematicFormat {
+    Svg,
+    GeoJson,
+}
+
+// Schematic positions of the stations of a network, in grid units where the median link is one
+// unit long, along with the projection to turn them back into coordinates
+pub struct Schematic {
+    pub positions: BTreeMap<String, (f64, f64)>,
+    // Octilinear direction of every link that got one, in multiples of 45° counter clockwise from
+    // east
+    pub directions: BTreeMap<(String, String), u8>,
+    origin: geo_types::Point,
+    metres_per_unit: f64,
+}
+
+impl Schematic {
+    // Number of links running within the tolerance of their octilinear direction
+    pub fn octilinear_links(&self) -> usize {
+        self.directions
+            .iter()
+            .filter(|((from, to), direction)| {
+                let (from, to) = (self.positions[from], self.positions[to]);
+                let angle = (to.1 - from.1).atan2(to.0 - from.0);
+                let deviation =
+                    (angle - **direction as f64 * FRAC_PI_4).rem_euclid(std::f64::consts::TAU);
+                deviation
+                    .min(std::f64::consts::TAU - deviation)
+                    .to_degrees()
+                    <= TOLERANCE_DEG
+            })
+            .count()
+    }
+
+    fn to_point(&self, (x, y): (f64, f64)) -> geo_types::Point {
+        let scale = self.origin.y().to_radians().cos();
+        geo_types::Point::new(
+            self.origin.x() + x * self.metres_per_unit / (METRES_PER_DEGREE * scale),
+            self.origin.y() + y * self.metres_per_unit / METRES_PER_DEGREE,
+        )
+    }
+}
+
+// Moves stations so that links run horizontally, vertically or diagonally. Every link first gets
+// the octilinear direction closest to its geographic bearing that is still free at both of its
+// stations without changing the order of links around them, then positions are relaxed towards
+// these directions while being held near geography. Moves that would change the order of links
+// around a station or make links cross where geography doesn't are undone.
+pub fn octilinear_layout(network: &StationNetwork) -> Schematic {
+    let located: BTreeMap<&str, geo_types::Point> = network
+        .stations
+        .iter()
+        .filter_map(|(station_id, station)| Some((station_id.as_str(), station.point?)))
+        .collect();
+    let count = located.len().max(1) as f64;
+    let origin = geo_types::Point::new(
+        located.values().map(|point| point.x()).sum::<f64>() / count,
+        located.values().map(|point| point.y()).sum::<f64>() / count,
+    );
+    let scale = origin.y().to_radians().cos();
+    let project = |point: &geo_types::Point| {
+        (
+            (point.x() - origin.x()) * METRES_PER_DEGREE * scale,
+            (point.y() - origin.y()) * METRES_PER_DEGREE,
+        )
+    };
+
+    let links: Vec<(&str, &str, usize)> = network
+        .links
+        .iter()
+        .filter(|((from, to), _)| {
+            located.contains_key(from.as_str()) && located.contains_key(to.as_str())
+        })
+        .map(|((from, to), link)| (from.as_str(), to.as_str(), link.trip_count))
+        .collect();
+    let mut lengths: Vec<f64> = links
+        .iter()
+        .map(|(from, to, _)| geodata::haversine_distance(located[from], located[to]))
+        .filter(|length| *length > 0.0)
+        .collect();
+    lengths.sort_by(f64::total_cmp);
+    let metres_per_unit = lengths.get(lengths.len() / 2).copied().unwrap_or(1.0);
+
+    let geography: HashMap<&str, (f64, f64)> = located
+        .iter()
+        .map(|(station_id, point)| {
+            let (x, y) = project(point);
+            (*station_id, (x / metres_per_unit, y / metres_per_unit))
+        })
+        .collect();
+
+    // Busiest links pick their direction first. A direction has to be free at both stations and
+    // keep the links around them in their geographic order, links left without one, like the
+    // ninth link of a station, aren't straightened
+    let mut order: Vec<usize> = (0..links.len()).collect();
+    order.sort_by_key(|index| std::cmp::Reverse(links[*index].2));
+    let mut slots: HashMap<&str, Vec<(f64, u8)>> = located
+        .keys()
+        .map(|station_id| (*station_id, Vec::new()))
+        .collect();
+    let mut directions: Vec<Option<u8>> = vec![None; links.len()];
+    for index in order {
+        let (from, to, _) = links[index];
+        let (a, b) = (geography[from], geography[to]);
+        let bearing = (b.1 - a.1).atan2(b.0 - a.0);
+        let mut candidates: Vec<u8> = (0..8).collect();
+        candidates.sort_by(|x, y| {
+            angle_between(bearing, *x as f64 * FRAC_PI_4)
+                .total_cmp(&angle_between(bearing, *y as f64 * FRAC_PI_4))
+        });
+        let direction = candidates.into_iter().find(|direction| {
+            keeps_order(&slots[from], bearing, *direction)
+                && keeps_order(&slots[to], bearing + PI, (direction + 4) % 8)
+        });
+        if let Some(direction) = direction {
+            slots
+                .entry(from)
+                .or_default()
+                .push((bearing.rem_euclid(TAU), direction));
+            slots
+                .entry(to)
+                .or_default()
+                .push(((bearing + PI).rem_euclid(TAU), (direction + 4) % 8));
+        }
+        directions[index] = direction;
+    }
+
+    let mut neighbours: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (from, to, _) in &links {
+        neighbours.entry(from).or_default().push(to);
+        neighbours.entry(to).or_default().push(from);
+    }
+    let around: HashMap<&str, Vec<&str>> = neighbours
+        .iter()
+        .map(|(station_id, linked)| (*station_id, cyclic_order(station_id, linked, &geography)))
+        .collect();
+    // Crossings geography already has stay, the relaxation must not add any
+    let crossed = crossings(&links, &geography);
+
+    let mut positions: HashMap<&str, (f64, f64)> = geography.clone();
+    for iteration in 0..ITERATIONS {
+        let previous = positions.clone();
+        // The pull towards geography fades out so that the last iterations straighten the links
+        let geography_weight = GEOGRAPHY_WEIGHT * (1.0 - iteration as f64 / ITERATIONS as f64);
+        let mut moves: HashMap<&str, (f64, f64, f64)> = HashMap::new();
+        for ((from, to, _), direction) in links.iter().zip(&directions) {
+            let Some(direction) = direction else {
+                continue;
+            };
+            let angle = *direction as f64 * FRAC_PI_4;
+            let unit = (angle.cos(), angle.sin());
+            let (a, b) = (positions[from], positions[to]);
+            let current = (b.0 - a.0, b.1 - a.1);
+            // Short links only grow to the separation of stations, stretching them further pushes
+            // branches across other links
+            let length = (current.0 * unit.0 + current.1 * unit.1).max(MIN_SEPARATION);
+            let correction = (
+                (unit.0 * length - current.0) / 2.0,
+                (unit.1 * length - current.1) / 2.0,
+            );
+            let entry = moves.entry(from).or_default();
+            *entry = (
+                entry.0 - correction.0,
+                entry.1 - correction.1,
+                entry.2 + 1.0,
+            );
+            let entry = moves.entry(to).or_default();
+            *entry = (
+                entry.0 + correction.0,
+                entry.1 + correction.1,
+                entry.2 + 1.0,
+            );
+        }
+        for (station_id, position) in positions.iter_mut() {
+            if let Some((dx, dy, count)) = moves.get(station_id) {
+                position.0 += dx / count;
+                position.1 += dy / count;
+            }
+            let home = geography[station_id];
+            position.0 += (home.0 - position.0) * geography_weight;
+            position.1 += (home.1 - position.1) * geography_weight;
+        }
+        separate(&mut positions);
+
+        // Stations whose moves turned the links around a station or made links cross take half
+        // their step back, and after a few tries go back to where they were, until the iteration
+        // keeps the topology of geography
+        for attempt in 1.. {
+            let mut reverted: BTreeSet<&str> = BTreeSet::new();
+            for (station_id, linked) in &neighbours {
+                if cyclic_order(station_id, linked, &positions) != around[station_id] {
+                    reverted.insert(station_id);
+                    reverted.extend(linked);
+                }
+            }
+            for (first, second) in crossings(&links, &positions).difference(&crossed) {
+                let (first, second) = (links[*first], links[*second]);
+                reverted.extend([first.0, first.1, second.0, second.1]);
+            }
+            reverted.retain(|station_id| positions[station_id] != previous[station_id]);
+            if reverted.is_empty() {
+                break;
+            }
+            for station_id in reverted {
+                let (from, to) = (previous[station_id], positions[station_id]);
+                let position = if attempt < BACKTRACKS {
+                    ((from.0 + to.0) / 2.0, (from.1 + to.1) / 2.0)
+                } else {
+                    from
+                };
+                positions.insert(station_id, position);
+            }
+        }
+    }
+
+    Schematic {
+        positions: positions
+            .into_iter()
+            .map(|(station_id, position)| (station_id.to_owned(), position))
+            .collect(),
+        directions: links
+            .iter()
+            .zip(directions)
+            .filter_map(|((from, to, _), direction)| {
+                Some(((from.to_string(), to.to_string()), direction?))
+            })
+            .collect(),
+        origin,
+        metres_per_unit,
+    }
+}
+
+fn angle_between(a: f64, b: f64) -> f64 {
+    let difference = (a - b).rem_euclid(std::f64::consts::TAU);
+    difference.min(std::f64::consts::TAU - difference)
+}
+
+// Whether a link with this bearing can take this direction at a station, without sharing it with
+// another link or passing one on the way round
+fn keeps_order(slots: &[(f64, u8)], bearing: f64, direction: u8) -> bool {
+    if slots.is_empty() {
+        return true;
+    }
+    let mut around = slots.to_vec();
+    around.push((bearing.rem_euclid(TAU), direction));
+    around.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let turns: Vec<u8> = around
+        .iter()
+        .zip(around.iter().cycle().skip(1))
+        .map(|(a, b)| (b.1 + 8 - a.1) % 8)
+        .collect();
+    !turns.contains(&0) && turns.iter().map(|turns| *turns as usize).sum::<usize>() == 8
+}
+
+// Linked stations counter clockwise around a station, starting from the smallest id
+fn cyclic_order<'a>(
+    station_id: &str,
+    linked: &[&'a str],
+    positions: &HashMap<&str, (f64, f64)>,
+) -> Vec<&'a str> {
+    let (x, y) = positions[station_id];
+    let mut order = linked.to_vec();
+    order.sort_by(|a, b| {
+        let (a, b) = (positions[a], positions[b]);
+        (a.1 - y)
+            .atan2(a.0 - x)
+            .total_cmp(&(b.1 - y).atan2(b.0 - x))
+    });
+    if let Some(first) = order
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, station_id)| **station_id)
+        .map(|(index, _)| index)
+    {
+        order.rotate_left(first);
+    }
+    order
+}
+
+// Pairs of links, by index, crossing away from their stations. Links are only compared with links
+// sharing a grid cell
+fn crossings(
+    links: &[(&str, &str, usize)],
+    positions: &HashMap<&str, (f64, f64)>,
+) -> BTreeSet<(usize, usize)> {
+    let mut cells: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+    for (index, (from, to, _)) in links.iter().enumerate() {
+        let (a, b) = (positions[from], positions[to]);
+        for x in a.0.min(b.0).floor() as i64..=a.0.max(b.0).floor() as i64 {
+            for y in a.1.min(b.1).floor() as i64..=a.1.max(b.1).floor() as i64 {
+                cells.entry((x, y)).or_default().push(index);
+            }
+        }
+    }
+
+    let side = |a: (f64, f64), b: (f64, f64), c: (f64, f64)| {
+        ((b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)).signum()
+    };
+    let mut crossings = BTreeSet::new();
+    for indices in cells.values() {
+        for (position, first) in indices.iter().enumerate() {
+            for second in &indices[position + 1..] {
+                let ((a, b, _), (c, d, _)) = (links[*first], links[*second]);
+                if a == c || a == d || b == c || b == d {
+                    continue;
+                }
+                let (a, b, c, d) = (positions[a], positions[b], positions[c], positions[d]);
+                if side(a, b, c) * side(a, b, d) < 0.0 && side(c, d, a) * side(c, d, b) < 0.0 {
+                    crossings.insert((*first.min(second), *first.max(second)));
+                }
+            }
+        }
+    }
+    crossings
+}
+
+// Pushes apart stations closer than the minimum separation, looking only at neighbouring cells
+fn separate(positions: &mut HashMap<&str, (f64, f64)>) {
+    let mut cells: HashMap<(i64, i64), Vec<&str>> = HashMap::new();
+    for (station_id, (x, y)) in positions.iter() {
+        let cell = (
+            (x / MIN_SEPARATION).floor() as i64,
+            (y / MIN_SEPARATION).floor() as i64,
+        );
+        cells.entry(cell).or_default().push(station_id);
+    }
+
+    let mut pushes: Vec<(&str, (f64, f64))> = Vec::new();
+    for ((cell_x, cell_y), station_ids) in &cells {
+        for station_id in station_ids {
+            let (x, y) = positions[station_id];
+            for neighbour_x in cell_x - 1..=cell_x + 1 {
+                for neighbour_y in cell_y - 1..=cell_y + 1 {
+                    for other in cells.get(&(neighbour_x, neighbour_y)).into_iter().flatten() {
+                        if other == station_id {
+                            continue;
+                        }
+                        let (other_x, other_y) = positions[other];
+                        let distance = ((x - other_x).powi(2) + (y - other_y).powi(2)).sqrt();
+                        if distance < MIN_SEPARATION {
+                            // Stations on the same spot are separated along the id order
+                            let (dx, dy) = if distance > 1e-9 {
+                                ((x - other_x) / distance, (y - other_y) / distance)
+                            } else if station_id < other {
+                                (-1.0, 0.0)
+                            } else {
+                                (1.0, 0.0)
+                            };
+                            let push = (MIN_SEPARATION - distance) / 4.0;
+                            pushes.push((station_id, (dx * push, dy * push)));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    for (station_id, (dx, dy)) in pushes {
+        if let Some(position) = positions.get_mut(station_id) {
+            position.0 += dx;
+            position.1 += dy;
+        }
+    }
+}
+
+// Schematic map with one coloured line per route along every link
+pub fn schematic_to_svg(
+    network: &StationNetwork,
+    schematic: &Schematic,
+    routes: &[Route],
+) -> String {
+    let route_index: HashMap<&str, (usize, &Route)> = routes
+        .iter()
+        .enumerate()
+        .map(|(index, route)| (route.route_id.as_str(), (index, route)))
+        .collect();
+
+    let xs = schematic.positions.values().map(|(x, _)| *x);
+    let ys = schematic.positions.values().map(|(_, y)| *y);
+    let (min_x, max_x) = (
+        xs.clone().fold(f64::INFINITY, f64::min),
+        xs.fold(f64::NEG_INFINITY, f64::max),
+    );
+    let (min_y, max_y) = (
+        ys.clone().fold(f64::INFINITY, f64::min),
+        ys.fold(f64::NEG_INFINITY, f64::max),
+    );
+    if !min_x.is_finite() {
+        return SvgDocument::new(2.0 * MARGIN_PX, 2.0 * MARGIN_PX).render();
+    }
+    // Screen y grows downwards
+    let point = |(x, y): (f64, f64)| {
+        (
+            MARGIN_PX + (x - min_x) * UNIT_PX,
+            MARGIN_PX + (max_y - y) * UNIT_PX,
+        )
+    };
+
+    let width = 2.0 * MARGIN_PX + (max_x - min_x) * UNIT_PX;
+    let height = 2.0 * MARGIN_PX + (max_y - min_y) * UNIT_PX;
+    let mut document = SvgDocument::new(width, height);
+
+    for ((from, to), link) in &network.links {
+        let (Some(from), Some(to)) = (schematic.positions.get(from), schematic.positions.get(to))
+        else {
+            continue;
+        };
+        let (from, to) = (point(*from), point(*to));
+        let length = ((to.0 - from.0).powi(2) + (to.1 - from.1).powi(2))
+            .sqrt()
+            .max(1e-9);
+        let normal = (-(to.1 - from.1) / length, (to.0 - from.0) / length);
+        // Routes sharing a link are drawn side by side
+        let count = link.route_ids.len() as f64;
+        for (index, route_id) in link.route_ids.iter().enumerate() {
+            let offset = (index as f64 - (count - 1.0) / 2.0) * 5.0;
+            let (colour_index, route) = match route_index.get(route_id.as_str()) {
+                Some((colour_index, route)) => (*colour_index, Some(*route)),
+                None => (0, None),
+            };
+            document.line(
+                (from.0 + normal.0 * offset, from.1 + normal.1 * offset),
+                (to.0 + normal.0 * offset, to.1 + normal.1 * offset),
+                &format!(
+                    "stroke:{};stroke-width:4;stroke-linecap:round",
+                    svg::route_colour(route, colour_index)
+                ),
+            );
+        }
+    }
+
+    for (station_id, position) in &schematic.positions {
+        let center = point(*position);
+        let name = network
+            .stations
+            .get(station_id)
+            .and_then(|station| station.name.as_deref())
+            .unwrap_or(station_id);
+        document.circle(
+            center,
+            5.0,
+            "fill:#fff;stroke:#333;stroke-width:2",
+            &format!("station-{}", station_id),
+            Some(name),
+        );
+        document.text((center.0 + 8.0, center.1 - 8.0), name, "font-size:10px");
+    }
+
+    document.render()
+}
+
+#[derive(Serialize)]
+struct SchematicFeature<'a> {
+    #[serde(serialize_with = "geojson::ser::serialize_geometry")]
+    geometry: geo_types::Geometry,
+    kind: &'a str,
+    id: String,
+    name: Option<&'a str>,
+    route_ids: Option<&'a BTreeSet<String>>,
+    trip_count: Option<usize>,
+}
+
+// Schematic stations and links as GeoJSON, projected back around the network's centre so that
+// the schematic can be laid over a map
+pub fn schematic_to_geojson(
+    network: &StationNetwork,
+    schematic: &Schematic,
+) -> Result<String, String> {
+    let mut features: Vec<SchematicFeature> = Vec::new();
+    for ((from, to), link) in &network.links {
+        let (Some(from_position), Some(to_position)) =
+            (schematic.positions.get(from), schematic.positions.get(to))
+        else {
+            continue;
+        };
+        features.push(SchematicFeature {
+            geometry: geo_types::Geometry::LineString(geo_types::LineString::from(vec![
+                schematic.to_point(*from_position),
+                schematic.to_point(*to_position),
+            ])),
+            kind: "link",
+            id: format!("{}-{}", from, to),
+            name: None,
+            route_ids: Some(&link.route_ids),
+            trip_count: Some(link.trip_count),
+        });
+    }
+    for (station_id, position) in &schematic.positions {
+        features.push(SchematicFeature {
+            geometry: geo_types::Geometry::Point(schematic.to_point(*position)),
+            kind: "station",
+            id: station_id.to_owned(),
+            name: network
+                .stations
+                .get(station_id)
+                .and_then(|station| station.name.as_deref()),
+            route_ids: None,
+            trip_count: None,
+        });
+    }
+
+    crate::functions::to_geojson_string(&features)
+}
+
+#[test]
+fn test_octilinear_layout() {
+    // A slightly skewed square with one diagonal
+    let station = |id: &str, x: f64, y: f64| {
+        (
+            id.to_owned(),
+            Station {
+                station_id: id.to_owned(),
+                name: None,
+                point: Some(geo_types::Point::new(x, y)),
+            },
+        )
+    };
+    let link = |from: &str, to: &str| ((from.to_owned(), to.to_owned()), Link::default());
+    let network = StationNetwork {
+        stations: BTreeMap::from([
+            station("A", 8.0, 48.0),
+            station("B", 8.013, 48.002),
+            station("C", 8.016, 48.011),
+            station("D", 7.998, 48.008),
+        ]),
+        links: BTreeMap::from([
+            link("A", "B"),
+            link("B", "C"),
+            link("C", "D"),
+            link("A", "D"),
+            link("A", "C"),
+        ]),
+    };
+
+    let schematic = octilinear_layout(&network);
+    assert_eq!(schematic.octilinear_links(), 5);
+    assert_eq!(
+        schematic.directions[&(String::from("A"), String::from("B"))],
+        0
+    );
+    assert_eq!(
+        schematic.directions[&(String::from("A"), String::from("C"))],
+        1
+    );
+    assert_eq!(
+        schematic.directions[&(String::from("A"), String::from("D"))],
+        2
+    );
+}
+
+#[test]
+fn test_octilinear_layout_keeps_edge_order() {
+    // Three links around C, the two to the east only 10° apart
+    let station = |id: &str, x: f64, y: f64| {
+        (
+            id.to_owned(),
+            Station {
+                station_id: id.to_owned(),
+                name: None,
+                point: Some(geo_types::Point::new(x, y)),
+            },
+        )
+    };
+    let link = |from: &str, to: &str, trip_count: usize| {
+        (
+            (from.to_owned(), to.to_owned()),
+            Link {
+                trip_count,
+                ..Link::default()
+            },
+        )
+    };
+    let network = StationNetwork {
+        stations: BTreeMap::from([
+            station("C", 8.0, 48.0),
+            station("P", 8.01263, 48.003076),
+            station("Q", 8.013236, 48.001562),
+            station("R", 7.98737, 47.996924),
+        ]),
+        links: BTreeMap::from([link("C", "P", 10), link("C", "Q", 1), link("C", "R", 5)]),
+    };
+
+    let schematic = octilinear_layout(&network);
+    // Q can't go north east of P and has to turn south east instead
+    assert_eq!(
+        schematic.directions[&(String::from("C"), String::from("Q"))],
+        7
+    );
+    assert_eq!(schematic.octilinear_links(), 3);
+    let positions: HashMap<&str, (f64, f64)> = schematic
+        .positions
+        .iter()
+        .map(|(station_id, position)| (station_id.as_str(), *position))
+        .collect();
+    assert_eq!(
+        cyclic_order("C", &["P", "Q", "R"], &positions),
+        vec!["P", "R", "Q"]
+    );
+
+    // A ninth link around a station gets no direction
+    let mut stations = vec![station("C", 8.0, 48.0)];
+    let mut links = Vec::new();
+    for spoke in 0..9 {
+        let angle = (spoke as f64 * 40.0).to_radians();
+        let id = format!("S{}", spoke);
+        stations.push(station(
+            &id,
+            8.0 + angle.cos() * 0.01,
+            48.0 + angle.sin() * 0.007,
+        ));
+        links.push(link("C", &id, 1));
+    }
+    let network = StationNetwork {
+        stations: stations.into_iter().collect(),
+        links: links.into_iter().collect(),
+    };
+    assert_eq!(octilinear_layout(&network).directions.len(), 8);
+}
+
+#[test]
+fn test_schematic_outputs() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let feed = Feed::load(&mut gtfs_file);
+    let network = network::station_network(&feed, &Scope::Feed.route_ids(&feed, &[]));
+    let schematic = octilinear_layout(&network);
+
+    assert_eq!(schematic.octilinear_links(), network.links.len());
+    let output = schematic_to_svg(&network, &schematic, &feed.routes);
+    assert_eq!(output.matches("<circle").count(), 9);
+    let geojson = schematic_to_geojson(&network, &schematic).unwrap();
+    assert_eq!(geojson.matches("\"Feature\"").count(), 17);
+}
@@ -0,0 +1,133 @@
+use std::fmt::Write;
+
+use crate::functions::gtfs::Route;
+
+// Colours for routes that don't define their own route_color
+const PALETTE: [&str; 10] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
+    "#bcbd22", "#17becf",
+];
+
+// A minimal svg writer, elements are appended in drawing order
+pub struct SvgDocument {
+    width: f64,
+    height: f64,
+    elements: Vec<String>,
+}
+
+impl SvgDocument {
+    pub fn new(width: f64, height: f64) -> SvgDocument {
+        SvgDocument {
+            width,
+            height,
+            elements: Vec::new(),
+        }
+    }
+
+    pub fn rect(
+        &mut self,
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        style: &str,
+        title: Option<&str>,
+    ) {
+        let title = title.map_or(String::new(), |title| {
+            format!("<title>{}</title>", escape(title))
+        });
+        self.elements.push(format!(
+            r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" style="{}">{}</rect>"#,
+            x, y, width, height, style, title
+        ));
+    }
+
+    pub fn line(&mut self, from: (f64, f64), to: (f64, f64), style: &str) {
+        self.elements.push(format!(
+            r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" style="{}"/>"#,
+            from.0, from.1, to.0, to.1, style
+        ));
+    }
+
+    pub fn circle(
+        &mut self,
+        center: (f64, f64),
+        radius: f64,
+        style: &str,
+        id: &str,
+        title: Option<&str>,
+    ) {
+        let title = title.map_or(String::new(), |title| {
+            format!("<title>{}</title>", escape(title))
+        });
+        self.elements.push(format!(
+            r#"<circle id="{}" cx="{:.1}" cy="{:.1}" r="{:.1}" style="{}">{}</circle>"#,
+            escape(id),
+            center.0,
+            center.1,
+            radius,
+            style,
+            title
+        ));
+    }
+
+    pub fn polyline(&mut self, points: &[(f64, f64)], style: &str, title: Option<&str>) {
+        let points: Vec<String> = points
+            .iter()
+            .map(|(x, y)| format!("{:.1},{:.1}", x, y))
+            .collect();
+        let title = title.map_or(String::new(), |title| {
+            format!("<title>{}</title>", escape(title))
+        });
+        self.elements.push(format!(
+            r#"<polyline points="{}" style="fill:none;{}">{}</polyline>"#,
+            points.join(" "),
+            style,
+            title
+        ));
+    }
+
+    pub fn text(&mut self, position: (f64, f64), text: &str, style: &str) {
+        self.elements.push(format!(
+            r#"<text x="{:.1}" y="{:.1}" style="{}">{}</text>"#,
+            position.0,
+            position.1,
+            style,
+            escape(text)
+        ));
+    }
+
+    pub fn render(&self) -> String {
+        let mut output = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{:.0}" height="{:.0}" viewBox="0 0 {:.0} {:.0}" font-family="sans-serif">"#,
+            self.width, self.height, self.width, self.height
+        );
+        output.push('\n');
+        for element in &self.elements {
+            let _ = writeln!(output, "  {}", element);
+        }
+        output.push_str("</svg>\n");
+        output
+    }
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// The route's own colour if it has a valid one, a palette colour otherwise
+pub fn route_colour(route: Option<&Route>, index: usize) -> String {
+    if let Some(colour) = route.and_then(|route| route.route_color.as_ref()) {
+        if colour.len() == 6 && colour.chars().all(|c| c.is_ascii_hexdigit()) {
+            return format!("#{}", colour);
+        }
+    }
+    PALETTE[index % PALETTE.len()].to_owned()
+}
+
+pub fn hour_label(seconds: u64) -> String {
+    format!("{:02}:00", seconds / 3600)
+}
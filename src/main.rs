@@ -3,9 +3,13 @@ mod objects;
 
 use std::path::PathBuf;
 
+use chrono::NaiveDate;
 use clap::{Args, Parser, Subcommand};
-use functions::{gtfs, visualize};
-use objects::{route, stop};
+use functions::{
+    analytics, delays, detour, geodata, graph, gtfs, hazard, isochrone, matrix, network_stats,
+    realtime, routing, scenario, stats, summary, transfers, validation, visualize,
+};
+use objects::{block, feed, network, pattern, route, spatial, stop};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -20,6 +24,29 @@ enum Commands {
     GeoJson(GeoJsonArgs),
     Extract(ExtractArgs),
     Visualize(VisualizeArgs),
+    Patterns(PatternsArgs),
+    Headways(HeadwaysArgs),
+    Stats(StatsArgs),
+    Summary(SummaryArgs),
+    Blocks(BlocksArgs),
+    Schematic(SchematicArgs),
+    Graph(GraphArgs),
+    NetworkStats(NetworkStatsArgs),
+    Route(RouteArgs),
+    Isochrone(IsochroneArgs),
+    Matrix(MatrixArgs),
+    Transfers(TransfersArgs),
+    Nearby(NearbyArgs),
+    EchoRt(EchoRtArgs),
+    ValidateRt(ValidateRtArgs),
+    Realized(RealizedArgs),
+    Delays(DelaysArgs),
+    Synthetic(SyntheticArgs),
+    ServeRt(ServeRtArgs),
+    Trajectories(TrajectoriesArgs),
+    Scenario(ScenarioArgs),
+    Hazard(HazardArgs),
+    Detour(DetourArgs),
 }
 
 #[derive(Args)]
@@ -47,7 +74,7 @@ struct ExtractArgs {
     #[command(subcommand)]
     file: StandardFiles,
 
-    id: String
+    id: String,
 }
 
 #[derive(Args)]
@@ -55,10 +82,609 @@ struct VisualizeArgs {
     input: std::path::PathBuf,
 
     #[command(subcommand)]
-    file: StandardFiles,
+    diagram: Diagram,
+
+    id: String,
+    output: std::path::PathBuf,
+}
+
+#[derive(Subcommand)]
+enum Diagram {
+    // Line diagram of a route's stations and the links between them
+    Routes {
+        #[arg(long, value_enum, default_value_t = visualize::RouteDiagramFormat::Dot)]
+        format: visualize::RouteDiagramFormat,
+    },
+    // Time-distance diagram of a route or pattern's trips on a service date
+    Marey {
+        #[arg(value_parser = parse_date)]
+        date: NaiveDate,
+    },
+}
+
+#[derive(Args)]
+struct PatternsArgs {
+    input: std::path::PathBuf,
+    output: std::path::PathBuf,
+
+    #[arg(long, value_enum, default_value_t = pattern::PatternFormat::Csv)]
+    format: pattern::PatternFormat,
+}
+
+#[derive(Args)]
+struct HeadwaysArgs {
+    input: std::path::PathBuf,
+
+    #[arg(value_parser = parse_date)]
+    date: NaiveDate,
+    output: std::path::PathBuf,
+
+    #[arg(long, value_enum, default_value_t = analytics::HeadwayFormat::Csv)]
+    format: analytics::HeadwayFormat,
+}
+
+#[derive(Args)]
+struct StatsArgs {
+    input: std::path::PathBuf,
+    output: std::path::PathBuf,
+
+    // Without a date or day type, typical weekdays, saturdays and sundays are reported
+    #[arg(long, value_parser = parse_date, conflicts_with = "day_type")]
+    date: Option<NaiveDate>,
+
+    #[arg(long, value_enum)]
+    day_type: Option<stats::DayType>,
+
+    #[arg(long, value_enum, default_value_t = stats::StatsFormat::Csv)]
+    format: stats::StatsFormat,
+}
+
+#[derive(Args)]
+struct SummaryArgs {
+    input: std::path::PathBuf,
+
+    // Also write the summary as json
+    #[arg(long)]
+    output: Option<std::path::PathBuf>,
+}
+
+#[derive(Args)]
+struct BlocksArgs {
+    input: std::path::PathBuf,
+
+    #[arg(value_parser = parse_date)]
+    date: NaiveDate,
+    output: std::path::PathBuf,
+
+    #[arg(long, value_enum, default_value_t = block::BlockFormat::Csv)]
+    format: block::BlockFormat,
+
+    // Also write the peak vehicle requirements as csv
+    #[arg(long)]
+    requirements: Option<std::path::PathBuf>,
+}
+
+#[derive(Args)]
+struct SchematicArgs {
+    input: std::path::PathBuf,
+    output: std::path::PathBuf,
+
+    // Without a route, agency or network the whole feed is drawn
+    #[arg(long = "route")]
+    routes: Vec<String>,
+
+    #[arg(long)]
+    agency: Option<String>,
+
+    #[arg(long)]
+    network: Option<String>,
+
+    #[arg(long, value_enum, default_value_t = visualize::schematic::SchematicFormat::Svg)]
+    format: visualize::schematic::SchematicFormat,
+}
+
+#[derive(Args)]
+struct GraphArgs {
+    input: std::path::PathBuf,
+    output: std::path::PathBuf,
+
+    #[arg(long, value_enum, default_value_t = graph::GraphFormat::Graphml)]
+    format: graph::GraphFormat,
+
+    // Keep stops apart instead of collapsing them into their parent stations
+    #[arg(long)]
+    stops: bool,
+}
+
+#[derive(Args)]
+struct NetworkStatsArgs {
+    input: std::path::PathBuf,
+    output: std::path::PathBuf,
+
+    #[arg(long, value_enum, default_value_t = network_stats::NetworkStatsFormat::Csv)]
+    format: network_stats::NetworkStatsFormat,
+
+    // Keep stops apart instead of collapsing them into their parent stations
+    #[arg(long)]
+    stops: bool,
+}
+
+#[derive(Args)]
+struct RouteArgs {
+    input: std::path::PathBuf,
+
+    #[arg(value_parser = parse_date)]
+    date: NaiveDate,
+
+    #[arg(value_parser = parse_time)]
+    time: gtfs::Time,
+
+    // A stop or station id, or a coordinate as lat,lon
+    #[arg(value_parser = parse_place)]
+    from: routing::Place,
+
+    #[arg(value_parser = parse_place)]
+    to: routing::Place,
+
+    #[arg(long, default_value_t = 4)]
+    max_transfers: usize,
+
+    #[command(flatten)]
+    walk: WalkArgs,
+
+    // Also write the journeys as json
+    #[arg(long)]
+    output: Option<std::path::PathBuf>,
+}
+
+#[derive(Args)]
+struct IsochroneArgs {
+    input: std::path::PathBuf,
+
+    #[arg(value_parser = parse_date)]
+    date: NaiveDate,
+
+    // Start of the departure window
+    #[arg(value_parser = parse_time)]
+    time: gtfs::Time,
+
+    // A stop or station id, or a coordinate as lat,lon
+    #[arg(value_parser = parse_place)]
+    from: routing::Place,
+
+    // Isochrone bands as GeoJSON polygons
+    output: std::path::PathBuf,
+
+    #[command(flatten)]
+    window: WindowArgs,
+
+    // Percentile of travel times over the departures, 50 being the median and 0 the fastest
+    #[arg(long, default_value_t = 50.0)]
+    percentile: f64,
+
+    #[arg(long, value_delimiter = ',', default_value = "15,30,45,60")]
+    bands: Vec<u64>,
+
+    #[arg(long, default_value_t = 4)]
+    max_transfers: usize,
+
+    #[command(flatten)]
+    walk: WalkArgs,
+
+    // Also write the reached stops and their travel times as GeoJSON points
+    #[arg(long)]
+    stops: Option<std::path::PathBuf>,
+}
+
+#[derive(Args)]
+struct MatrixArgs {
+    input: std::path::PathBuf,
+
+    #[arg(value_parser = parse_date)]
+    date: NaiveDate,
+
+    // Start of the departure window
+    #[arg(value_parser = parse_time)]
+    time: gtfs::Time,
+
+    output: std::path::PathBuf,
+
+    // Points or zones from a CSV file with id, lat and lon columns or from GeoJSON, all stops
+    // when left out
+    #[arg(long)]
+    origins: Option<std::path::PathBuf>,
+
+    #[arg(long)]
+    destinations: Option<std::path::PathBuf>,
+
+    #[command(flatten)]
+    window: WindowArgs,
+
+    // Percentiles of travel times over the departures, 50 being the median and 0 the fastest
+    #[arg(long, value_delimiter = ',', default_value = "50")]
+    percentiles: Vec<f64>,
+
+    #[arg(long, default_value_t = 4)]
+    max_transfers: usize,
+
+    #[command(flatten)]
+    walk: WalkArgs,
+
+    #[arg(long, value_enum, default_value_t = matrix::MatrixFormat::Csv)]
+    format: matrix::MatrixFormat,
+}
+
+#[derive(Args)]
+struct TransfersArgs {
+    input: std::path::PathBuf,
+
+    // The feed's transfers.txt with the generated walking transfers added
+    output: std::path::PathBuf,
+
+    #[command(flatten)]
+    walk: WalkArgs,
+}
+
+#[derive(Args)]
+struct NearbyArgs {
+    input: std::path::PathBuf,
+
+    // A coordinate as lat,lon, not needed with a polygon
+    #[arg(value_parser = parse_point, required_unless_present = "polygon", conflicts_with = "polygon")]
+    point: Option<geo_types::Point>,
+
+    // How many of the nearest to list
+    #[arg(long, default_value_t = 5, conflicts_with_all = ["radius", "polygon"])]
+    count: usize,
+
+    // Everything within this many metres
+    #[arg(long, conflicts_with = "polygon")]
+    radius: Option<f64>,
+
+    // Everything inside the polygons of a GeoJSON file
+    #[arg(long)]
+    polygon: Option<std::path::PathBuf>,
+
+    // Look for shapes instead of stops
+    #[arg(long)]
+    shapes: bool,
+
+    // Also write the matches as json
+    #[arg(long)]
+    output: Option<std::path::PathBuf>,
+}
+
+#[derive(Args)]
+struct EchoRtArgs {
+    input: std::path::PathBuf,
+
+    // A GTFS-RT feed message as protobuf or json
+    realtime: std::path::PathBuf,
+
+    // Print the decoded message as json instead
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args)]
+struct ValidateRtArgs {
+    input: std::path::PathBuf,
+
+    // A GTFS-RT feed message as protobuf or json
+    realtime: std::path::PathBuf,
+
+    // Unix time to check the feed's age against, for feeds fetched just now
+    #[arg(long)]
+    now: Option<u64>,
+
+    // Minutes late beyond which a delay is implausible
+    #[arg(long, default_value_t = 180)]
+    max_delay: i64,
+
+    // Minutes early beyond which a delay is implausible
+    #[arg(long, default_value_t = 30)]
+    max_early: i64,
+
+    // Seconds a timestamp may be older than the feed's
+    #[arg(long, default_value_t = 300)]
+    max_age: u64,
+
+    // Metres a vehicle may be away from its trip's shape
+    #[arg(long, default_value_t = 200.0)]
+    max_shape_distance: f64,
+
+    // Also write the findings as csv
+    #[arg(long)]
+    output: Option<std::path::PathBuf>,
+}
+
+#[derive(Args)]
+struct RealizedArgs {
+    input: std::path::PathBuf,
+
+    // A directory of archived TripUpdate snapshots, or a single one
+    snapshots: std::path::PathBuf,
+
+    #[arg(value_parser = parse_date)]
+    date: NaiveDate,
+
+    // Realized stop times of every trip instance as csv
+    output: std::path::PathBuf,
+
+    // Also write the feed as it ran on the date
+    #[arg(long)]
+    feed: Option<std::path::PathBuf>,
+}
+
+#[derive(Args)]
+struct DelaysArgs {
+    input: std::path::PathBuf,
+
+    // Csv with trip_id, service_date, stop_id or stop_sequence and observed_arrival or
+    // observed_departure, plus start_time for frequency based trips
+    observations: std::path::PathBuf,
+
+    output: std::path::PathBuf,
+
+    #[arg(long, value_enum, default_value_t = delays::Grouping::Stop)]
+    by: delays::Grouping,
+
+    // Seconds early still counting as on time
+    #[arg(long, default_value_t = 60)]
+    early: i64,
+
+    // Seconds late still counting as on time
+    #[arg(long, default_value_t = 300)]
+    late: i64,
+
+    // Also write the delay of every call of the observed trips as csv
+    #[arg(long)]
+    calls: Option<std::path::PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = delays::DelaysFormat::Csv)]
+    format: delays::DelaysFormat,
+}
+
+#[derive(Args)]
+struct SyntheticArgs {
+    input: std::path::PathBuf,
+
+    #[arg(value_parser = parse_date)]
+    date: NaiveDate,
+
+    // Directory for the timestamped trip update and vehicle position snapshots
+    output: std::path::PathBuf,
+
+    #[arg(long, value_parser = parse_time, default_value = "06:00")]
+    start: gtfs::Time,
+
+    #[arg(long, value_parser = parse_time, default_value = "10:00")]
+    end: gtfs::Time,
+
+    // Seconds between snapshots
+    #[arg(long, default_value_t = 30)]
+    interval: u64,
+
+    // Probability of a trip being canceled
+    #[arg(long, default_value_t = 0.02)]
+    cancel: f64,
+
+    // Probability of a trip detouring around stops
+    #[arg(long, default_value_t = 0.01)]
+    detour: f64,
+
+    #[arg(long, default_value_t = 1)]
+    seed: u64,
+}
+
+#[derive(Args)]
+struct ServeRtArgs {
+    // A directory of GTFS-RT snapshots, recorded or from the synthetic command
+    snapshots: std::path::PathBuf,
+
+    #[arg(long, default_value_t = 8080)]
+    port: u16,
+
+    // Replay seconds per real second
+    #[arg(long, default_value_t = 1.0)]
+    speed: f64,
+
+    // Start over after the last snapshot
+    #[arg(long = "loop")]
+    looping: bool,
+}
+
+#[derive(Args)]
+struct TrajectoriesArgs {
+    input: std::path::PathBuf,
+
+    // A directory of archived VehiclePosition snapshots, or a single one
+    snapshots: std::path::PathBuf,
+
+    // Every position as GeoJSON points
+    points: std::path::PathBuf,
+
+    // A GeoJSON line per vehicle and trip
+    trajectories: std::path::PathBuf,
+}
+
+#[derive(Args)]
+struct ScenarioArgs {
+    input: std::path::PathBuf,
+
+    // YAML or JSON with closures of stops and segments between stops over date ranges
+    scenario: std::path::PathBuf,
+
+    // The edited feed
+    output: std::path::PathBuf,
+
+    // Also write the closures as GTFS-RT alerts, as JSON for a .json path and protobuf otherwise
+    #[arg(long)]
+    alerts: Option<std::path::PathBuf>,
+}
+
+#[derive(Args)]
+struct HazardArgs {
+    input: std::path::PathBuf,
+
+    // GeoJSON with the flood extent or other hazard polygons
+    polygons: std::path::PathBuf,
+
+    // First date of the hazard, and the only date connectivity is compared on, counting
+    // destinations reached without a transfer
+    #[arg(value_parser = parse_date)]
+    date: NaiveDate,
+
+    // Impacted stops
+    output: std::path::PathBuf,
+
+    // Last date of the hazard, the first date if left out
+    #[arg(long, value_parser = parse_date)]
+    to: Option<NaiveDate>,
+
+    #[arg(long, value_enum, default_value_t = hazard::HazardFormat::Csv)]
+    format: hazard::HazardFormat,
+
+    // Also write the impacted routes as csv
+    #[arg(long)]
+    routes: Option<std::path::PathBuf>,
+
+    // Also write the feed with trips skipping flooded stops and cut through the hazard for the
+    // dates. Flooded stops stay in stops.txt, trips call at them on other dates.
+    #[arg(long)]
+    feed: Option<std::path::PathBuf>,
+}
+
+#[derive(Args)]
+struct DetourArgs {
+    input: std::path::PathBuf,
 
+    // Route or pattern id
     id: String,
-    output: std::path::PathBuf
+
+    // First and last date of the detour
+    #[arg(value_parser = parse_date)]
+    from: NaiveDate,
+    #[arg(value_parser = parse_date)]
+    to: NaiveDate,
+
+    // Edited feed
+    output: std::path::PathBuf,
+
+    // Closed stops, separated by commas
+    #[arg(long, required = true, value_delimiter = ',')]
+    closed: Vec<String>,
+
+    // Replacement of every closed stop in the same order, the nearest open stops if left out
+    #[arg(long, value_delimiter = ',')]
+    replacements: Vec<String>,
+
+    // Suffix of the new trip, service and shape ids
+    #[arg(long, default_value = "detour")]
+    tag: String,
+}
+
+#[derive(Args)]
+struct WindowArgs {
+    // Length of the departure window in minutes, starting at the given time
+    #[arg(long, default_value_t = 60)]
+    window: u64,
+
+    // Minutes between departures in the window
+    #[arg(long, default_value_t = 1)]
+    step: u64,
+}
+
+impl WindowArgs {
+    fn window(&self, start: &gtfs::Time) -> routing::Window {
+        routing::Window {
+            start: start.seconds(),
+            end: start.seconds() + self.window * 60,
+            step: self.step * 60,
+        }
+    }
+}
+
+#[derive(Args)]
+struct WalkArgs {
+    // Stops this close are linked by a walk, unless transfers.txt says otherwise
+    #[arg(long, default_value_t = 500.0)]
+    walk_distance: f64,
+
+    #[arg(long, default_value_t = 1.3)]
+    walk_speed: f64,
+}
+
+impl WalkArgs {
+    fn settings(&self) -> routing::WalkSettings {
+        routing::WalkSettings {
+            max_distance_m: self.walk_distance,
+            speed_m_s: self.walk_speed,
+        }
+    }
+}
+
+// Service dates are accepted in GTFS (20240131) and ISO (2024-01-31) notation
+fn parse_date(input: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(input, "%Y%m%d")
+        .or_else(|_| NaiveDate::parse_from_str(input, "%Y-%m-%d"))
+        .map_err(|error| format!("{}: expected a date like 20240131 or 2024-01-31", error))
+}
+
+// Times of day like 08:00 or 08:00:00, past 24:00 for trips after midnight
+fn parse_time(input: &str) -> Result<gtfs::Time, String> {
+    let parts: Result<Vec<u64>, _> = input.split(':').map(|part| part.parse::<u64>()).collect();
+    match parts.as_deref() {
+        Ok([h, m]) if *m < 60 => Ok(gtfs::Time { h: *h, m: *m, s: 0 }),
+        Ok([h, m, s]) if *m < 60 && *s < 60 => Ok(gtfs::Time {
+            h: *h,
+            m: *m,
+            s: *s,
+        }),
+        _ => Err(String::from("expected a time like 08:00 or 08:00:00")),
+    }
+}
+
+// Coordinates are given as lat,lon
+fn parse_point(input: &str) -> Result<geo_types::Point, String> {
+    let coordinates = input
+        .split_once(',')
+        .map(|(lat, lon)| (lat.trim().parse::<f64>(), lon.trim().parse::<f64>()));
+    match coordinates {
+        Some((Ok(lat), Ok(lon))) => Ok(geo_types::Point::new(lon, lat)),
+        _ => Err(String::from("expected a coordinate like 49.1427,9.2109")),
+    }
+}
+
+// Anything that isn't a coordinate is taken as a stop id
+fn parse_place(input: &str) -> Result<routing::Place, String> {
+    match parse_point(input) {
+        Ok(point) => Ok(routing::Place::Coordinate(point)),
+        Err(_) => Ok(routing::Place::Stop(input.to_owned())),
+    }
+}
+
+// Prints the matches of a nearby query and returns them as json
+fn nearby_stops(matches: Vec<spatial::StopMatch>) -> String {
+    for stop in &matches {
+        println!(
+            "{:>8.0} m  {}  {}",
+            stop.distance_m,
+            stop.stop_id,
+            stop.stop_name.as_deref().unwrap_or_default()
+        );
+    }
+    functions::to_json_string(&matches)
+}
+
+fn nearby_shapes(matches: Vec<spatial::ShapeMatch>) -> String {
+    for shape in &matches {
+        println!(
+            "{:>8.0} m  {}  segment {}",
+            shape.distance_m, shape.shape_id, shape.segment
+        );
+    }
+    functions::to_json_string(&matches)
 }
 
 #[derive(Subcommand)]
@@ -170,9 +796,7 @@ fn main() {
             let mut gtfs_file = functions::load_gtfs_file(gtfs_path);
 
             match args.file {
-                StandardFiles::Stops => {
-                    stop::print_stop_details(args.id, &mut gtfs_file)
-                }
+                StandardFiles::Stops => stop::print_stop_details(args.id, &mut gtfs_file),
                 StandardFiles::Routes => {
                     route::print_route_details(&mut gtfs_file, args.id);
                 }
@@ -180,19 +804,678 @@ fn main() {
                     println!("Not implemented yet")
                 }
             }
-        },
+        }
         Commands::Visualize(args) => {
             let gtfs_path: PathBuf = args.input;
             let mut gtfs_file = functions::load_gtfs_file(gtfs_path);
 
-            match args.file {
-                StandardFiles::Routes => {
-                    let dot = visualize::route_to_dot(args.id, &mut gtfs_file);
-                    std::fs::write(args.output, dot).expect("Unable to write file");
-                },
-                _ => {
-                    println!("Not implemented yet")
-                },
+            match args.diagram {
+                Diagram::Routes { format } => {
+                    let Some(route_graph) = visualize::route_graph(&mut gtfs_file, &args.id) else {
+                        println!("No route with id {}", args.id);
+                        return;
+                    };
+                    let output = match format {
+                        visualize::RouteDiagramFormat::Svg => {
+                            visualize::line_diagram::route_to_svg(&route_graph)
+                        }
+                        visualize::RouteDiagramFormat::Dot => visualize::route_to_dot(&route_graph),
+                    };
+                    std::fs::write(args.output, output).expect("Unable to write file");
+                }
+                Diagram::Marey { date } => {
+                    let feed = feed::Feed::load(&mut gtfs_file);
+                    match visualize::marey::marey_diagram(&feed, &args.id, date) {
+                        Some(svg) => {
+                            std::fs::write(args.output, svg).expect("Unable to write file")
+                        }
+                        None => println!("No route or pattern with id {}", args.id),
+                    }
+                }
+            }
+        }
+        Commands::Patterns(args) => {
+            let gtfs_path: PathBuf = args.input;
+            let mut gtfs_file = functions::load_gtfs_file(gtfs_path);
+
+            let patterns = pattern::extract_patterns(&mut gtfs_file);
+            let output = match args.format {
+                pattern::PatternFormat::Csv => {
+                    let records: Vec<pattern::PatternRecord> = patterns
+                        .iter()
+                        .map(pattern::PatternRecord::from_pattern)
+                        .collect();
+                    functions::to_csv_string(&records)
+                }
+                pattern::PatternFormat::Json => functions::to_json_string(&patterns),
+            };
+            std::fs::write(args.output, output).expect("Unable to write file");
+        }
+        Commands::Headways(args) => {
+            let gtfs_path: PathBuf = args.input;
+            let mut gtfs_file = functions::load_gtfs_file(gtfs_path);
+
+            let stats = analytics::read_headway_stats(&mut gtfs_file, args.date);
+            let output = match args.format {
+                analytics::HeadwayFormat::Csv => Ok(functions::to_csv_string(&stats)),
+                analytics::HeadwayFormat::Json => Ok(functions::to_json_string(&stats)),
+                analytics::HeadwayFormat::GeoJson => {
+                    let stops: Vec<gtfs::Stop> = gtfs_file.read_vec();
+                    analytics::stop_stats_geojson(&stats, &stops)
+                }
+            };
+            match output {
+                Ok(output) => std::fs::write(args.output, output).expect("Unable to write file"),
+                Err(error) => println!("{}", error),
+            }
+        }
+        Commands::Stats(args) => {
+            let gtfs_path: PathBuf = args.input;
+            let mut gtfs_file = functions::load_gtfs_file(gtfs_path);
+            let feed = feed::Feed::load(&mut gtfs_file);
+
+            let days: Vec<stats::ServiceDay> = match (args.date, args.day_type) {
+                (Some(date), _) => vec![stats::ServiceDay::Date(date)],
+                (None, Some(day_type)) => vec![stats::ServiceDay::Typical(day_type)],
+                (None, None) => vec![
+                    stats::ServiceDay::Typical(stats::DayType::Weekday),
+                    stats::ServiceDay::Typical(stats::DayType::Saturday),
+                    stats::ServiceDay::Typical(stats::DayType::Sunday),
+                ],
+            };
+            let kpis = stats::service_kpis(&feed, &days);
+            let output = match args.format {
+                stats::StatsFormat::Csv => functions::to_csv_string(&kpis),
+                stats::StatsFormat::Json => functions::to_json_string(&kpis),
+            };
+            std::fs::write(args.output, output).expect("Unable to write file");
+        }
+        Commands::Blocks(args) => {
+            let gtfs_path: PathBuf = args.input;
+            let mut gtfs_file = functions::load_gtfs_file(gtfs_path);
+            let feed = feed::Feed::load(&mut gtfs_file);
+
+            let (blocks, unblocked) = block::blocks_on_date(&feed, args.date);
+            let requirements = block::vehicle_requirements(&blocks, &unblocked, &feed);
+            block::print_vehicle_requirements(&requirements);
+
+            let output = match args.format {
+                block::BlockFormat::Csv => {
+                    functions::to_csv_string(&block::block_links(&blocks, &feed))
+                }
+                block::BlockFormat::Json => functions::to_json_string(&blocks),
+                block::BlockFormat::Svg => visualize::gantt::blocks_to_svg(&blocks, &feed),
+            };
+            std::fs::write(args.output, output).expect("Unable to write file");
+            if let Some(path) = args.requirements {
+                std::fs::write(path, functions::to_csv_string(&requirements))
+                    .expect("Unable to write file");
+            }
+        }
+        Commands::Schematic(args) => {
+            let gtfs_path: PathBuf = args.input;
+            let mut gtfs_file = functions::load_gtfs_file(gtfs_path);
+            let feed = feed::Feed::load(&mut gtfs_file);
+            let route_networks: Vec<gtfs::RouteNetwork> = gtfs_file.read_optional_vec();
+
+            let scope = match (args.routes.is_empty(), args.agency, args.network) {
+                (false, _, _) => network::Scope::Routes(args.routes),
+                (true, Some(agency), _) => network::Scope::Agency(agency),
+                (true, None, Some(network_id)) => network::Scope::Network(network_id),
+                (true, None, None) => network::Scope::Feed,
+            };
+            let station_network =
+                network::station_network(&feed, &scope.route_ids(&feed, &route_networks));
+            let schematic = visualize::schematic::octilinear_layout(&station_network);
+            println!(
+                "{} stations, {} of {} links octilinear",
+                schematic.positions.len(),
+                schematic.octilinear_links(),
+                station_network.links.len()
+            );
+
+            let output = match args.format {
+                visualize::schematic::SchematicFormat::Svg => {
+                    Ok(visualize::schematic::schematic_to_svg(
+                        &station_network,
+                        &schematic,
+                        &feed.routes,
+                    ))
+                }
+                visualize::schematic::SchematicFormat::GeoJson => {
+                    visualize::schematic::schematic_to_geojson(&station_network, &schematic)
+                }
+            };
+            match output {
+                Ok(output) => std::fs::write(args.output, output).expect("Unable to write file"),
+                Err(error) => println!("{}", error),
+            }
+        }
+        Commands::Graph(args) => {
+            let gtfs_path: PathBuf = args.input;
+            let mut gtfs_file = functions::load_gtfs_file(gtfs_path);
+            let feed = feed::Feed::load(&mut gtfs_file);
+
+            let network_graph = network::network_graph(&feed, !args.stops);
+            println!(
+                "{} nodes, {} edges",
+                network_graph.node_count(),
+                network_graph.edge_count()
+            );
+            let output = graph::export_graph(&network_graph, args.format);
+            match output {
+                Ok(output) => std::fs::write(args.output, output).expect("Unable to write file"),
+                Err(error) => println!("{}", error),
+            }
+        }
+        Commands::NetworkStats(args) => {
+            let gtfs_path: PathBuf = args.input;
+            let mut gtfs_file = functions::load_gtfs_file(gtfs_path);
+            let feed = feed::Feed::load(&mut gtfs_file);
+
+            let network_graph = network::network_graph(&feed, !args.stops);
+            let stats = network_stats::station_stats(&network_graph);
+            let components = stats
+                .iter()
+                .map(|stats| stats.component + 1)
+                .max()
+                .unwrap_or(0);
+            let cut_stations = stats
+                .iter()
+                .filter(|stats| stats.articulation_point)
+                .count();
+            println!(
+                "{} components, {} articulation stations",
+                components, cut_stations
+            );
+
+            let output = match args.format {
+                network_stats::NetworkStatsFormat::Csv => Ok(functions::to_csv_string(&stats)),
+                network_stats::NetworkStatsFormat::Json => Ok(functions::to_json_string(&stats)),
+                network_stats::NetworkStatsFormat::GeoJson => {
+                    network_stats::station_stats_geojson(&stats, &network_graph)
+                }
+            };
+            match output {
+                Ok(output) => std::fs::write(args.output, output).expect("Unable to write file"),
+                Err(error) => println!("{}", error),
+            }
+        }
+        Commands::Route(args) => {
+            let gtfs_path: PathBuf = args.input;
+            let mut gtfs_file = functions::load_gtfs_file(gtfs_path);
+            let feed = feed::Feed::load(&mut gtfs_file);
+
+            let timetable = routing::Timetable::new(&feed, args.date, args.walk.settings());
+            let Some(journeys) = routing::plan(
+                &timetable,
+                &args.from,
+                &args.to,
+                args.time.seconds(),
+                args.max_transfers,
+            ) else {
+                println!("Unknown stop in {:?} or {:?}", args.from, args.to);
+                return;
+            };
+            routing::print_journeys(&journeys);
+            if let Some(output) = args.output {
+                std::fs::write(output, functions::to_json_string(&journeys))
+                    .expect("Unable to write file");
+            }
+        }
+        Commands::Isochrone(args) => {
+            let gtfs_path: PathBuf = args.input;
+            let mut gtfs_file = functions::load_gtfs_file(gtfs_path);
+            let feed = feed::Feed::load(&mut gtfs_file);
+
+            let timetable = routing::Timetable::new(&feed, args.date, args.walk.settings());
+            let window = args.window.window(&args.time);
+            let Some(isochrone) = isochrone::isochrone(
+                &timetable,
+                &args.from,
+                window,
+                args.max_transfers,
+                args.percentile,
+            ) else {
+                println!("Unknown stop {:?}", args.from);
+                return;
+            };
+            println!("{} stops reached", isochrone.stops.len());
+            if let Some(stops) = args.stops {
+                match isochrone::reached_stops_geojson(&isochrone) {
+                    Ok(output) => std::fs::write(stops, output).expect("Unable to write file"),
+                    Err(error) => println!("{}", error),
+                }
+            }
+            let bands = isochrone::isochrone_bands(&isochrone, timetable.walk, &args.bands);
+            match isochrone::bands_geojson(bands) {
+                Ok(output) => std::fs::write(args.output, output).expect("Unable to write file"),
+                Err(error) => println!("{}", error),
+            }
+        }
+        Commands::Matrix(args) => {
+            let gtfs_path: PathBuf = args.input;
+            let mut gtfs_file = functions::load_gtfs_file(gtfs_path);
+            let feed = feed::Feed::load(&mut gtfs_file);
+
+            let timetable = routing::Timetable::new(&feed, args.date, args.walk.settings());
+            let locations = |path: Option<PathBuf>| match path {
+                Some(path) => matrix::read_locations(&path),
+                None => Ok(matrix::stop_locations(&timetable)),
+            };
+            let (origins, destinations) =
+                match (locations(args.origins), locations(args.destinations)) {
+                    (Ok(origins), Ok(destinations)) => (origins, destinations),
+                    (Err(error), _) | (_, Err(error)) => {
+                        println!("{}", error);
+                        return;
+                    }
+                };
+            let window = args.window.window(&args.time);
+            let matrix = matrix::travel_time_matrix(
+                &timetable,
+                &origins,
+                &destinations,
+                window,
+                args.max_transfers,
+                &args.percentiles,
+            );
+
+            let output = match args.format {
+                matrix::MatrixFormat::Csv => matrix::matrix_csv(&matrix).into_bytes(),
+                matrix::MatrixFormat::Binary => matrix::matrix_binary(&matrix),
+            };
+            std::fs::write(args.output, output).expect("Unable to write file");
+        }
+        Commands::Transfers(args) => {
+            let gtfs_path: PathBuf = args.input;
+            let mut gtfs_file = functions::load_gtfs_file(gtfs_path);
+            let stops: Vec<gtfs::Stop> = gtfs_file.read_vec();
+            let pathways: Vec<gtfs::Pathway> = gtfs_file.read_optional_vec();
+            let existing: Vec<gtfs::Transfer> = gtfs_file.read_optional_vec();
+
+            let rules =
+                transfers::generate_transfers(&stops, &pathways, &existing, args.walk.settings());
+            println!(
+                "{} transfers kept, {} generated",
+                existing.len(),
+                rules.generated
+            );
+            let output = functions::to_csv_string(&rules.transfers);
+            std::fs::write(args.output, output).expect("Unable to write file");
+        }
+        Commands::Nearby(args) => {
+            let gtfs_path: PathBuf = args.input;
+            let mut gtfs_file = functions::load_gtfs_file(gtfs_path);
+            let stops: Vec<gtfs::Stop> = gtfs_file.read_vec();
+            let shapes: Vec<gtfs::Shape> = if args.shapes {
+                gtfs_file.read_optional_vec()
+            } else {
+                Vec::new()
+            };
+            let index = spatial::SpatialIndex::new(&stops, &shapes);
+
+            let polygon = match args.polygon.map(|path| geodata::read_polygons(&path)) {
+                Some(Ok(polygon)) => Some(polygon),
+                Some(Err(error)) => {
+                    println!("{}", error);
+                    return;
+                }
+                None => None,
+            };
+            let output = match (polygon, args.point, args.shapes) {
+                (Some(polygon), _, false) => nearby_stops(index.stops_within_polygon(&polygon)),
+                (Some(polygon), _, true) => nearby_shapes(index.shapes_within_polygon(&polygon)),
+                (None, Some(point), false) => nearby_stops(match args.radius {
+                    Some(radius) => index.stops_within_distance(point, radius),
+                    None => index.nearest_stops(point, args.count),
+                }),
+                (None, Some(point), true) => nearby_shapes(match args.radius {
+                    Some(radius) => index.shapes_within_distance(point, radius),
+                    None => index.nearest_shapes(point, args.count),
+                }),
+                (None, None, _) => {
+                    println!("Either a point or a polygon is needed");
+                    return;
+                }
+            };
+            if let Some(path) = args.output {
+                std::fs::write(path, output).expect("Unable to write file");
+            }
+        }
+        Commands::EchoRt(args) => {
+            let message = match realtime::read_feed_message(&args.realtime) {
+                Ok(message) => message,
+                Err(error) => {
+                    println!("{}", error);
+                    return;
+                }
+            };
+            if args.json {
+                println!("{}", functions::to_json_string(&message));
+                return;
+            }
+            let gtfs_path: PathBuf = args.input;
+            let mut gtfs_file = functions::load_gtfs_file(gtfs_path);
+            let feed = feed::Feed::load(&mut gtfs_file);
+            print!(
+                "{}",
+                realtime::describe_feed_message(&message, &realtime::StaticIndex::new(&feed))
+            );
+        }
+        Commands::ValidateRt(args) => {
+            let message = match realtime::read_feed_message(&args.realtime) {
+                Ok(message) => message,
+                Err(error) => {
+                    println!("{}", error);
+                    return;
+                }
+            };
+            let gtfs_path: PathBuf = args.input;
+            let mut gtfs_file = functions::load_gtfs_file(gtfs_path);
+            let feed = feed::Feed::load(&mut gtfs_file);
+            let settings = realtime::validate::RealtimeSettings {
+                max_delay_s: args.max_delay * 60,
+                max_early_s: args.max_early * 60,
+                max_age_s: args.max_age,
+                max_shape_distance_m: args.max_shape_distance,
+                now: args.now,
+            };
+            let findings = realtime::validate::validate_feed_message(
+                &message,
+                &realtime::StaticIndex::new(&feed),
+                settings,
+            );
+            validation::print_findings(&findings);
+            if let Some(path) = args.output {
+                let output = functions::to_csv_string(&findings);
+                std::fs::write(path, output).expect("Unable to write file");
+            }
+        }
+        Commands::Realized(args) => {
+            let snapshots = match realtime::read_snapshots(&args.snapshots) {
+                Ok(snapshots) => snapshots,
+                Err(error) => {
+                    println!("{}", error);
+                    return;
+                }
+            };
+            let gtfs_path: PathBuf = args.input;
+            let mut gtfs_file = functions::load_gtfs_file(gtfs_path);
+            let feed = feed::Feed::load(&mut gtfs_file);
+            let trips = realtime::realized::realize_trips(&feed, args.date, &snapshots);
+            let canceled = trips.iter().filter(|trip| trip.canceled).count();
+            println!(
+                "{} snapshots applied to {} trips, {} canceled",
+                snapshots.len(),
+                trips.len(),
+                canceled
+            );
+            let output = functions::to_csv_string(&realtime::realized::realized_stop_times(
+                &trips, args.date,
+            ));
+            std::fs::write(args.output, output).expect("Unable to write file");
+            if let Some(path) = args.feed {
+                let realized = realtime::realized::realized_feed(&feed, args.date, &trips);
+                if let Err(error) = realized.write(&path, &mut gtfs_file) {
+                    println!("{}", error);
+                }
+            }
+        }
+        Commands::Delays(args) => {
+            let observations = match delays::read_observations(&args.observations) {
+                Ok(observations) => observations,
+                Err(error) => {
+                    println!("{}", error);
+                    return;
+                }
+            };
+            let gtfs_path: PathBuf = args.input;
+            let mut gtfs_file = functions::load_gtfs_file(gtfs_path);
+            let feed = feed::Feed::load(&mut gtfs_file);
+            let data = delays::join_observations(&feed, &observations);
+            println!(
+                "{} observations of {} trips, {} not matching the feed",
+                observations.len(),
+                data.trips,
+                data.unmatched
+            );
+
+            let window = delays::OnTimeWindow {
+                early_s: args.early,
+                late_s: args.late,
+            };
+            let punctuality = delays::punctuality(&feed, &data.calls, args.by, window);
+            let output = match args.format {
+                delays::DelaysFormat::Csv => functions::to_csv_string(&punctuality),
+                delays::DelaysFormat::Json => functions::to_json_string(&punctuality),
+            };
+            std::fs::write(args.output, output).expect("Unable to write file");
+            if let Some(path) = args.calls {
+                std::fs::write(path, functions::to_csv_string(&data.calls))
+                    .expect("Unable to write file");
+            }
+        }
+        Commands::Synthetic(args) => {
+            if !(0.0..=1.0).contains(&args.cancel) || !(0.0..=1.0).contains(&args.detour) {
+                println!("Probabilities must be between 0 and 1");
+                return;
+            }
+            let gtfs_path: PathBuf = args.input;
+            let mut gtfs_file = functions::load_gtfs_file(gtfs_path);
+            let feed = feed::Feed::load(&mut gtfs_file);
+            let settings = realtime::synthetic::SyntheticSettings {
+                start: args.start.seconds(),
+                end: args.end.seconds(),
+                interval_s: args.interval,
+                cancel_probability: args.cancel,
+                detour_probability: args.detour,
+                seed: args.seed,
+            };
+            match realtime::synthetic::write_series(&feed, args.date, &settings, &args.output) {
+                Ok(count) => println!("{} snapshots written to {}", count, args.output.display()),
+                Err(error) => println!("{}", error),
+            }
+        }
+        Commands::ServeRt(args) => {
+            if !args.speed.is_finite() || args.speed <= 0.0 {
+                println!("Speed must be a positive number");
+                return;
+            }
+            let replay = match realtime::read_snapshots(&args.snapshots)
+                .and_then(realtime::replay::Replay::new)
+            {
+                Ok(replay) => replay,
+                Err(error) => {
+                    println!("{}", error);
+                    return;
+                }
+            };
+            let clock = realtime::replay::ReplayClock {
+                speed: args.speed,
+                looping: args.looping,
+            };
+            if let Err(error) = realtime::replay::serve(&replay, args.port, clock) {
+                println!("{}", error);
+            }
+        }
+        Commands::Trajectories(args) => {
+            let snapshots = match realtime::read_snapshots(&args.snapshots) {
+                Ok(snapshots) => snapshots,
+                Err(error) => {
+                    println!("{}", error);
+                    return;
+                }
+            };
+            let gtfs_path: PathBuf = args.input;
+            let mut gtfs_file = functions::load_gtfs_file(gtfs_path);
+            let feed = feed::Feed::load(&mut gtfs_file);
+            let points = realtime::trajectories::position_points(&feed, &snapshots);
+            let trajectories = realtime::trajectories::trajectories(&points);
+            println!(
+                "{} positions of {} vehicle trips",
+                points.len(),
+                trajectories.len()
+            );
+            let outputs = [
+                (args.points, functions::to_geojson_string(&points)),
+                (
+                    args.trajectories,
+                    functions::to_geojson_string(&trajectories),
+                ),
+            ];
+            for (path, output) in outputs {
+                match output {
+                    Ok(output) => std::fs::write(path, output).expect("Unable to write file"),
+                    Err(error) => println!("{}", error),
+                }
+            }
+        }
+        Commands::Scenario(args) => {
+            let scenario = match scenario::read_scenario(&args.scenario) {
+                Ok(scenario) => scenario,
+                Err(error) => {
+                    println!("{}", error);
+                    return;
+                }
+            };
+            let gtfs_path: PathBuf = args.input;
+            let mut gtfs_file = functions::load_gtfs_file(gtfs_path);
+            let feed = feed::Feed::load(&mut gtfs_file);
+            let applied = match scenario::apply_scenario(&feed, &scenario) {
+                Ok(applied) => applied,
+                Err(error) => {
+                    println!("{}", error);
+                    return;
+                }
+            };
+            for report in &applied.reports {
+                let trips = &report.trips;
+                println!(
+                    "{}: {} dates, {} trips shortened, {} split, {} canceled on {} routes",
+                    report.id,
+                    trips.dates,
+                    trips.edited,
+                    trips.split,
+                    trips.canceled,
+                    trips.routes.len()
+                );
+            }
+            if let Err(error) = applied.feed.write(&args.output, &mut gtfs_file) {
+                println!("{}", error);
+            }
+            if let Some(path) = args.alerts {
+                let output = if path
+                    .extension()
+                    .is_some_and(|extension| extension == "json")
+                {
+                    functions::to_json_string(&applied.alerts).into_bytes()
+                } else {
+                    prost::Message::encode_to_vec(&applied.alerts)
+                };
+                std::fs::write(path, output).expect("Unable to write file");
+            }
+        }
+        Commands::Hazard(args) => {
+            let to = args.to.unwrap_or(args.date);
+            if to < args.date {
+                println!("The last date is before the first");
+                return;
+            }
+            let polygons = match geodata::read_polygons(&args.polygons) {
+                Ok(polygons) => polygons,
+                Err(error) => {
+                    println!("{}", error);
+                    return;
+                }
+            };
+            let gtfs_path: PathBuf = args.input;
+            let mut gtfs_file = functions::load_gtfs_file(gtfs_path);
+            let feed = feed::Feed::load(&mut gtfs_file);
+            let impact = hazard::hazard_impact(&feed, &polygons, args.date, to);
+            println!(
+                "{} stops flooded, {} trips cut short, {} split and {} canceled on {} routes",
+                impact.flooded_stops,
+                impact.replaced.edited,
+                impact.replaced.split,
+                impact.replaced.canceled,
+                impact.routes.len()
+            );
+            for day in &impact.days {
+                println!(
+                    "{}: {} of {} trips impacted ({}%)",
+                    day.date, day.impacted_trips, day.trips, day.impacted_pct
+                );
+            }
+            let output = match args.format {
+                hazard::HazardFormat::Csv => Ok(functions::to_csv_string(&impact.stops)),
+                hazard::HazardFormat::Json => Ok(functions::to_json_string(&impact.stops)),
+                hazard::HazardFormat::GeoJson => hazard::stop_impacts_geojson(&feed, &impact.stops),
+            };
+            match output {
+                Ok(output) => std::fs::write(args.output, output).expect("Unable to write file"),
+                Err(error) => println!("{}", error),
+            }
+            if let Some(path) = args.routes {
+                std::fs::write(path, functions::to_csv_string(&impact.routes))
+                    .expect("Unable to write file");
+            }
+            if let Some(path) = args.feed {
+                if let Err(error) = impact.reduced.write(&path, &mut gtfs_file) {
+                    println!("{}", error);
+                }
+            }
+        }
+        Commands::Detour(args) => {
+            if args.to < args.from {
+                println!("The last date is before the first");
+                return;
+            }
+            let gtfs_path: PathBuf = args.input;
+            let mut gtfs_file = functions::load_gtfs_file(gtfs_path);
+            let feed = feed::Feed::load(&mut gtfs_file);
+            let replacements =
+                match detour::replacement_stops(&feed, &args.closed, &args.replacements) {
+                    Ok(replacements) => replacements,
+                    Err(error) => {
+                        println!("{}", error);
+                        return;
+                    }
+                };
+            for (closed, replacement) in &replacements {
+                println!("{} -> {}", closed, replacement);
+            }
+            let detour = match detour::detour(
+                &feed,
+                &args.id,
+                &replacements,
+                args.from,
+                args.to,
+                &args.tag,
+            ) {
+                Ok(detour) => detour,
+                Err(error) => {
+                    println!("{}", error);
+                    return;
+                }
+            };
+            println!(
+                "{} trips detoured and {} canceled on {} dates, with {} new shapes",
+                detour.trips.edited, detour.trips.canceled, detour.trips.dates, detour.shapes
+            );
+            if let Err(error) = detour.feed.write(&args.output, &mut gtfs_file) {
+                println!("{}", error);
+            }
+        }
+        Commands::Summary(args) => {
+            let gtfs_path: PathBuf = args.input;
+            let mut gtfs_file = functions::load_gtfs_file(gtfs_path);
+
+            let feed_summary = summary::summarize(&mut gtfs_file);
+            summary::print_summary(&feed_summary);
+            if let Some(output) = args.output {
+                let json = functions::to_json_string(&feed_summary);
+                std::fs::write(output, json).expect("Unable to write file");
             }
         }
     }
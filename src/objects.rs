@@ -1,2 +1,8 @@
+pub mod block;
+pub mod feed;
+pub mod network;
+pub mod pattern;
+pub mod route;
+pub mod spatial;
 pub mod stop;
-pub mod route;
\ No newline at end of file
+pub mod trip;
@@ -0,0 +1,293 @@
+use std::collections::{BTreeMap, HashMap};
+#[cfg(test)]
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::functions::geodata;
+#[cfg(test)]
+use crate::functions::gtfs;
+use crate::functions::gtfs::{Route, Time};
+
+use super::feed::Feed;
+use super::trip::TripInstance;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum BlockFormat {
+    // One row per link between consecutive trips of a block
+    Csv,
+    Json,
+    // Gantt chart of the blocks
+    Svg,
+}
+
+// A trip as run by the vehicle of a block
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockTrip {
+    pub trip_id: String,
+    pub route_id: String,
+    pub start: u64,
+    pub end: u64,
+    pub first_stop_id: String,
+    pub last_stop_id: String,
+}
+
+// All trips of one block on a service day, ordered by start time
+#[derive(Debug, Clone, Serialize)]
+pub struct Block {
+    pub block_id: String,
+    pub trips: Vec<BlockTrip>,
+}
+
+// One row of the block diagram, describing a trip and the link from the previous trip
+#[derive(Debug, Serialize, PartialEq)]
+pub struct BlockLink {
+    pub block_id: String,
+    pub sequence: usize,
+    pub trip_id: String,
+    pub route_id: String,
+    pub start_time: String,
+    pub end_time: String,
+    pub first_stop_id: String,
+    pub last_stop_id: String,
+    pub layover_min: Option<f64>,
+    pub deadhead_m: Option<f64>,
+    pub overlaps_previous: bool,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct VehicleRequirement {
+    pub level: String,
+    pub id: String,
+    pub peak_vehicles: usize,
+    pub peak_time: Option<String>,
+}
+
+impl BlockTrip {
+    fn from_instance(instance: &TripInstance) -> BlockTrip {
+        BlockTrip {
+            trip_id: instance.trip.trip_id.to_owned(),
+            route_id: instance.trip.route_id.to_owned(),
+            start: instance.start_time(),
+            end: instance.end_time(),
+            first_stop_id: instance
+                .stops
+                .first()
+                .map(|stop| stop.stop_id.to_owned())
+                .unwrap_or_default(),
+            last_stop_id: instance
+                .stops
+                .last()
+                .map(|stop| stop.stop_id.to_owned())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+pub fn blocks_on_date(feed: &Feed, date: NaiveDate) -> (Vec<Block>, Vec<BlockTrip>) {
+    let instances = feed.trip_instances(&feed.active_services(date));
+    build_blocks(&instances)
+}
+
+// Chains the trip instances of a day into blocks. Trips without a block_id are returned apart.
+pub fn build_blocks(instances: &[TripInstance]) -> (Vec<Block>, Vec<BlockTrip>) {
+    let mut blocks: BTreeMap<String, Vec<BlockTrip>> = BTreeMap::new();
+    let mut unblocked: Vec<BlockTrip> = Vec::new();
+    for instance in instances {
+        match &instance.trip.block_id {
+            Some(block_id) if !block_id.is_empty() => blocks
+                .entry(block_id.to_owned())
+                .or_default()
+                .push(BlockTrip::from_instance(instance)),
+            _ => unblocked.push(BlockTrip::from_instance(instance)),
+        }
+    }
+
+    let blocks = blocks
+        .into_iter()
+        .map(|(block_id, mut trips)| {
+            trips.sort_by_key(|trip| (trip.start, trip.end));
+            Block { block_id, trips }
+        })
+        .collect();
+    (blocks, unblocked)
+}
+
+pub fn block_links(blocks: &[Block], feed: &Feed) -> Vec<BlockLink> {
+    let stop_points: HashMap<&str, geo_types::Point> = feed
+        .stops
+        .iter()
+        .filter_map(|stop| Some((stop.stop_id.as_str(), geodata::stop_point(stop)?)))
+        .collect();
+
+    let mut links: Vec<BlockLink> = Vec::new();
+    for block in blocks {
+        for (index, trip) in block.trips.iter().enumerate() {
+            let previous = index.checked_sub(1).map(|previous| &block.trips[previous]);
+            let layover = previous.map(|previous| trip.start as i64 - previous.end as i64);
+            let deadhead = previous.and_then(|previous| {
+                if previous.last_stop_id == trip.first_stop_id {
+                    return Some(0.0);
+                }
+                let from = stop_points.get(previous.last_stop_id.as_str())?;
+                let to = stop_points.get(trip.first_stop_id.as_str())?;
+                Some(geodata::haversine_distance(*from, *to).round())
+            });
+
+            links.push(BlockLink {
+                block_id: block.block_id.to_owned(),
+                sequence: index + 1,
+                trip_id: trip.trip_id.to_owned(),
+                route_id: trip.route_id.to_owned(),
+                start_time: Time::from_seconds(trip.start).to_string(),
+                end_time: Time::from_seconds(trip.end).to_string(),
+                first_stop_id: trip.first_stop_id.to_owned(),
+                last_stop_id: trip.last_stop_id.to_owned(),
+                layover_min: layover.map(|seconds| seconds as f64 / 60.0),
+                deadhead_m: deadhead,
+                overlaps_previous: layover.is_some_and(|seconds| seconds < 0),
+            });
+        }
+    }
+
+    links
+}
+
+// Peak number of vehicles in service at once. A vehicle stays committed to its block through
+// layovers, and to a route while its next trip in the block is on the same route.
+pub fn vehicle_requirements(
+    blocks: &[Block],
+    unblocked: &[BlockTrip],
+    feed: &Feed,
+) -> Vec<VehicleRequirement> {
+    let routes: HashMap<&str, &Route> = feed
+        .routes
+        .iter()
+        .map(|route| (route.route_id.as_str(), route))
+        .collect();
+    let agency_of = |route_id: &str| match routes.get(route_id) {
+        Some(route) => feed.route_agency(route),
+        None => String::new(),
+    };
+
+    let mut route_intervals: BTreeMap<String, Vec<(u64, u64)>> = BTreeMap::new();
+    let mut agency_intervals: BTreeMap<String, Vec<(u64, u64)>> = BTreeMap::new();
+    for block in blocks {
+        let (Some(first), Some(last)) = (block.trips.first(), block.trips.last()) else {
+            continue;
+        };
+        let end = block
+            .trips
+            .iter()
+            .map(|trip| trip.end)
+            .max()
+            .unwrap_or(last.end);
+        agency_intervals
+            .entry(agency_of(&first.route_id))
+            .or_default()
+            .push((first.start, end));
+
+        for (index, trip) in block.trips.iter().enumerate() {
+            let end = match block.trips.get(index + 1) {
+                Some(next) if next.route_id == trip.route_id => next.start.max(trip.end),
+                _ => trip.end,
+            };
+            route_intervals
+                .entry(trip.route_id.to_owned())
+                .or_default()
+                .push((trip.start, end));
+        }
+    }
+    for trip in unblocked {
+        route_intervals
+            .entry(trip.route_id.to_owned())
+            .or_default()
+            .push((trip.start, trip.end));
+        agency_intervals
+            .entry(agency_of(&trip.route_id))
+            .or_default()
+            .push((trip.start, trip.end));
+    }
+
+    let mut requirements: Vec<VehicleRequirement> = Vec::new();
+    for (level, intervals) in [("agency", agency_intervals), ("route", route_intervals)] {
+        for (id, intervals) in intervals {
+            let (peak_vehicles, peak_time) = peak_overlap(&intervals);
+            requirements.push(VehicleRequirement {
+                level: level.to_owned(),
+                id,
+                peak_vehicles,
+                peak_time: peak_time.map(|time| Time::from_seconds(time).to_string()),
+            });
+        }
+    }
+    requirements
+}
+
+// Maximum number of overlapping intervals, and when it is first reached.
+// An interval ending at the same time another starts can be run by the same vehicle.
+fn peak_overlap(intervals: &[(u64, u64)]) -> (usize, Option<u64>) {
+    let mut events: Vec<(u64, i64)> = Vec::new();
+    for (start, end) in intervals {
+        events.push((*start, 1));
+        events.push((*end, -1));
+    }
+    events.sort();
+
+    let mut current: i64 = 0;
+    let mut peak: (usize, Option<u64>) = (0, None);
+    for (time, change) in events {
+        current += change;
+        if current as usize > peak.0 {
+            peak = (current as usize, Some(time));
+        }
+    }
+    peak
+}
+
+pub fn print_vehicle_requirements(requirements: &[VehicleRequirement]) {
+    for requirement in requirements {
+        println!(
+            "{} {}: peak of {} vehicles at {}",
+            requirement.level,
+            requirement.id,
+            requirement.peak_vehicles,
+            requirement.peak_time.as_deref().unwrap_or("-")
+        );
+    }
+}
+
+#[test]
+fn test_block_links() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let feed = Feed::load(&mut gtfs_file);
+    let date = NaiveDate::from_ymd_opt(2007, 6, 5).unwrap();
+
+    let (blocks, unblocked) = blocks_on_date(&feed, date);
+    let links = block_links(&blocks, &feed);
+
+    // Block 1 runs AB1 to Bullfrog and continues on BFC1 ten minutes after arriving
+    let block: Vec<&BlockLink> = links.iter().filter(|link| link.block_id == "1").collect();
+    assert_eq!(block.len(), 2);
+    assert_eq!(block[0].trip_id, "AB1");
+    assert_eq!(block[1].trip_id, "BFC1");
+    assert_eq!(block[1].layover_min, Some(10.0));
+    assert_eq!(block[1].deadhead_m, Some(0.0));
+    assert!(!block[1].overlaps_previous);
+
+    let requirements = vehicle_requirements(&blocks, &unblocked, &feed);
+    let airport_bullfrog = requirements
+        .iter()
+        .find(|requirement| requirement.level == "route" && requirement.id == "AB")
+        .unwrap();
+    assert_eq!(airport_bullfrog.peak_vehicles, 1);
+}
+
+#[test]
+fn test_peak_overlap() {
+    let intervals = [(0, 10), (5, 15), (10, 20), (12, 14)];
+    assert_eq!(peak_overlap(&intervals), (3, Some(12)));
+}
@@ -0,0 +1,96 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+
+use crate::functions::calendar;
+use crate::functions::gtfs::{
+    self, Agency, Calendar, CalendarDate, Frequency, Route, Shape, Stop, StopTime, Transfer, Trip,
+};
+
+use super::pattern::{self, StopPattern};
+use super::trip::{self, TripInstance};
+
+// The core tables of a feed, read once for analyses that join most of them
+#[derive(Clone)]
+pub struct Feed {
+    pub agencies: Vec<Agency>,
+    pub stops: Vec<Stop>,
+    pub routes: Vec<Route>,
+    pub trips: Vec<Trip>,
+    pub stop_times: Vec<StopTime>,
+    pub calendars: Vec<Calendar>,
+    pub calendar_dates: Vec<CalendarDate>,
+    pub frequencies: Vec<Frequency>,
+    pub shapes: Vec<Shape>,
+    pub transfers: Vec<Transfer>,
+}
+
+impl Feed {
+    pub fn load(gtfs_file: &mut gtfs::GtfsFile) -> Feed {
+        Feed {
+            agencies: gtfs_file.read_vec(),
+            stops: gtfs_file.read_vec(),
+            routes: gtfs_file.read_vec(),
+            trips: gtfs_file.read_vec(),
+            stop_times: gtfs_file.read_vec(),
+            calendars: gtfs_file.read_optional_vec(),
+            calendar_dates: gtfs_file.read_optional_vec(),
+            frequencies: gtfs_file.read_optional_vec(),
+            shapes: gtfs_file.read_optional_vec(),
+            transfers: gtfs_file.read_optional_vec(),
+        }
+    }
+
+    // Writes the tables into a new archive, with the other files of the source copied unchanged
+    pub fn write(&self, path: &Path, source: &mut gtfs::GtfsFile) -> Result<(), String> {
+        let mut writer = gtfs::GtfsWriter::new(path)?;
+        writer.write_vec(&self.agencies)?;
+        writer.write_vec(&self.stops)?;
+        writer.write_vec(&self.routes)?;
+        writer.write_vec(&self.trips)?;
+        writer.write_vec(&self.stop_times)?;
+        writer.write_vec(&self.calendars)?;
+        writer.write_vec(&self.calendar_dates)?;
+        writer.write_vec(&self.frequencies)?;
+        writer.write_vec(&self.shapes)?;
+        writer.write_vec(&self.transfers)?;
+        writer.copy_other_files(source)?;
+        writer.finish()
+    }
+
+    pub fn active_services(&self, date: NaiveDate) -> HashSet<String> {
+        calendar::active_services(&self.calendars, &self.calendar_dates, date)
+    }
+
+    pub fn trip_instances(&self, services: &HashSet<String>) -> Vec<TripInstance> {
+        trip::expand_trips(&self.trips, &self.stop_times, &self.frequencies, services)
+    }
+
+    pub fn stop_patterns(&self) -> Vec<StopPattern> {
+        pattern::build_patterns(&self.routes, &self.trips, &self.stop_times, &self.stops)
+    }
+
+    // Of the first agency, as all agencies of a feed share one
+    pub fn timezone(&self) -> Tz {
+        self.agencies
+            .first()
+            .and_then(|agency| agency.agency_timezone.parse().ok())
+            .unwrap_or(Tz::UTC)
+    }
+
+    // The agency operating a route, falling back to the only agency of single agency feeds
+    pub fn route_agency(&self, route: &Route) -> String {
+        if let Some(agency_id) = &route.agency_id {
+            return agency_id.to_owned();
+        }
+        match self.agencies.first() {
+            Some(agency) => agency
+                .agency_id
+                .clone()
+                .unwrap_or(agency.agency_name.to_owned()),
+            None => String::new(),
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+#[cfg(test)]
+use std::path::PathBuf;
+
+use crate::functions::geodata;
+#[cfg(test)]
+use crate::functions::gtfs;
+use crate::functions::gtfs::{RouteNetwork, Stop};
+
+use petgraph::graph::{DiGraph, NodeIndex};
+
+use super::feed::Feed;
+
+// Which routes of a feed an analysis looks at
+pub enum Scope {
+    Feed,
+    Routes(Vec<String>),
+    Agency(String),
+    // A fares v2 network, from routes.txt network_id or route_networks.txt
+    Network(String),
+}
+
+impl Scope {
+    pub fn route_ids(&self, feed: &Feed, route_networks: &[RouteNetwork]) -> HashSet<String> {
+        let routes = feed.routes.iter();
+        match self {
+            Scope::Feed => routes.map(|route| route.route_id.to_owned()).collect(),
+            Scope::Routes(route_ids) => routes
+                .filter(|route| route_ids.contains(&route.route_id))
+                .map(|route| route.route_id.to_owned())
+                .collect(),
+            Scope::Agency(agency_id) => routes
+                .filter(|route| &feed.route_agency(route) == agency_id)
+                .map(|route| route.route_id.to_owned())
+                .collect(),
+            Scope::Network(network_id) => routes
+                .filter(|route| {
+                    route.network_id.as_ref() == Some(network_id)
+                        || route_networks.iter().any(|route_network| {
+                            &route_network.network_id == network_id
+                                && route_network.route_id == route.route_id
+                        })
+                })
+                .map(|route| route.route_id.to_owned())
+                .collect(),
+        }
+    }
+}
+
+// A stop, or the parent station its platforms are collapsed into
+#[derive(Debug, Clone)]
+pub struct Station {
+    pub station_id: String,
+    pub name: Option<String>,
+    pub point: Option<geo_types::Point>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Link {
+    pub trip_count: usize,
+    pub route_ids: BTreeSet<String>,
+}
+
+// Undirected network of stations linked by consecutive stops of stop patterns
+pub struct StationNetwork {
+    pub stations: BTreeMap<String, Station>,
+    // Keyed by the ordered pair of station ids
+    pub links: BTreeMap<(String, String), Link>,
+}
+
+// The parent station of a stop, or the stop itself
+pub fn station_id(stops: &HashMap<&str, &Stop>, stop_id: &str) -> String {
+    match stops
+        .get(stop_id)
+        .and_then(|stop| stop.parent_station.as_ref())
+    {
+        Some(parent) if !parent.is_empty() => parent.to_owned(),
+        _ => stop_id.to_owned(),
+    }
+}
+
+pub fn station_network(feed: &Feed, route_ids: &HashSet<String>) -> StationNetwork {
+    let stops: HashMap<&str, &Stop> = feed
+        .stops
+        .iter()
+        .map(|stop| (stop.stop_id.as_str(), stop))
+        .collect();
+
+    let mut links: BTreeMap<(String, String), Link> = BTreeMap::new();
+    let mut members: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for pattern in feed.stop_patterns() {
+        if !route_ids.contains(&pattern.route_id) {
+            continue;
+        }
+        let mut sequence: Vec<String> = Vec::new();
+        for stop_id in &pattern.stop_ids {
+            let station = station_id(&stops, stop_id);
+            members
+                .entry(station.to_owned())
+                .or_default()
+                .push(stop_id.to_owned());
+            sequence.push(station);
+        }
+        sequence.dedup();
+
+        for pair in sequence.windows(2) {
+            let key = if pair[0] < pair[1] {
+                (pair[0].to_owned(), pair[1].to_owned())
+            } else {
+                (pair[1].to_owned(), pair[0].to_owned())
+            };
+            let link = links.entry(key).or_default();
+            link.trip_count += pattern.trip_count;
+            link.route_ids.insert(pattern.route_id.to_owned());
+        }
+    }
+
+    let stations = members
+        .into_iter()
+        .map(|(station_id, stop_ids)| {
+            let station = stops.get(station_id.as_str());
+            // Stations without coordinates are placed at the centre of their stops
+            let point = station
+                .and_then(|station| geodata::stop_point(station))
+                .or_else(|| {
+                    let points: Vec<geo_types::Point> = stop_ids
+                        .iter()
+                        .filter_map(|stop_id| geodata::stop_point(stops.get(stop_id.as_str())?))
+                        .collect();
+                    (!points.is_empty()).then(|| {
+                        let count = points.len() as f64;
+                        geo_types::Point::new(
+                            points.iter().map(|point| point.x()).sum::<f64>() / count,
+                            points.iter().map(|point| point.y()).sum::<f64>() / count,
+                        )
+                    })
+                });
+            let name = station.and_then(|station| station.stop_name.clone());
+            (
+                station_id.to_owned(),
+                Station {
+                    station_id,
+                    name,
+                    point,
+                },
+            )
+        })
+        .collect();
+
+    StationNetwork { stations, links }
+}
+
+// Directed link between consecutive stops of trip instances
+#[derive(Debug, Clone, Default)]
+pub struct Edge {
+    pub trip_count: usize,
+    pub route_ids: BTreeSet<String>,
+    // Seconds from departure at the first stop to arrival at the second
+    pub min_run_time: u64,
+    pub total_run_time: u64,
+    // Straight line distance in metres, where both stops have coordinates
+    pub distance_m: Option<f64>,
+}
+
+impl Edge {
+    pub fn avg_run_time(&self) -> f64 {
+        self.total_run_time as f64 / self.trip_count.max(1) as f64
+    }
+}
+
+// Directed graph of the whole feed, with every trip of the timetable counted once. Nodes are
+// stops, or their parent stations when `collapse_stations` is set.
+pub fn network_graph(feed: &Feed, collapse_stations: bool) -> DiGraph<Station, Edge> {
+    let stops: HashMap<&str, &Stop> = feed
+        .stops
+        .iter()
+        .map(|stop| (stop.stop_id.as_str(), stop))
+        .collect();
+    let node_id = |stop_id: &str| {
+        if collapse_stations {
+            station_id(&stops, stop_id)
+        } else {
+            stop_id.to_owned()
+        }
+    };
+
+    let mut graph: DiGraph<Station, Edge> = DiGraph::new();
+    let mut nodes: HashMap<String, NodeIndex> = HashMap::new();
+    let mut node_index = |graph: &mut DiGraph<Station, Edge>, id: String| {
+        *nodes.entry(id.to_owned()).or_insert_with(|| {
+            let stop = stops.get(id.as_str());
+            graph.add_node(Station {
+                station_id: id,
+                name: stop.and_then(|stop| stop.stop_name.clone()),
+                point: stop.and_then(|stop| geodata::stop_point(stop)),
+            })
+        })
+    };
+
+    let all_services: HashSet<String> = feed
+        .trips
+        .iter()
+        .map(|trip| trip.service_id.to_owned())
+        .collect();
+    let mut edges: HashMap<(NodeIndex, NodeIndex), Edge> = HashMap::new();
+    for instance in feed.trip_instances(&all_services) {
+        for pair in instance.stops.windows(2) {
+            let from = node_index(&mut graph, node_id(&pair[0].stop_id));
+            let to = node_index(&mut graph, node_id(&pair[1].stop_id));
+            if from == to {
+                continue;
+            }
+            let run_time = pair[1].arrival.saturating_sub(pair[0].departure);
+            let edge = edges.entry((from, to)).or_insert_with(|| Edge {
+                min_run_time: run_time,
+                ..Edge::default()
+            });
+            edge.trip_count += 1;
+            edge.route_ids.insert(instance.trip.route_id.to_owned());
+            edge.min_run_time = edge.min_run_time.min(run_time);
+            edge.total_run_time += run_time;
+        }
+    }
+
+    let mut edges: Vec<((NodeIndex, NodeIndex), Edge)> = edges.into_iter().collect();
+    edges.sort_by_key(|((from, to), _)| (*from, *to));
+    for ((from, to), mut edge) in edges {
+        if let (Some(a), Some(b)) = (graph[from].point, graph[to].point) {
+            edge.distance_m = Some(geodata::haversine_distance(a, b).round());
+        }
+        graph.add_edge(from, to, edge);
+    }
+    graph
+}
+
+#[test]
+fn test_station_network() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let feed = Feed::load(&mut gtfs_file);
+
+    let network = station_network(&feed, &Scope::Feed.route_ids(&feed, &[]));
+    assert_eq!(network.stations.len(), 9);
+    assert_eq!(network.links.len(), 8);
+    let airport = &network.links[&(String::from("BEATTY_AIRPORT"), String::from("STAGECOACH"))];
+    assert_eq!(airport.route_ids.iter().collect::<Vec<_>>(), vec!["STBA"]);
+
+    let city = Scope::Routes(vec![String::from("CITY")]).route_ids(&feed, &[]);
+    assert_eq!(station_network(&feed, &city).links.len(), 4);
+    assert_eq!(
+        Scope::Agency(String::from("DTA"))
+            .route_ids(&feed, &[])
+            .len(),
+        5
+    );
+}
+
+#[test]
+fn test_network_graph() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let feed = Feed::load(&mut gtfs_file);
+
+    let graph = network_graph(&feed, true);
+    assert_eq!(graph.node_count(), 9);
+    // Every link but the shuttle from Stagecoach to the airport is run in both directions
+    assert_eq!(graph.edge_count(), 15);
+
+    let index = |id: &str| {
+        graph
+            .node_indices()
+            .find(|node| graph[*node].station_id == id)
+            .unwrap()
+    };
+    let edge = graph
+        .find_edge(index("BEATTY_AIRPORT"), index("BULLFROG"))
+        .unwrap();
+    let edge = &graph[edge];
+    assert_eq!(edge.route_ids.iter().collect::<Vec<_>>(), vec!["AB"]);
+    assert_eq!(edge.min_run_time, 10 * 60);
+    assert!(edge.distance_m.is_some());
+}
@@ -0,0 +1,315 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+#[cfg(test)]
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::functions::gtfs::{self, Direction, Route, Stop, StopTime, Trip};
+
+use super::trip::stop_times_by_trip;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum PatternFormat {
+    Csv,
+    Json,
+}
+
+// A stop pattern is the ordered list of stops shared by one or more trips of a route
+#[derive(Debug, Clone, Serialize)]
+pub struct StopPattern {
+    pub pattern_id: String,
+    pub route_id: String,
+    pub direction_id: Option<Direction>,
+    pub label: String,
+    pub trip_count: usize,
+    pub shape_id: Option<String>,
+    pub first_stop_name: Option<String>,
+    pub last_stop_name: Option<String>,
+    pub stop_ids: Vec<String>,
+    pub trip_ids: Vec<String>,
+}
+
+// Flat version of a pattern, as csv can't serialize the stop and trip lists
+#[derive(Debug, Serialize)]
+pub struct PatternRecord {
+    pub pattern_id: String,
+    pub route_id: String,
+    pub direction_id: Option<Direction>,
+    pub label: String,
+    pub trip_count: usize,
+    pub stop_count: usize,
+    pub shape_id: Option<String>,
+    pub first_stop_name: Option<String>,
+    pub last_stop_name: Option<String>,
+    pub stop_ids: String,
+}
+
+impl PatternRecord {
+    pub fn from_pattern(pattern: &StopPattern) -> PatternRecord {
+        PatternRecord {
+            pattern_id: pattern.pattern_id.to_owned(),
+            route_id: pattern.route_id.to_owned(),
+            direction_id: pattern.direction_id,
+            label: pattern.label.to_owned(),
+            trip_count: pattern.trip_count,
+            stop_count: pattern.stop_ids.len(),
+            shape_id: pattern.shape_id.clone(),
+            first_stop_name: pattern.first_stop_name.clone(),
+            last_stop_name: pattern.last_stop_name.clone(),
+            stop_ids: pattern.stop_ids.join(";"),
+        }
+    }
+}
+
+pub fn extract_patterns(gtfs_file: &mut gtfs::GtfsFile) -> Vec<StopPattern> {
+    let routes: Vec<Route> = gtfs_file.read_vec();
+    let trips: Vec<Trip> = gtfs_file.read_vec();
+    let stop_times: Vec<StopTime> = gtfs_file.read_vec();
+    let stops: Vec<Stop> = gtfs_file.read_vec();
+
+    build_patterns(&routes, &trips, &stop_times, &stops)
+}
+
+pub fn build_patterns(
+    routes: &[Route],
+    trips: &[Trip],
+    stop_times: &[StopTime],
+    stops: &[Stop],
+) -> Vec<StopPattern> {
+    let stop_names: HashMap<&str, &str> = stops
+        .iter()
+        .filter_map(|stop| Some((stop.stop_id.as_str(), stop.stop_name.as_deref()?)))
+        .collect();
+    let timetable = stop_times_by_trip(stop_times);
+
+    let mut route_trips: HashMap<&str, Vec<&Trip>> = HashMap::new();
+    for trip in trips {
+        route_trips
+            .entry(trip.route_id.as_str())
+            .or_default()
+            .push(trip);
+    }
+
+    let mut patterns: Vec<StopPattern> = Vec::new();
+    for route in routes {
+        let Some(trips) = route_trips.get(route.route_id.as_str()) else {
+            continue;
+        };
+
+        // Trips with the same direction and the same stop sequence form one pattern
+        let mut groups: Vec<(Option<Direction>, Vec<String>, Vec<&Trip>)> = Vec::new();
+        for trip in trips {
+            let Some(trip_stop_times) = timetable.get(&trip.trip_id) else {
+                continue;
+            };
+            let stop_ids: Vec<String> = trip_stop_times
+                .iter()
+                .filter_map(|stop_time| stop_time.stop_id.clone())
+                .collect();
+            match groups
+                .iter_mut()
+                .find(|group| group.0 == trip.direction_id && group.1 == stop_ids)
+            {
+                Some(group) => group.2.push(trip),
+                None => groups.push((trip.direction_id, stop_ids, vec![trip])),
+            }
+        }
+
+        groups.sort_by(|a, b| {
+            direction_order(a.0)
+                .cmp(&direction_order(b.0))
+                .then(b.2.len().cmp(&a.2.len()))
+                .then(a.1.cmp(&b.1))
+        });
+
+        let mut route_patterns: Vec<StopPattern> = Vec::new();
+        for (index, (direction_id, stop_ids, group_trips)) in groups.into_iter().enumerate() {
+            let first_stop_name = stop_ids
+                .first()
+                .and_then(|id| stop_names.get(id.as_str()))
+                .map(|name| name.to_string());
+            let last_stop_name = stop_ids
+                .last()
+                .and_then(|id| stop_names.get(id.as_str()))
+                .map(|name| name.to_string());
+            let label = format!(
+                "{}: {} → {}",
+                route_name(route),
+                first_stop_name
+                    .as_deref()
+                    .unwrap_or(stop_ids.first().map_or("", |id| id)),
+                last_stop_name
+                    .as_deref()
+                    .unwrap_or(stop_ids.last().map_or("", |id| id)),
+            );
+
+            route_patterns.push(StopPattern {
+                pattern_id: format!("{}:{}", route.route_id, index + 1),
+                route_id: route.route_id.to_owned(),
+                direction_id,
+                label,
+                trip_count: group_trips.len(),
+                shape_id: representative_shape(&group_trips),
+                first_stop_name,
+                last_stop_name,
+                stop_ids,
+                trip_ids: group_trips
+                    .iter()
+                    .map(|trip| trip.trip_id.to_owned())
+                    .collect(),
+            });
+        }
+
+        disambiguate_labels(&mut route_patterns, &stop_names);
+        patterns.append(&mut route_patterns);
+    }
+
+    patterns
+}
+
+pub fn route_name(route: &Route) -> &str {
+    if let Some(name) = &route.route_short_name {
+        if !name.is_empty() {
+            return name;
+        }
+    }
+    if let Some(name) = &route.route_long_name {
+        if !name.is_empty() {
+            return name;
+        }
+    }
+    &route.route_id
+}
+
+fn direction_order(direction: Option<Direction>) -> u8 {
+    match direction {
+        Some(direction) => direction as u8,
+        None => u8::MAX,
+    }
+}
+
+// The most common shape among the trips of a pattern
+fn representative_shape(trips: &[&Trip]) -> Option<String> {
+    let mut shape_counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for trip in trips {
+        if let Some(shape_id) = &trip.shape_id {
+            *shape_counts.entry(shape_id.as_str()).or_default() += 1;
+        }
+    }
+
+    let mut representative: Option<(&str, usize)> = None;
+    for (shape_id, count) in shape_counts {
+        match representative {
+            Some((_, best)) if best >= count => {}
+            _ => representative = Some((shape_id, count)),
+        }
+    }
+    representative.map(|(shape_id, _)| shape_id.to_owned())
+}
+
+// Patterns between the same terminals get a "via" stop that only they serve
+fn disambiguate_labels(patterns: &mut [StopPattern], stop_names: &HashMap<&str, &str>) {
+    let mut suffixes: Vec<Option<String>> = vec![None; patterns.len()];
+    for (index, pattern) in patterns.iter().enumerate() {
+        let siblings: Vec<&StopPattern> = patterns
+            .iter()
+            .enumerate()
+            .filter(|(other, sibling)| *other != index && sibling.label == pattern.label)
+            .map(|(_, sibling)| sibling)
+            .collect();
+        if siblings.is_empty() {
+            continue;
+        }
+
+        let sibling_stops: HashSet<&String> = siblings
+            .iter()
+            .flat_map(|sibling| sibling.stop_ids.iter())
+            .collect();
+        let suffix = match pattern
+            .stop_ids
+            .iter()
+            .find(|id| !sibling_stops.contains(id))
+        {
+            Some(id) => format!(
+                " via {}",
+                stop_names.get(id.as_str()).unwrap_or(&id.as_str())
+            ),
+            None => format!(" ({} stops)", pattern.stop_ids.len()),
+        };
+        suffixes[index] = Some(suffix);
+    }
+
+    for (pattern, suffix) in patterns.iter_mut().zip(suffixes) {
+        if let Some(suffix) = suffix {
+            pattern.label.push_str(&suffix);
+        }
+    }
+
+    // Last resort for patterns that still can't be told apart
+    let mut label_counts: HashMap<String, usize> = HashMap::new();
+    for pattern in patterns.iter() {
+        *label_counts.entry(pattern.label.to_owned()).or_default() += 1;
+    }
+    for pattern in patterns.iter_mut() {
+        if label_counts[&pattern.label] > 1 {
+            pattern.label = format!("{} [{}]", pattern.label, pattern.pattern_id);
+        }
+    }
+}
+
+#[test]
+fn test_build_patterns() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let patterns = extract_patterns(&mut gtfs_file);
+
+    let amargosa: Vec<&StopPattern> = patterns
+        .iter()
+        .filter(|pattern| pattern.route_id == "AAMV")
+        .collect();
+    assert_eq!(amargosa.len(), 2);
+    assert_eq!(amargosa[0].pattern_id, "AAMV:1");
+    assert_eq!(amargosa[0].direction_id, Some(Direction::Outbound));
+    assert_eq!(amargosa[0].trip_ids, vec!["AAMV1", "AAMV3"]);
+    assert_eq!(amargosa[0].stop_ids, vec!["BEATTY_AIRPORT", "AMV"]);
+    assert_eq!(
+        amargosa[0].label,
+        "50: Nye County Airport (Demo) → Amargosa Valley (Demo)"
+    );
+    assert_eq!(amargosa[1].direction_id, Some(Direction::Inbound));
+
+    let shuttle: Vec<&StopPattern> = patterns
+        .iter()
+        .filter(|pattern| pattern.route_id == "STBA")
+        .collect();
+    assert_eq!(shuttle.len(), 1);
+    assert_eq!(shuttle[0].direction_id, None);
+    assert_eq!(patterns.len(), 9);
+}
+
+#[test]
+fn test_disambiguate_labels() {
+    let pattern = |id: &str, stops: &[&str]| StopPattern {
+        pattern_id: id.to_owned(),
+        route_id: String::from("R"),
+        direction_id: None,
+        label: String::from("R: A → C"),
+        trip_count: 1,
+        shape_id: None,
+        first_stop_name: Some(String::from("A")),
+        last_stop_name: Some(String::from("C")),
+        stop_ids: stops.iter().map(|stop| stop.to_string()).collect(),
+        trip_ids: Vec::new(),
+    };
+    let mut patterns = vec![
+        pattern("R:1", &["A", "B", "C"]),
+        pattern("R:2", &["A", "D", "C"]),
+    ];
+    let stop_names: HashMap<&str, &str> = HashMap::from([("B", "Bee"), ("D", "Dee")]);
+
+    disambiguate_labels(&mut patterns, &stop_names);
+
+    assert_eq!(patterns[0].label, "R: A → C via Bee");
+    assert_eq!(patterns[1].label, "R: A → C via Dee");
+}
@@ -2,6 +2,8 @@ use std::collections::BTreeMap;
 
 use crate::functions::gtfs::{self, Route, Stop, StopTime, Trip};
 
+use super::pattern::{self, StopPattern};
+
 #[derive(Clone)]
 pub struct FullRoute {
     pub route_description: Route,
@@ -21,7 +23,6 @@ impl FullRoute {
     //             stops.push(parent_stop.clone());
     //         }
     //     }
-        
 
     //     FullRoute {
     //         route_description: self.route_description,
@@ -37,15 +38,24 @@ impl FullRoute {
         let mut parents: Vec<Stop> = Vec::new();
         for stop in &self.associated_stops {
             if let Some(parent_station) = &stop.parent_station {
-                if let Some(parent) = stops_map.remove(parent_station){
+                if let Some(parent) = stops_map.remove(parent_station) {
                     parents.push(parent);
                 }
             }
-        }       
+        }
 
         parents
     }
 
+    pub fn stop_patterns(&self) -> Vec<StopPattern> {
+        pattern::build_patterns(
+            std::slice::from_ref(&self.route_description),
+            &self.trips,
+            &self.associated_stop_times,
+            &self.associated_stops,
+        )
+    }
+
     pub fn all_stops(&self, gtfs_file: &mut gtfs::GtfsFile) -> Vec<Stop> {
         let mut parents = self.parent_stops(gtfs_file);
         for stop in &self.associated_stops {
@@ -55,7 +65,7 @@ impl FullRoute {
     }
 }
 
-pub fn print_route_details(gtfs_file: &mut gtfs::GtfsFile, route_id:String) {
+pub fn print_route_details(gtfs_file: &mut gtfs::GtfsFile, route_id: String) {
     let route = extract_route_info(gtfs_file, &route_id);
     match route {
         None => println!("Stop id {} not found", route_id),
@@ -63,7 +73,17 @@ pub fn print_route_details(gtfs_file: &mut gtfs::GtfsFile, route_id:String) {
             println!("{:?}", route.route_description);
             println!("{:?}", route.trips);
             println!("{:?}", route.associated_stop_times);
-            println!("{:?}", route.associated_stops)
+            println!("{:?}", route.associated_stops);
+            for stop_pattern in route.stop_patterns() {
+                println!(
+                    "{} {}: {} trips, {} stops {:?}",
+                    stop_pattern.pattern_id,
+                    stop_pattern.label,
+                    stop_pattern.trip_count,
+                    stop_pattern.stop_ids.len(),
+                    stop_pattern.stop_ids
+                );
+            }
         }
     }
 }
@@ -78,7 +98,7 @@ pub fn extract_route_info(gtfs_file: &mut gtfs::GtfsFile, id: &String) -> Option
         route_description: route,
         trips,
         associated_stop_times,
-        associated_stops
+        associated_stops,
     };
 
     Some(route_info)
@@ -130,8 +150,8 @@ fn get_trips(gtfs_file: &mut gtfs::GtfsFile, route_id: &str) -> Vec<Trip> {
     let mut route_trips: Vec<Trip> = Vec::new();
 
     for trip in trips {
-        if trip.route_id == route_id.to_owned() {
-                route_trips.push(trip)
+        if trip.route_id == route_id {
+            route_trips.push(trip)
         }
     }
 
@@ -0,0 +1,397 @@
+use std::collections::HashSet;
+#[cfg(test)]
+use std::path::PathBuf;
+
+use geo::{Contains, Intersects};
+use rstar::primitives::{GeomWithData, Line};
+use rstar::{RTree, AABB};
+use serde::Serialize;
+
+use crate::functions::geodata::{self, LocalProjection};
+#[cfg(test)]
+use crate::functions::gtfs;
+use crate::functions::gtfs::{Shape, Stop};
+
+#[cfg(test)]
+use super::feed::Feed;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StopMatch {
+    #[serde(skip)]
+    pub index: usize,
+    pub stop_id: String,
+    pub stop_name: Option<String>,
+    // Great circle distance, zero for stops inside a polygon
+    pub distance_m: f64,
+}
+
+// The closest segment of a shape
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ShapeMatch {
+    pub shape_id: String,
+    pub segment: usize,
+    pub distance_m: f64,
+}
+
+// A shape segment, keyed by the shape's position in the index and the segment's position along it
+type Segment = GeomWithData<Line<[f64; 2]>, (usize, usize)>;
+
+// R-trees over the stops with coordinates and the segments of every shape, in metres of a
+// projection fitted to the feed
+pub struct SpatialIndex<'a> {
+    projection: LocalProjection,
+    stops: &'a [Stop],
+    stop_tree: RTree<GeomWithData<[f64; 2], usize>>,
+    shape_ids: Vec<String>,
+    shape_lines: Vec<Vec<geo_types::Point>>,
+    segment_tree: RTree<Segment>,
+}
+
+impl<'a> SpatialIndex<'a> {
+    pub fn new(stops: &'a [Stop], shapes: &[Shape]) -> SpatialIndex<'a> {
+        let mut lines: Vec<(String, Vec<geo_types::Point>)> =
+            geodata::shape_lines(shapes).into_iter().collect();
+        lines.sort_by(|a, b| a.0.cmp(&b.0));
+        let (shape_ids, shape_lines): (Vec<String>, Vec<Vec<geo_types::Point>>) =
+            lines.into_iter().unzip();
+
+        let located: Vec<(usize, geo_types::Point)> = stops
+            .iter()
+            .enumerate()
+            .filter_map(|(index, stop)| Some((index, geodata::stop_point(stop)?)))
+            .collect();
+        let all_points: Vec<geo_types::Point> = located
+            .iter()
+            .map(|(_, point)| *point)
+            .chain(shape_lines.iter().flatten().copied())
+            .collect();
+        let projection = LocalProjection::fit(&all_points);
+
+        let stop_tree = RTree::bulk_load(
+            located
+                .iter()
+                .map(|(index, point)| GeomWithData::new(projection.project(*point), *index))
+                .collect(),
+        );
+        let mut segments: Vec<Segment> = Vec::new();
+        for (shape, line) in shape_lines.iter().enumerate() {
+            for (segment, pair) in line.windows(2).enumerate() {
+                let line = Line::new(projection.project(pair[0]), projection.project(pair[1]));
+                segments.push(GeomWithData::new(line, (shape, segment)));
+            }
+        }
+
+        SpatialIndex {
+            projection,
+            stops,
+            stop_tree,
+            shape_ids,
+            shape_lines,
+            segment_tree: RTree::bulk_load(segments),
+        }
+    }
+
+    fn stop_match(&self, index: usize, distance_m: f64) -> StopMatch {
+        StopMatch {
+            index,
+            stop_id: self.stops[index].stop_id.to_owned(),
+            stop_name: self.stops[index].stop_name.clone(),
+            distance_m,
+        }
+    }
+
+    fn segment_distance(&self, (shape, segment): (usize, usize), point: geo_types::Point) -> f64 {
+        let line = &self.shape_lines[shape][segment..segment + 2];
+        geodata::project_onto_line(line, point, 0).map_or(f64::MAX, |(_, _, offset)| offset)
+    }
+
+    fn sorted<T>(mut matches: Vec<T>, distance: impl Fn(&T) -> f64) -> Vec<T> {
+        matches.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+        matches
+    }
+
+    pub fn nearest_stops(&self, point: geo_types::Point, count: usize) -> Vec<StopMatch> {
+        let matches = self
+            .stop_tree
+            .nearest_neighbor_iter(&self.projection.project(point))
+            .take(count)
+            .filter_map(|stop| {
+                let distance = geodata::haversine_distance(
+                    point,
+                    geodata::stop_point(&self.stops[stop.data])?,
+                );
+                Some(self.stop_match(stop.data, distance))
+            })
+            .collect();
+        Self::sorted(matches, |stop: &StopMatch| stop.distance_m)
+    }
+
+    pub fn stops_within_distance(
+        &self,
+        point: geo_types::Point,
+        distance_m: f64,
+    ) -> Vec<StopMatch> {
+        let radius = self.projection.radius(distance_m);
+        let matches = self
+            .stop_tree
+            .locate_within_distance(self.projection.project(point), radius * radius)
+            .filter_map(|stop| {
+                let distance = geodata::haversine_distance(
+                    point,
+                    geodata::stop_point(&self.stops[stop.data])?,
+                );
+                (distance <= distance_m).then(|| self.stop_match(stop.data, distance))
+            })
+            .collect();
+        Self::sorted(matches, |stop: &StopMatch| stop.distance_m)
+    }
+
+    pub fn stops_within_polygon(&self, polygon: &geo_types::MultiPolygon) -> Vec<StopMatch> {
+        let Some(envelope) = self.envelope(polygon) else {
+            return Vec::new();
+        };
+        let mut matches: Vec<StopMatch> = self
+            .stop_tree
+            .locate_in_envelope(&envelope)
+            .filter(|stop| {
+                geodata::stop_point(&self.stops[stop.data])
+                    .is_some_and(|point| polygon.contains(&point))
+            })
+            .map(|stop| self.stop_match(stop.data, 0.0))
+            .collect();
+        matches.sort_by_key(|stop| stop.index);
+        matches
+    }
+
+    // Pairs of stops closer than the distance, by their index in the stops, each pair once with
+    // the lower index first
+    pub fn stop_pairs_within(&self, distance_m: f64) -> Vec<(usize, usize, f64)> {
+        let radius = self.projection.radius(distance_m);
+        let mut pairs: Vec<(usize, usize, f64)> = Vec::new();
+        for stop in self.stop_tree.iter() {
+            let Some(point) = geodata::stop_point(&self.stops[stop.data]) else {
+                continue;
+            };
+            for other in self
+                .stop_tree
+                .locate_within_distance(*stop.geom(), radius * radius)
+            {
+                if other.data <= stop.data {
+                    continue;
+                }
+                let Some(other_point) = geodata::stop_point(&self.stops[other.data]) else {
+                    continue;
+                };
+                let distance = geodata::haversine_distance(point, other_point);
+                if distance <= distance_m {
+                    pairs.push((stop.data, other.data, distance));
+                }
+            }
+        }
+        pairs.sort_by_key(|(a, b, _)| (*a, *b));
+        pairs
+    }
+
+    // The closest shapes, each by its closest segment
+    pub fn nearest_shapes(&self, point: geo_types::Point, count: usize) -> Vec<ShapeMatch> {
+        let mut seen: HashSet<usize> = HashSet::new();
+        let mut matches: Vec<ShapeMatch> = Vec::new();
+        for segment in self
+            .segment_tree
+            .nearest_neighbor_iter(&self.projection.project(point))
+        {
+            if matches.len() == count {
+                break;
+            }
+            if seen.insert(segment.data.0) {
+                matches.push(ShapeMatch {
+                    shape_id: self.shape_ids[segment.data.0].to_owned(),
+                    segment: segment.data.1,
+                    distance_m: self.segment_distance(segment.data, point),
+                });
+            }
+        }
+        Self::sorted(matches, |shape: &ShapeMatch| shape.distance_m)
+    }
+
+    pub fn shapes_within_distance(
+        &self,
+        point: geo_types::Point,
+        distance_m: f64,
+    ) -> Vec<ShapeMatch> {
+        let radius = self.projection.radius(distance_m);
+        let mut matches: Vec<ShapeMatch> = Vec::new();
+        for segment in self
+            .segment_tree
+            .locate_within_distance(self.projection.project(point), radius * radius)
+        {
+            let distance = self.segment_distance(segment.data, point);
+            if distance > distance_m {
+                continue;
+            }
+            let shape_id = &self.shape_ids[segment.data.0];
+            match matches.iter_mut().find(|shape| &shape.shape_id == shape_id) {
+                Some(shape) if shape.distance_m <= distance => {}
+                Some(shape) => {
+                    shape.segment = segment.data.1;
+                    shape.distance_m = distance;
+                }
+                None => matches.push(ShapeMatch {
+                    shape_id: shape_id.to_owned(),
+                    segment: segment.data.1,
+                    distance_m: distance,
+                }),
+            }
+        }
+        Self::sorted(matches, |shape: &ShapeMatch| shape.distance_m)
+    }
+
+    // Shapes crossing or inside the polygon, by their first segment that does
+    pub fn shapes_within_polygon(&self, polygon: &geo_types::MultiPolygon) -> Vec<ShapeMatch> {
+        let Some(envelope) = self.envelope(polygon) else {
+            return Vec::new();
+        };
+        let mut matches: Vec<ShapeMatch> = Vec::new();
+        let mut candidates: Vec<(usize, usize)> = self
+            .segment_tree
+            .locate_in_envelope_intersecting(&envelope)
+            .map(|segment| segment.data)
+            .collect();
+        candidates.sort();
+        for (shape, segment) in candidates {
+            if matches
+                .last()
+                .is_some_and(|last| last.shape_id == self.shape_ids[shape])
+            {
+                continue;
+            }
+            let line = &self.shape_lines[shape];
+            if geo_types::Line::new(line[segment], line[segment + 1]).intersects(polygon) {
+                matches.push(ShapeMatch {
+                    shape_id: self.shape_ids[shape].to_owned(),
+                    segment,
+                    distance_m: 0.0,
+                });
+            }
+        }
+        matches
+    }
+
+    fn envelope(&self, polygon: &geo_types::MultiPolygon) -> Option<AABB<[f64; 2]>> {
+        let bounds = geo::BoundingRect::bounding_rect(polygon)?;
+        Some(AABB::from_corners(
+            self.projection.project(bounds.min().into()),
+            self.projection.project(bounds.max().into()),
+        ))
+    }
+}
+
+#[test]
+fn test_nearby_stops() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let feed = Feed::load(&mut gtfs_file);
+    let index = SpatialIndex::new(&feed.stops, &feed.shapes);
+
+    let north_avenue = geo_types::Point::new(-116.7648, 36.9149);
+    let nearest = index.nearest_stops(north_avenue, 3);
+    let ids: Vec<&str> = nearest.iter().map(|stop| stop.stop_id.as_str()).collect();
+    assert_eq!(ids, vec!["NANAA", "NADAV", "DADAN"]);
+    assert!(nearest[0].distance_m < nearest[1].distance_m);
+
+    let within = index.stops_within_distance(north_avenue, 400.0);
+    assert_eq!(within.len(), 2);
+    assert!(index.stops_within_distance(north_avenue, 100.0).is_empty());
+
+    let square = geo_types::MultiPolygon::new(vec![geo_types::Polygon::new(
+        geo_types::LineString::from(vec![
+            (-116.77, 36.90),
+            (-116.75, 36.90),
+            (-116.75, 36.92),
+            (-116.77, 36.92),
+        ]),
+        vec![],
+    )]);
+    let inside: Vec<&str> = index
+        .stops_within_polygon(&square)
+        .iter()
+        .map(|stop| feed.stops[stop.index].stop_id.as_str())
+        .collect();
+    assert_eq!(
+        inside,
+        vec!["STAGECOACH", "NADAV", "NANAA", "DADAN", "EMSI"]
+    );
+}
+
+#[test]
+fn test_stop_pairs_within() {
+    let stop = |stop_id: &str, lon: f64, lat: Option<f64>| Stop {
+        stop_id: stop_id.to_owned(),
+        stop_code: None,
+        stop_name: None,
+        tts_stop_name: None,
+        stop_desc: None,
+        stop_lat: lat,
+        stop_lon: Some(lon),
+        zone_id: None,
+        stop_url: None,
+        location_type: None,
+        parent_station: None,
+        stop_timezone: None,
+        wheelchair_boarding: None,
+        level_id: None,
+        platform_code: None,
+    };
+    let stops = vec![
+        stop("A", 8.0, Some(48.0)),
+        stop("B", 8.0, Some(48.003)),
+        stop("C", 8.0, None),
+        stop("D", 8.0, Some(48.01)),
+        stop("E", 8.006, Some(48.0)),
+    ];
+    let pairs = SpatialIndex::new(&stops, &[]).stop_pairs_within(500.0);
+    let indices: Vec<(usize, usize)> = pairs.iter().map(|(a, b, _)| (*a, *b)).collect();
+    assert_eq!(indices, vec![(0, 1), (0, 4)]);
+    assert!((pairs[0].2 - 333.6).abs() < 1.0);
+}
+
+#[test]
+fn test_nearby_shapes() {
+    let shape = |shape_id: &str, sequence: u64, lon: f64, lat: f64| Shape {
+        shape_id: shape_id.to_owned(),
+        shape_pt_lat: lat,
+        shape_pt_lon: lon,
+        shape_pt_sequence: sequence,
+        shape_dist_travelled: None,
+    };
+    // An east-west line and a north-south one crossing it further east
+    let shapes = vec![
+        shape("east", 1, 8.0, 48.0),
+        shape("east", 2, 8.01, 48.0),
+        shape("east", 3, 8.02, 48.0),
+        shape("north", 1, 8.03, 47.99),
+        shape("north", 2, 8.03, 48.01),
+    ];
+    let index = SpatialIndex::new(&[], &shapes);
+
+    let point = geo_types::Point::new(8.015, 48.001);
+    let nearest = index.nearest_shapes(point, 2);
+    assert_eq!(nearest[0].shape_id, "east");
+    assert_eq!(nearest[0].segment, 1);
+    assert!((nearest[0].distance_m - 111.2).abs() < 1.0);
+    assert_eq!(nearest[1].shape_id, "north");
+
+    assert_eq!(index.shapes_within_distance(point, 500.0).len(), 1);
+    let square = geo_types::MultiPolygon::new(vec![geo_types::Polygon::new(
+        geo_types::LineString::from(vec![
+            (8.025, 47.995),
+            (8.035, 47.995),
+            (8.035, 48.005),
+            (8.025, 48.005),
+        ]),
+        vec![],
+    )]);
+    let crossing = index.shapes_within_polygon(&square);
+    assert_eq!(crossing.len(), 1);
+    assert_eq!(crossing[0].shape_id, "north");
+}
@@ -1,16 +1,13 @@
 use crate::functions::gtfs::{self, Route, Stop, StopTime, Trip};
 use std::collections::BTreeMap;
 
-
 pub struct FullStop {
     stop_description: Stop,
     stop_times: Vec<StopTime>,
     associated_trips: Vec<Trip>,
-    associated_routes: Vec<Route>
+    associated_routes: Vec<Route>,
 }
 
-
-
 pub fn print_stop_details(stop_id: String, gtfs_file: &mut gtfs::GtfsFile) {
     let stop = extract_stop_info(gtfs_file, &stop_id);
     match stop {
@@ -21,21 +18,20 @@ pub fn print_stop_details(stop_id: String, gtfs_file: &mut gtfs::GtfsFile) {
             println!("{:?}", stop.associated_trips);
             println!("{:?}", stop.associated_routes);
         }
-    }   
+    }
 }
 
 fn extract_stop_info(gtfs_file: &mut gtfs::GtfsFile, id: &String) -> Option<FullStop> {
-    
     let stop = get_stop_description(gtfs_file, id)?;
     let stop_times = get_stop_times(gtfs_file, id);
     let associated_trips = get_matching_trips(gtfs_file, &stop_times);
-    let associated_routes = get_matching_routes(gtfs_file, &associated_trips);    
-    
+    let associated_routes = get_matching_routes(gtfs_file, &associated_trips);
+
     let stop_info = FullStop {
         stop_description: stop,
-        stop_times: stop_times,
-        associated_trips: associated_trips,
-        associated_routes: associated_routes,
+        stop_times,
+        associated_trips,
+        associated_routes,
     };
 
     Some(stop_info)
@@ -69,14 +65,13 @@ fn get_stop_description(gtfs_file: &mut gtfs::GtfsFile, id: &String) -> Option<S
 fn get_stop_times(gtfs_file: &mut gtfs::GtfsFile, stop_id: &String) -> Vec<StopTime> {
     let stop_times: Vec<gtfs::StopTime> = gtfs_file.read_vec();
     let mut local_stop_vec: Vec<StopTime> = Vec::new();
-    
+
     for stop_time in stop_times {
         if let Some(id) = stop_time.stop_id.clone() {
-            if id == stop_id.to_owned() {
+            if &id == stop_id {
                 local_stop_vec.push(stop_time)
             }
         }
-        
     }
 
     local_stop_vec
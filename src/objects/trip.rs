@@ -0,0 +1,200 @@
+use std::collections::{HashMap, HashSet};
+#[cfg(test)]
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+
+use crate::functions::calendar;
+use crate::functions::gtfs::{self, Frequency, StopTime, Trip};
+
+// A stop call of a trip instance, with times in seconds since the start of the service day
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduledStop {
+    pub stop_id: String,
+    pub stop_sequence: u64,
+    pub arrival: u64,
+    pub departure: u64,
+    pub shape_dist_travelled: Option<f64>,
+}
+
+// One run of a trip on a service day. Frequency based trips expand into several instances.
+#[derive(Debug, Clone)]
+pub struct TripInstance {
+    pub trip: Trip,
+    pub stops: Vec<ScheduledStop>,
+}
+
+impl TripInstance {
+    pub fn start_time(&self) -> u64 {
+        self.stops.first().map_or(0, |stop| stop.departure)
+    }
+
+    pub fn end_time(&self) -> u64 {
+        self.stops.last().map_or(0, |stop| stop.arrival)
+    }
+}
+
+// Groups stop times by trip and orders each trip's stops by stop_sequence
+pub fn stop_times_by_trip(stop_times: &[StopTime]) -> HashMap<String, Vec<StopTime>> {
+    let mut trips_map: HashMap<String, Vec<StopTime>> = HashMap::new();
+    for stop_time in stop_times {
+        match trips_map.get_mut(&stop_time.trip_id) {
+            Some(stop_time_vec) => stop_time_vec.push(stop_time.to_owned()),
+            None => {
+                trips_map.insert(stop_time.trip_id.to_owned(), vec![stop_time.to_owned()]);
+            }
+        }
+    }
+
+    for stop_time_vec in trips_map.values_mut() {
+        stop_time_vec.sort_by_key(|stop_time| stop_time.stop_sequence);
+    }
+
+    trips_map
+}
+
+pub fn read_trip_instances(gtfs_file: &mut gtfs::GtfsFile, date: NaiveDate) -> Vec<TripInstance> {
+    let services = calendar::read_active_services(gtfs_file, date);
+    let trips: Vec<Trip> = gtfs_file.read_vec();
+    let stop_times: Vec<StopTime> = gtfs_file.read_vec();
+    let frequencies: Vec<Frequency> = gtfs_file.read_optional_vec();
+
+    expand_trips(&trips, &stop_times, &frequencies, &services)
+}
+
+// All trip instances running on the given services, ordered by start time
+pub fn expand_trips(
+    trips: &[Trip],
+    stop_times: &[StopTime],
+    frequencies: &[Frequency],
+    services: &HashSet<String>,
+) -> Vec<TripInstance> {
+    let timetable = stop_times_by_trip(stop_times);
+    let mut trip_frequencies: HashMap<&str, Vec<&Frequency>> = HashMap::new();
+    for frequency in frequencies {
+        trip_frequencies
+            .entry(frequency.trip_id.as_str())
+            .or_default()
+            .push(frequency);
+    }
+
+    let mut instances: Vec<TripInstance> = Vec::new();
+    for trip in trips {
+        if !services.contains(&trip.service_id) {
+            continue;
+        }
+        let Some(trip_stop_times) = timetable.get(&trip.trip_id) else {
+            continue;
+        };
+        let Some(stops) = scheduled_stops(trip_stop_times) else {
+            continue;
+        };
+
+        match trip_frequencies.get(trip.trip_id.as_str()) {
+            None => instances.push(TripInstance {
+                trip: trip.clone(),
+                stops,
+            }),
+            Some(windows) => {
+                // The stop times of a frequency based trip only describe the travel times
+                // between stops, so every instance is shifted to its own start time
+                let first_departure = stops[0].departure;
+                for window in windows {
+                    if window.headway_secs == 0 {
+                        continue;
+                    }
+                    let mut start = window.start_time.seconds();
+                    while start < window.end_time.seconds() {
+                        let shifted = stops
+                            .iter()
+                            .map(|stop| ScheduledStop {
+                                arrival: (stop.arrival + start).saturating_sub(first_departure),
+                                departure: (stop.departure + start).saturating_sub(first_departure),
+                                ..stop.clone()
+                            })
+                            .collect();
+                        instances.push(TripInstance {
+                            trip: trip.clone(),
+                            stops: shifted,
+                        });
+                        start += window.headway_secs;
+                    }
+                }
+            }
+        }
+    }
+
+    instances.sort_by_key(|instance| instance.start_time());
+    instances
+}
+
+// Fills in missing times by interpolating between the neighbouring timed stops
+fn scheduled_stops(stop_times: &[StopTime]) -> Option<Vec<ScheduledStop>> {
+    let known: Vec<Option<(u64, u64)>> = stop_times
+        .iter()
+        .map(|stop_time| {
+            let arrival = stop_time
+                .arrival_time
+                .as_ref()
+                .or(stop_time.departure_time.as_ref())?;
+            let departure = stop_time.departure_time.as_ref().unwrap_or(arrival);
+            Some((arrival.seconds(), departure.seconds()))
+        })
+        .collect();
+
+    let mut stops: Vec<ScheduledStop> = Vec::new();
+    for (index, stop_time) in stop_times.iter().enumerate() {
+        let stop_id = stop_time.stop_id.clone()?;
+        let (arrival, departure) = match known[index] {
+            Some(times) => times,
+            None => {
+                let (previous_index, previous) = (0..index)
+                    .rev()
+                    .find_map(|i| known[i].map(|times| (i, times.1)))?;
+                let (next_index, next) =
+                    (index + 1..known.len()).find_map(|i| known[i].map(|times| (i, times.0)))?;
+                let fraction =
+                    (index - previous_index) as f64 / (next_index - previous_index) as f64;
+                let time =
+                    previous + (next.saturating_sub(previous) as f64 * fraction).round() as u64;
+                (time, time)
+            }
+        };
+        stops.push(ScheduledStop {
+            stop_id,
+            stop_sequence: stop_time.stop_sequence.unwrap_or(index as u64),
+            arrival,
+            departure,
+            shape_dist_travelled: stop_time.shape_dist_travelled,
+        });
+    }
+
+    if stops.is_empty() {
+        None
+    } else {
+        Some(stops)
+    }
+}
+
+#[test]
+fn test_expand_trips() {
+    let path: PathBuf = PathBuf::from("test_data/sample-feed-1-fixed.zip");
+    let mut gtfs_file = gtfs::GtfsFile::new(&path).unwrap();
+    let saturday = NaiveDate::from_ymd_opt(2007, 6, 2).unwrap();
+    let instances = read_trip_instances(&mut gtfs_file, saturday);
+
+    // STBA runs every 30 minutes from 6:00 to 22:00
+    let shuttle: Vec<&TripInstance> = instances
+        .iter()
+        .filter(|instance| instance.trip.trip_id == "STBA")
+        .collect();
+    assert_eq!(shuttle.len(), 32);
+    assert_eq!(shuttle[1].start_time(), 6 * 3600 + 30 * 60);
+    assert_eq!(shuttle[1].stops[1].arrival, 6 * 3600 + 50 * 60);
+
+    let amargosa = instances
+        .iter()
+        .filter(|instance| instance.trip.route_id == "AAMV")
+        .count();
+    assert_eq!(amargosa, 4);
+}